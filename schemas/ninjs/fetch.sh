@@ -0,0 +1,11 @@
+#!/bin/sh
+# Downloads the IPTC ninjs schemas the ninjs tests validate the documents
+# against. Commit the files as they are published, without changes.
+set -e
+
+cd "$(dirname "$0")"
+
+for version in 1.3 2.0; do
+    curl --fail --silent --show-error --location --output "ninjs-schema_$version.json" \
+        "https://www.iptc.org/std/ninjs/ninjs-schema_$version.json"
+done
@@ -0,0 +1,47 @@
+-- Triggers notifying the Content API about published, updated and
+-- unpublished articles, routes and authors.
+--
+-- The channel is the argument of each trigger and has to match one of the
+-- channels in PUBLISHER_CHANGES_CHANNELS (publisher_changes by default).
+
+CREATE OR REPLACE FUNCTION content_api_notify_change() RETURNS trigger AS $$
+DECLARE
+    payload json;
+BEGIN
+    IF TG_TABLE_NAME = 'swp_article' THEN
+        payload := json_build_object(
+            'table', TG_TABLE_NAME,
+            'operation', TG_OP,
+            'id', CASE WHEN TG_OP = 'DELETE' THEN OLD.id ELSE NEW.id END,
+            'status', CASE WHEN TG_OP = 'DELETE' THEN NULL ELSE NEW.status END,
+            'old_status', CASE WHEN TG_OP = 'INSERT' THEN NULL ELSE OLD.status END,
+            'route_id', CASE WHEN TG_OP = 'DELETE' THEN OLD.route_id ELSE NEW.route_id END
+        );
+    ELSE
+        payload := json_build_object(
+            'table', TG_TABLE_NAME,
+            'operation', TG_OP,
+            'id', CASE WHEN TG_OP = 'DELETE' THEN OLD.id ELSE NEW.id END
+        );
+    END IF;
+
+    PERFORM pg_notify(TG_ARGV[0], payload::text);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS content_api_article_changes ON swp_article;
+CREATE TRIGGER content_api_article_changes
+    AFTER INSERT OR UPDATE OR DELETE ON swp_article
+    FOR EACH ROW EXECUTE PROCEDURE content_api_notify_change('publisher_changes');
+
+DROP TRIGGER IF EXISTS content_api_route_changes ON swp_route;
+CREATE TRIGGER content_api_route_changes
+    AFTER INSERT OR UPDATE OR DELETE ON swp_route
+    FOR EACH ROW EXECUTE PROCEDURE content_api_notify_change('publisher_changes');
+
+DROP TRIGGER IF EXISTS content_api_author_changes ON swp_author;
+CREATE TRIGGER content_api_author_changes
+    AFTER INSERT OR UPDATE OR DELETE ON swp_author
+    FOR EACH ROW EXECUTE PROCEDURE content_api_notify_change('publisher_changes');
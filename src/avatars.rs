@@ -0,0 +1,153 @@
+//! Avatars of the authors: the image of `swp_author_media` on the avatar CDN
+//! or, for authors without one, an SVG of their initials generated by this
+//! service at `/avatars/authors/{id}.svg`.
+
+use rocket::http::{ContentType, Status};
+use sha2::{Digest, Sha256};
+use crate::graphql::generator::{generate_avatar_url, UrlBuilder};
+use crate::graphql::{Context, ImageVariant};
+use crate::models::{Author as AuthorModel, Image as ImageModel};
+use crate::response::CacheableResponse;
+use crate::xml::escape;
+
+const DEFAULT_SIZES: &str = "small=64,medium=128,large=256";
+const DEFAULT_SIZE: u32 = 256;
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 1024;
+const CACHE_AGE: u32 = 86400;
+
+/// Background colors of generated avatars, all readable with white text.
+const COLORS: &[&str] = &[
+    "#c62828", "#ad1457", "#6a1b9a", "#4527a0", "#283593", "#1565c0", "#00695c", "#2e7d32", "#558b2f", "#bf360c",
+    "#4e342e", "#37474f",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Avatar {
+    /// Image of `swp_author_media`.
+    Stored(ImageModel),
+    /// Initials of the author with this id.
+    Generated(i32),
+}
+
+impl Avatar {
+    /// Stored avatars are resized by the CDN with the `width` query parameter
+    /// (as understood by Fastly's Image Optimizer, imgix or Cloudflare).
+    pub fn url(&self, urls: &UrlBuilder, size: Option<u32>) -> String {
+        match (self, size) {
+            (Avatar::Stored(image), None) => generate_avatar_url(&image.asset_id, &image.file_extension),
+            (Avatar::Stored(image), Some(size)) => {
+                format!("{}?width={}", generate_avatar_url(&image.asset_id, &image.file_extension), size)
+            }
+            (Avatar::Generated(author_id), None) => urls.api_url(&format!("/avatars/authors/{}.svg", author_id)),
+            (Avatar::Generated(author_id), Some(size)) => {
+                urls.api_url(&format!("/avatars/authors/{}.svg?size={}", author_id, size))
+            }
+        }
+    }
+
+    /// Avatar in the sizes of `PUBLISHER_AVATAR_SIZES`. The height of resized
+    /// stored images is unknown, generated avatars are square.
+    pub fn renditions(&self, urls: &UrlBuilder) -> Vec<ImageVariant> {
+        sizes()
+            .into_iter()
+            .map(|(name, size)| ImageVariant {
+                url: self.url(urls, Some(size)),
+                name,
+                width: size as i32,
+                height: match self {
+                    Avatar::Stored(_) => None,
+                    Avatar::Generated(_) => Some(size as i32),
+                },
+            })
+            .collect()
+    }
+}
+
+/// `name=size` pairs separated by commas, e.g. `small=64,large=256`.
+fn sizes() -> Vec<(String, u32)> {
+    let sizes = std::env::var("PUBLISHER_AVATAR_SIZES").unwrap_or_else(|_| DEFAULT_SIZES.to_string());
+
+    sizes
+        .split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next().and_then(|size| size.trim().parse::<u32>().ok())) {
+                (Some(name), Some(size)) if !name.trim().is_empty() => {
+                    Some((name.trim().to_string(), size.max(MIN_SIZE).min(MAX_SIZE)))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// First letters of the first and last words of the name.
+pub fn initials(name: &str) -> String {
+    let letters = name
+        .split_whitespace()
+        .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+        .collect::<Vec<_>>();
+
+    let initials = match (letters.first(), letters.last()) {
+        (Some(first), Some(last)) if letters.len() > 1 => format!("{}{}", first, last),
+        (Some(first), _) => first.to_string(),
+        _ => "?".to_string(),
+    };
+
+    initials.to_uppercase()
+}
+
+/// Color picked from the hash of the name, so it doesn't change between
+/// requests or restarts.
+pub fn color(name: &str) -> &'static str {
+    let hash = Sha256::new().chain(name.trim().to_lowercase().as_bytes()).result();
+
+    COLORS[hash[0] as usize % COLORS.len()]
+}
+
+pub fn svg(name: &str, size: u32) -> String {
+    let initials = initials(name);
+    let font_size = if initials.chars().count() > 1 { 40 } else { 50 };
+
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 100 100" role="img" aria-label="{name}">"#,
+            r#"<rect width="100" height="100" fill="{color}"/>"#,
+            r##"<text x="50" y="50" dy="0.35em" text-anchor="middle" font-family="Helvetica, Arial, sans-serif" font-size="{font_size}" fill="#ffffff">{initials}</text>"##,
+            "</svg>"
+        ),
+        size = size,
+        name = escape(name),
+        color = color(name),
+        font_size = font_size,
+        initials = escape(&initials),
+    )
+}
+
+/// Generated avatar of the author, `file` is `{id}.svg`. The size defaults
+/// to 256 pixels.
+pub fn avatar_response(file: &str, size: Option<u32>, context: &Context) -> Result<CacheableResponse, Status> {
+    let author_id = match file.rfind('.') {
+        Some(dot) if &file[dot..] == ".svg" => file[..dot].parse::<i32>().map_err(|_| Status::NotFound)?,
+        _ => return Err(Status::NotFound),
+    };
+
+    let author = context
+        .loader
+        .load::<AuthorModel>(author_id, &context.db_con)
+        .map_err(|error| {
+            eprintln!("avatars: {}", error);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+    context.record_key(format!("author-{}", author.id));
+
+    let body = svg(&author.name, size.unwrap_or(DEFAULT_SIZE).max(MIN_SIZE).min(MAX_SIZE));
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(ContentType::SVG)
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", CACHE_AGE))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
@@ -0,0 +1,91 @@
+pub mod listener;
+pub mod webhook;
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Article,
+    Route,
+    Author,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Published,
+    Updated,
+    Unpublished,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Published => "published",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Unpublished => "unpublished",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    Database,
+    Webhook,
+}
+
+/// Something was published, updated or unpublished in the Publisher.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub entity: Entity,
+    pub id: i32,
+    pub kind: ChangeKind,
+    pub route_id: Option<i32>,
+    pub source: ChangeSource,
+}
+
+impl ChangeEvent {
+    /// Surrogate keys of the responses affected by the change.
+    pub fn surrogate_keys(&self) -> Vec<String> {
+        let mut keys = match self.entity {
+            Entity::Article => vec![format!("article-{}", self.id), "articles".to_string()],
+            Entity::Route => vec![format!("route-{}", self.id), format!("route-{}-articles", self.id)],
+            Entity::Author => vec![format!("author-{}", self.id)],
+        };
+
+        if let (Entity::Article, Some(route_id)) = (self.entity, self.route_id) {
+            keys.push(format!("route-{}-articles", route_id));
+        }
+
+        keys
+    }
+}
+
+/// In-process publish/subscribe bus for change events. Every subscriber gets
+/// its own receiver, subscribers which went away are dropped on publish.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<ChangeEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().expect("event bus lock poisoned").push(sender);
+
+        receiver
+    }
+
+    pub fn publish(&self, event: ChangeEvent) {
+        let mut subscribers = self.subscribers.lock().expect("event bus lock poisoned");
+
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+use std::thread;
+use std::time::Duration;
+use fallible_iterator::FallibleIterator;
+use postgres::{Client, NoTls};
+use super::{ChangeEvent, ChangeKind, ChangeSource, Entity, EventBus};
+
+const DEFAULT_CHANNEL: &str = "publisher_changes";
+const MAX_RECONNECT_DELAY: u64 = 60;
+
+/// Payload sent by the triggers from `sql/change_notifications.sql`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct NotificationPayload {
+    pub table: String,
+    pub operation: String,
+    pub id: i32,
+    pub status: Option<String>,
+    pub old_status: Option<String>,
+    pub route_id: Option<i32>,
+}
+
+impl NotificationPayload {
+    pub fn into_event(self) -> Option<ChangeEvent> {
+        let entity = match self.table.as_str() {
+            "swp_article" => Entity::Article,
+            "swp_route" => Entity::Route,
+            "swp_author" => Entity::Author,
+            _ => return None,
+        };
+
+        let kind = match entity {
+            Entity::Article => {
+                let is_published = self.status.as_ref().map_or(false, |status| status == "published");
+                let was_published = self.old_status.as_ref().map_or(false, |status| status == "published");
+
+                match (self.operation.as_str(), was_published, is_published) {
+                    ("DELETE", true, _) => ChangeKind::Unpublished,
+                    ("DELETE", false, _) => return None,
+                    (_, false, true) => ChangeKind::Published,
+                    (_, true, true) => ChangeKind::Updated,
+                    (_, true, false) => ChangeKind::Unpublished,
+                    (_, false, false) => return None,
+                }
+            }
+            Entity::Route | Entity::Author => match self.operation.as_str() {
+                "INSERT" => ChangeKind::Published,
+                "DELETE" => ChangeKind::Unpublished,
+                _ => ChangeKind::Updated,
+            },
+        };
+
+        Some(ChangeEvent {
+            entity,
+            id: self.id,
+            kind,
+            route_id: self.route_id,
+            source: ChangeSource::Database,
+        })
+    }
+}
+
+/// Starts a background thread which `LISTEN`s on the configured channels and
+/// publishes received notifications on the event bus. The connection is
+/// re-established with a growing delay when it drops.
+pub fn spawn_from_env(bus: EventBus) {
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let channels = std::env::var("PUBLISHER_CHANGES_CHANNELS")
+        .unwrap_or_else(|_| DEFAULT_CHANNEL.to_string())
+        .split(',')
+        .map(|channel| channel.trim().to_string())
+        .filter(|channel| !channel.is_empty())
+        .collect::<Vec<_>>();
+
+    if channels.is_empty() {
+        return;
+    }
+
+    thread::Builder::new()
+        .name("changes-listener".to_string())
+        .spawn(move || {
+            let mut delay = 1;
+
+            loop {
+                match listen(&database_url, &channels, &bus) {
+                    Ok(()) => delay = 1,
+                    Err(error) => {
+                        eprintln!("changes listener: {}, reconnecting in {}s", error, delay);
+                        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                    }
+                }
+
+                thread::sleep(Duration::from_secs(delay));
+            }
+        })
+        .expect("failed to spawn changes listener thread");
+}
+
+fn listen(database_url: &str, channels: &[String], bus: &EventBus) -> Result<(), postgres::Error> {
+    let mut client = Client::connect(database_url, NoTls)?;
+
+    for channel in channels {
+        client.batch_execute(&format!("LISTEN \"{}\"", channel.replace('"', "\"\"")))?;
+    }
+
+    let mut notifications = client.notifications();
+    let mut iter = notifications.blocking_iter();
+
+    while let Some(notification) = iter.next()? {
+        match serde_json::from_str::<NotificationPayload>(notification.payload()) {
+            Ok(payload) => {
+                if let Some(event) = payload.into_event() {
+                    bus.publish(event);
+                }
+            }
+            Err(error) => eprintln!(
+                "changes listener: invalid payload on channel {}: {}",
+                notification.channel(),
+                error
+            ),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::io::Read;
+use rocket::{
+    data::{self, FromDataSimple},
+    http::Status,
+    request::{self, FromRequest, Request},
+    Data, Outcome,
+};
+use super::{ChangeEvent, ChangeKind, ChangeSource, Entity};
+
+const BODY_LIMIT: u64 = 1024 * 1024 * 5;
+const EVENT_HEADER: &str = "X-Webhook-Event";
+const TOKEN_HEADER: &str = "X-Webhook-Token";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookRoute {
+    pub id: i32,
+}
+
+/// The part of the resource serialized by Publisher's outgoing webhooks we
+/// need to build a change event.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookPayload {
+    pub id: i32,
+    pub status: Option<String>,
+    pub route: Option<WebhookRoute>,
+}
+
+/// Body and event name (e.g. `article[published]`) of an inbound webhook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub event: Option<String>,
+    pub payload: WebhookPayload,
+}
+
+impl Webhook {
+    pub fn into_event(self) -> Option<ChangeEvent> {
+        let route_id = self.payload.route.as_ref().map(|route| route.id);
+        let is_published = self.payload.status.as_ref().map_or(false, |status| status == "published");
+
+        let (entity, kind) = match self.event.as_ref().map(String::as_str) {
+            Some("article[published]") => (Entity::Article, ChangeKind::Published),
+            Some("article[updated]") if is_published => (Entity::Article, ChangeKind::Updated),
+            Some("article[unpublished]") | Some("article[canceled]") => (Entity::Article, ChangeKind::Unpublished),
+            Some("route[created]") => (Entity::Route, ChangeKind::Published),
+            Some("route[updated]") => (Entity::Route, ChangeKind::Updated),
+            Some("route[deleted]") => (Entity::Route, ChangeKind::Unpublished),
+            Some("author[created]") => (Entity::Author, ChangeKind::Published),
+            Some("author[updated]") => (Entity::Author, ChangeKind::Updated),
+            Some("author[deleted]") => (Entity::Author, ChangeKind::Unpublished),
+            // without the event name only articles can be recognized by their status
+            None if route_id.is_some() && is_published => (Entity::Article, ChangeKind::Updated),
+            None if route_id.is_some() => (Entity::Article, ChangeKind::Unpublished),
+            _ => return None,
+        };
+
+        Some(ChangeEvent {
+            entity,
+            id: self.payload.id,
+            kind,
+            route_id: if entity == Entity::Article { route_id } else { None },
+            source: ChangeSource::Webhook,
+        })
+    }
+}
+
+impl FromDataSimple for Webhook {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        let mut body = String::new();
+        if let Err(error) = data.open().take(BODY_LIMIT).read_to_string(&mut body) {
+            return Outcome::Failure((Status::InternalServerError, format!("{:?}", error)));
+        }
+
+        let payload = match serde_json::from_str(&body) {
+            Ok(payload) => payload,
+            Err(error) => return Outcome::Failure((Status::BadRequest, format!("{}", error))),
+        };
+
+        Outcome::Success(Webhook {
+            event: request.headers().get_one(EVENT_HEADER).map(|event| event.to_lowercase()),
+            payload,
+        })
+    }
+}
+
+/// Guard checking the shared secret configured in `PUBLISHER_WEBHOOK_SECRET`,
+/// sent either in the `X-Webhook-Token` header or in the `token` query
+/// parameter (Publisher's webhooks can only be configured with a URL).
+pub struct WebhookAuthentication;
+
+impl<'a, 'r> FromRequest<'a, 'r> for WebhookAuthentication {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<WebhookAuthentication, ()> {
+        let secret = match std::env::var("PUBLISHER_WEBHOOK_SECRET") {
+            Ok(ref secret) if !secret.is_empty() => secret.to_owned(),
+            _ => return Outcome::Failure((Status::NotFound, ())),
+        };
+
+        let token = request
+            .headers()
+            .get_one(TOKEN_HEADER)
+            .map(str::to_owned)
+            .or_else(|| request.get_query_value::<String>("token").and_then(Result::ok));
+
+        match token {
+            Some(ref token) if constant_time_eq(token.as_bytes(), secret.as_bytes()) => {
+                Outcome::Success(WebhookAuthentication)
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
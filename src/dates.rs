@@ -0,0 +1,74 @@
+//! Formatting of the article dates, which are stored in UTC without a time
+//! zone.
+
+use chrono::prelude::*;
+
+const RFC_822_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+const GERMAN_MONTHS: [&str; 12] = [
+    "Januar", "Februar", "März", "April", "Mai", "Juni", "Juli", "August", "September", "Oktober", "November",
+    "Dezember",
+];
+const FRENCH_MONTHS: [&str; 12] = [
+    "janvier", "février", "mars", "avril", "mai", "juin", "juillet", "août", "septembre", "octobre", "novembre",
+    "décembre",
+];
+const SPANISH_MONTHS: [&str; 12] = [
+    "enero", "febrero", "marzo", "abril", "mayo", "junio", "julio", "agosto", "septiembre", "octubre", "noviembre",
+    "diciembre",
+];
+
+/// RFC 3339 date in UTC with second precision, e.g. `2019-05-01T12:00:00Z`.
+pub fn format_date(date: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(date, Utc).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// RFC 822 date of RSS feeds, e.g. `Wed, 01 May 2019 12:00:00 GMT`.
+pub fn format_rfc822_date(date: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(date, Utc).format(RFC_822_FORMAT).to_string()
+}
+
+/// Date written out in the language of the locale (`de`, `fr-CH`, `es_MX`,
+/// ...), the languages of the bylines: "1. Mai 2019", "1er mai 2019",
+/// "1 de mayo de 2019", and "May 1, 2019" in English and the other languages.
+pub fn format_long_date(date: NaiveDateTime, locale: &str) -> String {
+    let language = locale.split(|c| c == '-' || c == '_').next().unwrap_or("").to_lowercase();
+    let month = date.month0() as usize;
+    let day = date.day();
+    let year = date.year();
+
+    match language.as_str() {
+        "de" => format!("{}. {} {}", day, GERMAN_MONTHS[month], year),
+        "fr" if day == 1 => format!("1er {} {}", FRENCH_MONTHS[month], year),
+        "fr" => format!("{} {} {}", day, FRENCH_MONTHS[month], year),
+        "es" => format!("{} de {} de {}", day, SPANISH_MONTHS[month], year),
+        _ => date.format("%B %-d, %Y").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_dates_out_in_the_language_of_the_locale() {
+        let date = NaiveDate::from_ymd(2019, 5, 1).and_hms(12, 0, 0);
+        let later = NaiveDate::from_ymd(2019, 3, 21).and_hms(12, 0, 0);
+
+        assert_eq!(format_long_date(date, "en"), "May 1, 2019");
+        assert_eq!(format_long_date(date, "de-AT"), "1. Mai 2019");
+        assert_eq!(format_long_date(later, "de"), "21. März 2019");
+        assert_eq!(format_long_date(date, "fr_CH"), "1er mai 2019");
+        assert_eq!(format_long_date(later, "fr"), "21 mars 2019");
+        assert_eq!(format_long_date(date, "es"), "1 de mayo de 2019");
+        assert_eq!(format_long_date(date, "nl"), "May 1, 2019");
+    }
+
+    #[test]
+    fn formats_dates_in_utc() {
+        let date = NaiveDate::from_ymd(2019, 5, 1).and_hms(12, 0, 0);
+
+        assert_eq!(format_date(date), "2019-05-01T12:00:00Z");
+        assert_eq!(format_rfc822_date(date), "Wed, 01 May 2019 12:00:00 GMT");
+    }
+}
@@ -11,3 +11,131 @@ pub fn db_pool() -> DbConnPool {
         .build(ConnectionManager::<PgConnection>::new(database_url))
         .expect("failed to create db connection pool")
 }
+
+/// Proxy counting the queries the connections send to Postgres, for the
+/// tests of the number of queries of a request.
+#[cfg(test)]
+pub mod query_counter {
+    use std::io::{self, Read, Write};
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use super::*;
+
+    const SSL_REQUEST: u32 = 80_877_103;
+    const GSSENC_REQUEST: u32 = 80_877_104;
+
+    pub struct QueryCounter {
+        database_url: String,
+        queries: Arc<AtomicUsize>,
+    }
+
+    impl QueryCounter {
+        /// Forwards the connections to the database of `DATABASE_URL`, which
+        /// has to be reached over TCP.
+        pub fn start() -> Self {
+            let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+            let mut url = url::Url::parse(&database_url).expect("invalid DATABASE_URL");
+            let upstream = format!(
+                "{}:{}",
+                url.host_str().filter(|host| !host.is_empty()).expect("DATABASE_URL must have a host"),
+                url.port().unwrap_or(5432)
+            );
+
+            let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind the query counter");
+            url.set_host(Some("127.0.0.1")).expect("DATABASE_URL can't have a host");
+            url.set_port(Some(listener.local_addr().unwrap().port())).expect("DATABASE_URL can't have a port");
+            // the messages have to be readable
+            url.query_pairs_mut().append_pair("sslmode", "disable");
+
+            let queries = Arc::new(AtomicUsize::new(0));
+            let counted = queries.clone();
+            thread::spawn(move || {
+                for client in listener.incoming().filter_map(Result::ok) {
+                    let server = TcpStream::connect(&upstream).expect("failed to connect to the database");
+                    let queries = counted.clone();
+                    thread::spawn(move || forward(client, server, &queries));
+                }
+            });
+
+            QueryCounter {
+                database_url: url.to_string(),
+                queries,
+            }
+        }
+
+        /// A pool of one connection, so that a test sees its temporary tables.
+        pub fn pool(&self) -> DbConnPool {
+            r2d2::Pool::builder()
+                .max_size(1)
+                .build(ConnectionManager::<PgConnection>::new(self.database_url.as_str()))
+                .expect("failed to create db connection pool")
+        }
+
+        /// Queries sent since the last reset.
+        pub fn queries(&self) -> usize {
+            self.queries.load(Ordering::SeqCst)
+        }
+
+        pub fn reset(&self) {
+            self.queries.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Copies the server's messages as they are and counts the client's
+    /// simple queries (`Q`) and executions of prepared statements (`E`).
+    fn forward(mut client: TcpStream, mut server: TcpStream, queries: &AtomicUsize) {
+        let (mut client_writer, mut server_reader) = (client.try_clone().unwrap(), server.try_clone().unwrap());
+        thread::spawn(move || {
+            let _ = io::copy(&mut server_reader, &mut client_writer);
+            let _ = client_writer.shutdown(Shutdown::Both);
+        });
+
+        if let Err(error) = count_queries(&mut client, &mut server, queries) {
+            if error.kind() != io::ErrorKind::UnexpectedEof {
+                eprintln!("query counter: {}", error);
+            }
+        }
+        let _ = server.shutdown(Shutdown::Both);
+    }
+
+    fn count_queries(client: &mut TcpStream, server: &mut TcpStream, queries: &AtomicUsize) -> io::Result<()> {
+        // untyped startup messages, encryption requests are followed by another one
+        loop {
+            let message = read_message(client, 0)?;
+            server.write_all(&message)?;
+
+            let code = u32::from_be_bytes([message[4], message[5], message[6], message[7]]);
+            if code != SSL_REQUEST && code != GSSENC_REQUEST {
+                break;
+            }
+        }
+
+        loop {
+            let message = read_message(client, 1)?;
+            if message[0] == b'Q' || message[0] == b'E' {
+                queries.fetch_add(1, Ordering::SeqCst);
+            }
+            server.write_all(&message)?;
+        }
+    }
+
+    /// A message with a `tag_length` bytes tag, then its length (including
+    /// itself) as a 4 bytes integer.
+    fn read_message(stream: &mut TcpStream, tag_length: usize) -> io::Result<Vec<u8>> {
+        let mut message = vec![0; tag_length + 4];
+        stream.read_exact(&mut message)?;
+
+        let length = u32::from_be_bytes([
+            message[tag_length],
+            message[tag_length + 1],
+            message[tag_length + 2],
+            message[tag_length + 3],
+        ]) as usize;
+        message.resize(tag_length + length, 0);
+        stream.read_exact(&mut message[tag_length + 4..])?;
+
+        Ok(message)
+    }
+}
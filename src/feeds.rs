@@ -0,0 +1,312 @@
+pub mod atom;
+pub mod json_feed;
+pub mod rss;
+
+use chrono::prelude::*;
+use diesel::prelude::*;
+use juniper::http::GraphQLRequest;
+use rocket::http::{ContentType, Status};
+use crate::graphql::{Context, Schema};
+use crate::response::CacheableResponse;
+
+const DEFAULT_ITEMS: i32 = 20;
+const MAX_ITEMS: i32 = 100;
+const DEFAULT_MAX_AGE: u32 = 300;
+
+/// Articles are loaded through the `articles` query, so feeds share the
+/// filtering and eager loading with the GraphQL API.
+const FEED_QUERY: &str = r#"
+query Feed($first: Int!, $route: Int, $author: Int, $keyword: Int) {
+  articles(first: $first, route: $route, author: $author, keyword: $keyword, status: "published") {
+    edges {
+      node {
+        id
+        url
+        title
+        lead
+        body
+        publishedAt
+        updatedAt
+        authors { name }
+        keywords { name }
+        featureMedia { mimetype description image { url } }
+      }
+    }
+  }
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            FeedFormat::Rss => ContentType::with_params("application", "rss+xml", ("charset", "utf-8")),
+            FeedFormat::Atom => ContentType::with_params("application", "atom+xml", ("charset", "utf-8")),
+            FeedFormat::Json => ContentType::with_params("application", "feed+json", ("charset", "utf-8")),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+            FeedFormat::Json => "json",
+        }
+    }
+}
+
+/// Splits a feed file name, e.g. `sport.rss`, into the slug and the format.
+pub fn parse_file_name(file_name: &str) -> Option<(&str, FeedFormat)> {
+    let index = file_name.rfind('.')?;
+    let format = match &file_name[index + 1..] {
+        "rss" | "xml" => FeedFormat::Rss,
+        "atom" => FeedFormat::Atom,
+        "json" => FeedFormat::Json,
+        _ => return None,
+    };
+
+    Some((&file_name[..index], format))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedSource {
+    Route,
+    Author,
+    Keyword,
+}
+
+impl FeedSource {
+    fn path(&self) -> &'static str {
+        match self {
+            FeedSource::Route => "/feeds",
+            FeedSource::Author => "/feeds/author",
+            FeedSource::Keyword => "/feeds/keyword",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub title: String,
+    pub link: String,
+    pub feed_url: String,
+    pub updated: Option<NaiveDateTime>,
+    pub items: Vec<FeedItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub summary: String,
+    pub content_html: String,
+    pub published_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+    pub enclosure: Option<Enclosure>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: String,
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+struct FeedData {
+    articles: Option<FeedArticles>,
+}
+
+#[derive(Deserialize)]
+struct FeedArticles {
+    edges: Vec<FeedEdge>,
+}
+
+#[derive(Deserialize)]
+struct FeedEdge {
+    node: FeedArticle,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FeedArticle {
+    id: String,
+    url: String,
+    title: String,
+    lead: String,
+    body: String,
+    published_at: Option<f64>,
+    updated_at: Option<f64>,
+    authors: Vec<Named>,
+    keywords: Vec<Named>,
+    feature_media: Option<FeedMedia>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct FeedMedia {
+    mimetype: String,
+    description: String,
+    image: FeedImage,
+}
+
+#[derive(Deserialize)]
+struct FeedImage {
+    url: String,
+}
+
+impl From<FeedArticle> for FeedItem {
+    fn from(article: FeedArticle) -> Self {
+        FeedItem {
+            url: article.url,
+            id: article.id,
+            title: article.title,
+            summary: article.lead,
+            content_html: article.body,
+            published_at: article.published_at.map(from_timestamp),
+            updated_at: article.updated_at.map(from_timestamp),
+            authors: article.authors.into_iter().map(|author| author.name).collect(),
+            categories: article.keywords.into_iter().map(|keyword| keyword.name).collect(),
+            enclosure: article.feature_media.map(|media| Enclosure {
+                url: media.image.url,
+                mime_type: media.mimetype,
+                title: media.description,
+            }),
+        }
+    }
+}
+
+fn from_timestamp(timestamp: f64) -> NaiveDateTime {
+    NaiveDateTime::from_timestamp(timestamp as i64, 0)
+}
+
+/// Number of items in a feed, `PUBLISHER_FEEDS_ITEMS` unless requested
+/// otherwise.
+pub fn item_count(requested: Option<i32>) -> i32 {
+    requested
+        .or_else(|| std::env::var("PUBLISHER_FEEDS_ITEMS").ok().and_then(|value| value.parse().ok()))
+        .unwrap_or(DEFAULT_ITEMS)
+        .max(1)
+        .min(MAX_ITEMS)
+}
+
+/// Builds the feed of the route, author or keyword with the given slug.
+/// Returns `None` when there is no such entity.
+pub fn load_feed(
+    source: FeedSource,
+    slug: &str,
+    format: FeedFormat,
+    items: i32,
+    schema: &Schema,
+    context: &Context,
+) -> Result<Option<Feed>, String> {
+    use crate::schema::{swp_author, swp_keyword, swp_route};
+
+    let conn = &context.db_con;
+    let found = match source {
+        FeedSource::Route => swp_route::table
+            .filter(swp_route::slug.eq(slug))
+            .select((swp_route::id, swp_route::name))
+            .first::<(i32, String)>(conn),
+        FeedSource::Author => swp_author::table
+            .filter(swp_author::slug.eq(slug))
+            .select((swp_author::id, swp_author::name))
+            .first::<(i32, String)>(conn),
+        FeedSource::Keyword => swp_keyword::table
+            .filter(swp_keyword::slug.eq(slug))
+            .select((swp_keyword::id, swp_keyword::name))
+            .first::<(i32, String)>(conn),
+    }
+    .optional()
+    .map_err(|error| error.to_string())?;
+
+    let (id, name) = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let variables = match source {
+        FeedSource::Route => serde_json::json!({ "first": items, "route": id }),
+        FeedSource::Author => serde_json::json!({ "first": items, "author": id }),
+        FeedSource::Keyword => serde_json::json!({ "first": items, "keyword": id }),
+    };
+    let variables = serde_json::from_value(variables).map_err(|error| error.to_string())?;
+
+    let request = GraphQLRequest::new(FEED_QUERY.to_string(), None, Some(variables));
+    let response = serde_json::to_value(&request.execute(schema, context)).map_err(|error| error.to_string())?;
+
+    if let Some(errors) = response.get("errors") {
+        return Err(errors.to_string());
+    }
+
+    let data: FeedData = serde_json::from_value(response["data"].clone()).map_err(|error| error.to_string())?;
+    let items = data
+        .articles
+        .map(|articles| articles.edges.into_iter().map(|edge| FeedItem::from(edge.node)).collect())
+        .unwrap_or_else(Vec::new);
+
+    let link = match source {
+        FeedSource::Route => context.urls.route_url(slug),
+        FeedSource::Author => context.urls.author_url(slug),
+        FeedSource::Keyword => context.urls.site_url("/"),
+    };
+
+    Ok(Some(Feed {
+        title: name,
+        link,
+        feed_url: context.urls.site_url(&format!("{}/{}.{}", source.path(), slug, format.extension())),
+        updated: context.response_metadata().last_modified,
+        items,
+    }))
+}
+
+pub fn render(feed: &Feed, format: FeedFormat) -> String {
+    match format {
+        FeedFormat::Rss => rss::render(feed),
+        FeedFormat::Atom => atom::render(feed),
+        FeedFormat::Json => json_feed::render(feed),
+    }
+}
+
+pub fn feed_response(
+    source: FeedSource,
+    file_name: &str,
+    items: Option<i32>,
+    schema: &Schema,
+    context: &Context,
+) -> Result<CacheableResponse, Status> {
+    let (slug, format) = parse_file_name(file_name).ok_or(Status::NotFound)?;
+    let feed = load_feed(source, slug, format, item_count(items), schema, context)
+        .map_err(|error| {
+            eprintln!("feeds: {}", error);
+            Status::InternalServerError
+        })?
+        .ok_or(Status::NotFound)?;
+
+    let max_age = std::env::var("PUBLISHER_FEEDS_MAX_AGE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_AGE);
+    let body = render(&feed, format);
+    let metadata = context.response_metadata();
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(format.content_type())
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", max_age))
+        .last_modified(metadata.last_modified)
+        .surrogate_keys(metadata.surrogate_keys))
+}
@@ -0,0 +1,54 @@
+use chrono::prelude::*;
+use crate::dates::format_date;
+use crate::xml::escape;
+use super::Feed;
+
+/// Renders an Atom 1.0 document, the feature media is linked as an enclosure.
+pub fn render(feed: &Feed) -> String {
+    let mut xml = String::new();
+    let updated = feed.updated.unwrap_or_else(|| Utc::now().naive_utc());
+
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    xml.push_str(&format!("<id>{}</id>", escape(&feed.feed_url)));
+    xml.push_str(&format!("<title>{}</title>", escape(&feed.title)));
+    xml.push_str(&format!("<updated>{}</updated>", format_date(updated)));
+    xml.push_str(&format!(r#"<link rel="alternate" type="text/html" href="{}"/>"#, escape(&feed.link)));
+    xml.push_str(&format!(r#"<link rel="self" href="{}"/>"#, escape(&feed.feed_url)));
+
+    for item in &feed.items {
+        let published_at = item.published_at.unwrap_or(updated);
+
+        xml.push_str("<entry>");
+        xml.push_str(&format!("<id>{}</id>", escape(&item.url)));
+        xml.push_str(&format!("<title>{}</title>", escape(&item.title)));
+        xml.push_str(&format!(r#"<link rel="alternate" type="text/html" href="{}"/>"#, escape(&item.url)));
+        xml.push_str(&format!("<published>{}</published>", format_date(published_at)));
+        xml.push_str(&format!("<updated>{}</updated>", format_date(item.updated_at.unwrap_or(published_at))));
+        xml.push_str(&format!("<summary>{}</summary>", escape(&item.summary)));
+        xml.push_str(&format!(r#"<content type="html">{}</content>"#, escape(&item.content_html)));
+
+        for author in &item.authors {
+            xml.push_str(&format!("<author><name>{}</name></author>", escape(author)));
+        }
+
+        for category in &item.categories {
+            xml.push_str(&format!(r#"<category term="{}"/>"#, escape(category)));
+        }
+
+        if let Some(enclosure) = &item.enclosure {
+            xml.push_str(&format!(
+                r#"<link rel="enclosure" type="{}" title="{}" href="{}"/>"#,
+                escape(&enclosure.mime_type),
+                escape(&enclosure.title),
+                escape(&enclosure.url)
+            ));
+        }
+
+        xml.push_str("</entry>");
+    }
+
+    xml.push_str("</feed>");
+
+    xml
+}
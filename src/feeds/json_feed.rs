@@ -0,0 +1,49 @@
+use crate::dates::format_date;
+use super::Feed;
+
+/// Renders a [JSON Feed 1.1](https://jsonfeed.org/version/1.1) document.
+pub fn render(feed: &Feed) -> String {
+    let items = feed
+        .items
+        .iter()
+        .map(|item| {
+            let mut json = serde_json::json!({
+                "id": item.url,
+                "url": item.url,
+                "title": item.title,
+                "summary": item.summary,
+                "content_html": item.content_html,
+                "authors": item.authors.iter().map(|name| serde_json::json!({ "name": name })).collect::<Vec<_>>(),
+                "tags": item.categories,
+            });
+
+            if let Some(published_at) = item.published_at {
+                json["date_published"] = format_date(published_at).into();
+            }
+
+            if let Some(updated_at) = item.updated_at {
+                json["date_modified"] = format_date(updated_at).into();
+            }
+
+            if let Some(enclosure) = &item.enclosure {
+                json["image"] = enclosure.url.clone().into();
+                json["attachments"] = serde_json::json!([{
+                    "url": enclosure.url,
+                    "mime_type": enclosure.mime_type,
+                    "title": enclosure.title,
+                }]);
+            }
+
+            json
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "home_page_url": feed.link,
+        "feed_url": feed.feed_url,
+        "items": items,
+    })
+    .to_string()
+}
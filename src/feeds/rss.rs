@@ -0,0 +1,59 @@
+use crate::dates::format_rfc822_date;
+use crate::xml::escape;
+use super::Feed;
+
+/// Renders an RSS 2.0 document, authors are listed as `dc:creator`.
+pub fn render(feed: &Feed) -> String {
+    let mut xml = String::new();
+
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:atom="http://www.w3.org/2005/Atom">"#);
+    xml.push_str("<channel>");
+    xml.push_str(&format!("<title>{}</title>", escape(&feed.title)));
+    xml.push_str(&format!("<link>{}</link>", escape(&feed.link)));
+    xml.push_str(&format!("<description>{}</description>", escape(&feed.title)));
+    xml.push_str(&format!(
+        r#"<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+        escape(&feed.feed_url)
+    ));
+
+    if let Some(updated) = feed.updated {
+        xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>", format_rfc822_date(updated)));
+    }
+
+    for item in &feed.items {
+        xml.push_str("<item>");
+        xml.push_str(&format!("<title>{}</title>", escape(&item.title)));
+        xml.push_str(&format!("<link>{}</link>", escape(&item.url)));
+        xml.push_str(&format!(r#"<guid isPermaLink="true">{}</guid>"#, escape(&item.url)));
+        xml.push_str(&format!("<description>{}</description>", escape(&item.summary)));
+        xml.push_str(&format!("<content:encoded>{}</content:encoded>", escape(&item.content_html)));
+
+        if let Some(published_at) = item.published_at {
+            xml.push_str(&format!("<pubDate>{}</pubDate>", format_rfc822_date(published_at)));
+        }
+
+        for author in &item.authors {
+            xml.push_str(&format!("<dc:creator>{}</dc:creator>", escape(author)));
+        }
+
+        for category in &item.categories {
+            xml.push_str(&format!("<category>{}</category>", escape(category)));
+        }
+
+        if let Some(enclosure) = &item.enclosure {
+            // the size of the file is unknown, 0 is what the spec recommends then
+            xml.push_str(&format!(
+                r#"<enclosure url="{}" length="0" type="{}"/>"#,
+                escape(&enclosure.url),
+                escape(&enclosure.mime_type)
+            ));
+        }
+
+        xml.push_str("</item>");
+    }
+
+    xml.push_str("</channel></rss>");
+
+    xml
+}
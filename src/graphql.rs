@@ -2,7 +2,6 @@ use juniper::{Executor, Context as JuniperContext, FieldResult, FieldError, ID};
 use super::models::Image as ImageModel;
 use super::models::Article as ArticleModel;
 use super::models::Author as AuthorModel;
-use super::models::AuthorAvatar as AuthorAvatarModel;
 use super::models::Keyword as KeywordModel;
 use super::models::Statistics as StatisticsModel;
 use super::models::ArticleAuthor as ArticleAuthorModel;
@@ -19,7 +18,13 @@ use super::models::Slideshow as SlideshowModel;
 use super::models::SlideshowItem as SlideshowItemModel;
 use juniper_eager_loading::{prelude::*, *};
 use juniper_from_schema::graphql_schema_from_file;
+use crate::avatars::Avatar;
+use crate::changes::ChangeEvent;
 use crate::db::{DbConn, DbConnPool};
+use crate::page_views::PageViewRecorder;
+use crate::syndication::RouteFormats;
+use crate::syndication::wxr::ExportAuthentication;
+use crate::tenant::{Organization, Tenant, TenantOrganizations};
 use diesel::prelude::*;
 use diesel::debug_query;
 use chrono::prelude::*;
@@ -28,16 +33,142 @@ use rocket::{
     request::{self, FromRequest, Request},
     Outcome, State,
 };
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Mutex;
 use serde::de::{Deserialize, Deserializer, Visitor, SeqAccess, MapAccess};
 use base64::{encode, decode};
+pub mod analytics;
+pub mod bylines;
+pub mod cache;
+pub mod cache_control;
 pub mod generator;
+pub mod loader;
+pub mod ninjs;
+pub mod paths;
+pub mod persisted_queries;
+pub mod query_plan;
+pub mod request;
+pub mod response;
+pub mod seo;
+pub mod structured_data;
+pub mod subscriptions;
+
+pub use self::analytics::AnalyticsBucket;
+pub use self::bylines::AuthorRoleGroup;
+pub use self::seo::EffectiveSeo;
+pub use self::subscriptions::Subscription;
+use self::query_plan::{ArticleColumn, ArticleProjection, ArticlesPlan, Count};
 
 graphql_schema_from_file!("schema.graphql");
 
+/// Configuration of the fields, read once at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextConfig {
+    pub byline_roles: bylines::BylineRoles,
+    pub organizations: TenantOrganizations,
+    /// `Count::Exact` or `Count::Estimate` (`PUBLISHER_ARTICLES_COUNT`).
+    pub articles_count: Count,
+}
+
+impl ContextConfig {
+    pub fn from_env() -> Self {
+        ContextConfig {
+            byline_roles: bylines::BylineRoles::from_env(),
+            organizations: TenantOrganizations::from_env(),
+            articles_count: Count::from_env(),
+        }
+    }
+}
+
 pub struct Context {
-    pub db_con: DbConn
+    pub db_con: DbConn,
+    pub response_metadata: Mutex<response::ResponseMetadata>,
+    /// Set when executing a subscription for a change event.
+    pub change_event: Option<ChangeEvent>,
+    pub tenant: Tenant,
+    pub urls: generator::UrlBuilder,
+    pub organization: Option<Organization>,
+    /// Set for HTTP requests, page views can't be recorded otherwise.
+    pub page_views: Option<PageViewRecorder>,
+    /// Set for HTTP requests with the export token, which `analytics` requires.
+    pub export_authentication: Option<ExportAuthentication>,
+    /// Models loaded by the computed fields, fetched once per request.
+    pub loader: loader::Loader,
+    pub route_formats: RouteFormats,
+    pub config: ContextConfig,
+    /// Columns selected for the articles `articles` loaded with placeholders
+    /// for the other columns, by article id.
+    pub article_projections: Mutex<HashMap<i32, ArticleProjection>>,
+}
+
+impl Context {
+    pub fn new(
+        db_con: DbConn,
+        urls: &generator::UrlBuilders,
+        route_formats: &RouteFormats,
+        config: &ContextConfig,
+    ) -> Self {
+        Context {
+            db_con,
+            response_metadata: Mutex::new(response::ResponseMetadata::default()),
+            change_event: None,
+            tenant: Tenant {
+                code: crate::tenant::DEFAULT_TENANT.to_string(),
+                language: crate::tenant::DEFAULT_LANGUAGE.to_string(),
+            },
+            urls: urls.for_tenant(crate::tenant::DEFAULT_TENANT),
+            organization: config.organizations.for_tenant(crate::tenant::DEFAULT_TENANT),
+            page_views: None,
+            export_authentication: None,
+            loader: loader::Loader::default(),
+            route_formats: route_formats.clone(),
+            config: config.clone(),
+            article_projections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn for_tenant(
+        db_con: DbConn,
+        tenant: &Tenant,
+        urls: &generator::UrlBuilders,
+        route_formats: &RouteFormats,
+        config: &ContextConfig,
+    ) -> Self {
+        Context {
+            tenant: tenant.clone(),
+            urls: urls.for_tenant(&tenant.code),
+            organization: config.organizations.for_tenant(&tenant.code),
+            ..Context::new(db_con, urls, route_formats, config)
+        }
+    }
+
+    /// The context executing a subscription for the change event.
+    pub fn for_event(self, change_event: ChangeEvent) -> Self {
+        Context {
+            change_event: Some(change_event),
+            ..self
+        }
+    }
+
+    pub fn response_metadata(&self) -> response::ResponseMetadata {
+        self.response_metadata.lock().expect("response metadata lock poisoned").clone()
+    }
+
+    /// Records entities returned in the response, see `response::ResponseEntity`.
+    pub fn record<'a, T: response::ResponseEntity + 'a>(&self, entities: impl IntoIterator<Item = &'a T>) {
+        let mut metadata = self.response_metadata.lock().expect("response metadata lock poisoned");
+
+        for entity in entities {
+            metadata.record(entity);
+        }
+    }
+
+    pub fn record_key(&self, key: String) {
+        self.response_metadata.lock().expect("response metadata lock poisoned").record_key(key);
+    }
 }
 
 impl JuniperContext for Context {}
@@ -47,9 +178,19 @@ impl<'a, 'r> FromRequest<'a, 'r> for Context {
 
     fn from_request(request: &'a Request<'r>) -> request::Outcome<Context, ()> {
         let db_pool = request.guard::<State<DbConnPool>>()?;
+        let urls = request.guard::<State<generator::UrlBuilders>>()?;
+        let route_formats = request.guard::<State<RouteFormats>>()?;
+        let config = request.guard::<State<ContextConfig>>()?;
+        let tenant = request.guard::<Tenant>()?;
+        let page_views = request.guard::<PageViewRecorder>().succeeded();
+        let export_authentication = request.guard::<ExportAuthentication>().succeeded();
 
         match db_pool.get() {
-            Ok(db_con) => Outcome::Success(Context { db_con }),
+            Ok(db_con) => Outcome::Success(Context {
+                page_views,
+                export_authentication,
+                ..Context::for_tenant(db_con, &tenant, &urls, &route_formats, &config)
+            }),
             Err(_) => Outcome::Failure((Status::ServiceUnavailable, ())),
         }
     }
@@ -60,8 +201,25 @@ pub struct Query;
 pub struct Mutation;
 
 impl MutationFields for Mutation {
-    fn field_noop(&self, _executor: &Executor<'_, Context>) -> FieldResult<&bool> {
-        Ok(&true)
+    fn field_record_page_view(
+        &self,
+        executor: &Executor<'_, Context>,
+        article_id: i32,
+        referrer: Option<String>,
+        visitor_hash: Option<String>,
+    ) -> FieldResult<bool> {
+        let context = executor.context();
+        let recorder = context
+            .page_views
+            .as_ref()
+            .ok_or_else(|| FieldError::from("page views can't be recorded here"))?;
+
+        Ok(recorder.record(
+            article_id,
+            referrer.as_ref().map(String::as_str),
+            visitor_hash.as_ref().map(String::as_str),
+            &context.urls.site_url("/"),
+        ))
     }
 }
 
@@ -105,6 +263,11 @@ pub struct Article {
         foreign_key_field = "article_id",
     )]
     slideshows: HasMany<Slideshow>,
+    #[has_many(
+        root_model_field = "statistics",
+        foreign_key_field = "article_id",
+    )]
+    statistics: HasMany<Statistics>,
 }
 
 #[derive(Clone, Debug, PartialEq, EagerLoading)]
@@ -147,14 +310,43 @@ pub struct ArticleMedia {
     renditions: HasMany<ImageRendition>,
 }
 
-#[derive(Clone, Debug, PartialEq, EagerLoading)]
-#[eager_loading(
-    model = "ImageModel",
-    error = "diesel::result::Error",
-    connection = "PgConnection"
-)]
-pub struct Image {
-    image: ImageModel,
+#[derive(Clone, Debug, PartialEq)]
+pub enum Image {
+    Asset(ImageModel),
+    /// Image of an author, whose URLs differ.
+    Avatar(Avatar),
+}
+
+impl Image {
+    /// `None` for generated avatars, which aren't stored.
+    fn stored(&self) -> Option<&ImageModel> {
+        match self {
+            Image::Asset(image) | Image::Avatar(Avatar::Stored(image)) => Some(image),
+            Image::Avatar(Avatar::Generated(_)) => None,
+        }
+    }
+}
+
+impl GraphqlNodeForModel for Image {
+    type Model = ImageModel;
+    type Id = i32;
+    type Connection = PgConnection;
+    type Error = diesel::result::Error;
+
+    fn new_from_model(model: &Self::Model) -> Self {
+        Image::Asset(model.clone())
+    }
+}
+
+impl EagerLoadAllChildren for Image {
+    fn eager_load_all_children_for_each(
+        _nodes: &mut [Self],
+        _models: &[Self::Model],
+        _db: &Self::Connection,
+        _trail: &QueryTrail<'_, Self, Walked>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, EagerLoading)]
@@ -183,6 +375,33 @@ pub struct Author {
     author: AuthorModel,
 }
 
+impl Author {
+    /// Image of the author's first `swp_author_media`, generated when there
+    /// is none. The avatars of a list are fetched together (`defer_avatars`).
+    fn avatar(&self, context: &Context) -> QueryResult<Avatar> {
+        use crate::schema::swp_author_media;
+
+        let conn = &context.db_con;
+        let media = context.loader.load_by(swp_author_media::author_id, self.author.id, conn)?;
+        let image = match media.iter().min_by_key(|media| media.id) {
+            Some(media) => context.loader.load::<ImageModel>(media.image_id, conn)?,
+            None => None,
+        };
+
+        Ok(match image {
+            Some(image) => Avatar::Stored(image),
+            None => Avatar::Generated(self.author.id),
+        })
+    }
+}
+
+/// Avatars of the authors to fetch with the first `Author::avatar`.
+fn defer_avatars<'a>(authors: impl IntoIterator<Item = &'a Author>, context: &Context) {
+    use crate::schema::swp_author_media;
+
+    context.loader.defer_by(swp_author_media::author_id, authors.into_iter().map(|author| author.author.id));
+}
+
 #[derive(Clone, Debug, PartialEq, EagerLoading)]
 #[eager_loading(
     model = "KeywordModel",
@@ -291,8 +510,8 @@ impl ArticleFields for Article {
         Ok(ID::new(self.article.id.to_string()))
     }
 
-    fn field_title(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.article.title)
+    fn field_title(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        Ok(self.model(&[ArticleColumn::Title], executor.context())?.title.clone())
     }
 
     fn field_status(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
@@ -303,12 +522,12 @@ impl ArticleFields for Article {
         Ok(&self.article.slug)
     }
 
-    fn field_lead(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.article.lead)
+    fn field_lead(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        Ok(self.model(&[ArticleColumn::Lead], executor.context())?.lead.clone())
     }
 
-    fn field_body(&self, _: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.article.body)
+    fn field_body(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        Ok(self.model(&[ArticleColumn::Body], executor.context())?.body.clone())
     }
 
     fn field_comments_count(&self, _: &Executor<'_, Context>) -> FieldResult<&i32> {
@@ -320,23 +539,7 @@ impl ArticleFields for Article {
         _executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Statistics, Walked>,
     ) -> FieldResult<Option<Statistics>> {
-        use crate::schema::swp_article_statistics::dsl;
-        use crate::schema::swp_article_statistics::columns::article_id;
-
-        let conn = &_executor.context().db_con;
-        let statistics = dsl::swp_article_statistics
-            .filter(article_id.eq(self.article.id))
-            .first::<StatisticsModel>(conn)
-            .optional()
-            .unwrap();
-
-        if let Some(stats) = statistics {
-            Ok(Some(Statistics {
-                statistics: stats
-            }))
-        } else {
-            Ok(None)
-        }
+        Ok(self.statistics.try_unwrap()?.first().cloned())
     }
 
     fn field_published_at(&self, _: &Executor<'_, Context>) -> FieldResult<Option<NaiveDateTime>> {
@@ -347,12 +550,52 @@ impl ArticleFields for Article {
         Ok(self.article.updated_at)
     }
 
+    fn field_url(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        let context = executor.context();
+        let route_slug = self.route_slug(context)?;
+
+        Ok(context.urls.article_url(&route_slug, &self.article.slug, self.article.id, self.article.published_at))
+    }
+
+    fn field_canonical_url(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        let context = executor.context();
+        let route_slug = self.route_slug(context)?;
+
+        Ok(context.urls.canonical_article_url(&route_slug, &self.article.slug, self.article.id, self.article.published_at))
+    }
+
+    fn field_structured_data(&self, executor: &Executor<'_, Context>) -> FieldResult<Json> {
+        let context = executor.context();
+        let document = structured_data::news_article(&self.model(ArticleColumn::ALL, context)?, context)?;
+
+        Ok(Json(document.to_string()))
+    }
+
+    fn field_structured_data_script(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        let context = executor.context();
+        let document = structured_data::news_article(&self.model(ArticleColumn::ALL, context)?, context)?;
+
+        Ok(structured_data::script_tag(&document))
+    }
+
+    fn field_ninjs(&self, executor: &Executor<'_, Context>, version: String) -> FieldResult<Json> {
+        let version = ninjs::NinjsVersion::parse(Some(&version))
+            .ok_or_else(|| FieldError::from(format!("unsupported ninjs version {}", version)))?;
+        let context = executor.context();
+        let document = ninjs::article_ninjs(&self.model(ArticleColumn::ALL, context)?, version, context)?;
+
+        Ok(Json(document.to_string()))
+    }
+
     fn field_route(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Route, Walked>,
     ) -> FieldResult<&Route> {
-        Ok(self.route.try_unwrap()?)
+        let route = self.route.try_unwrap()?;
+        executor.context().record(Some(route));
+
+        Ok(route)
     }
 
     fn field_media(
@@ -365,26 +608,79 @@ impl ArticleFields for Article {
 
     fn field_authors(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Author, Walked>,
-    ) -> FieldResult<&Vec<Author>> {
-        Ok(self.authors.try_unwrap()?)
+        role: Option<String>,
+    ) -> FieldResult<Vec<Author>> {
+        let mut authors = self
+            .authors
+            .try_unwrap()?
+            .iter()
+            .filter(|author| match &role {
+                Some(role) => author.author.role.to_lowercase() == role.trim().to_lowercase(),
+                None => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        authors.sort_by_key(|author| author.author.id);
+        executor.context().record(&authors);
+        defer_avatars(&authors, executor.context());
+
+        Ok(authors)
+    }
+
+    fn field_authors_by_role(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, AuthorRoleGroup, Walked>,
+    ) -> FieldResult<Vec<AuthorRoleGroup>> {
+        let context = executor.context();
+        let models = self.author_models(context)?;
+        let authors = match trail.authors().walk() {
+            Some(author_trail) => map_models_to_graphql_nodes(&models, &author_trail, &context.db_con)?,
+            None => Author::from_db_models(&models),
+        };
+        defer_avatars(&authors, context);
+
+        Ok(bylines::group_by_role(&authors, &context.config.byline_roles))
+    }
+
+    fn field_byline(
+        &self,
+        executor: &Executor<'_, Context>,
+        locale: String,
+        format: BylineFormat,
+        oxford_comma: bool,
+    ) -> FieldResult<Option<String>> {
+        let context = executor.context();
+        let authors = Author::from_db_models(&self.author_models(context)?);
+        context.record(&authors);
+
+        Ok(bylines::byline(
+            &bylines::group_by_role(&authors, &context.config.byline_roles),
+            &locale,
+            &format,
+            oxford_comma,
+        ))
     }
 
     fn field_keywords(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Keyword, Walked>,
     ) -> FieldResult<&Vec<Keyword>> {
-        Ok(self.keywords.try_unwrap()?)
+        let keywords = self.keywords.try_unwrap()?;
+        executor.context().record(keywords);
+
+        Ok(keywords)
     }
 
-    fn field_extra(&self, _: &Executor<'_, Context>) -> FieldResult<&Option<String>> {
-        Ok(&self.article.extra)
+    fn field_extra(&self, executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        Ok(self.model(&[ArticleColumn::Extra], executor.context())?.extra.clone())
     }
 
-    fn field_metadata(&self, _: &Executor<'_, Context>) -> FieldResult<&Option<String>> {
-        Ok(&self.article.metadata)
+    fn field_metadata(&self, executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        Ok(self.model(&[ArticleColumn::Metadata], executor.context())?.metadata.clone())
     }
 
     fn field_feature_media<'a>(
@@ -409,6 +705,16 @@ impl ArticleFields for Article {
         self.seo_metadata.try_unwrap().map_err(From::from)
     }
 
+    fn field_effective_seo(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, EffectiveSeo, Walked>,
+    ) -> FieldResult<EffectiveSeo> {
+        let context = executor.context();
+
+        Ok(EffectiveSeo::load(&self.model(ArticleColumn::ALL, context)?, context)?)
+    }
+
     fn field_related_articles(
         &self,
         _executor: &Executor<'_, Context>,
@@ -434,6 +740,67 @@ impl ArticleFields for Article {
     }
 }
 
+impl Article {
+    /// The article's model with the columns loaded. Articles of `articles`
+    /// may only have the planned columns, the placeholders of the others are
+    /// never returned: the complete model is loaded instead.
+    fn model(&self, columns: &[ArticleColumn], context: &Context) -> QueryResult<Cow<'_, ArticleModel>> {
+        let partial = context
+            .article_projections
+            .lock()
+            .expect("article projections lock poisoned")
+            .get(&self.article.id)
+            .map_or(false, |projection| !columns.iter().all(|column| projection.selects(*column)));
+
+        if !partial {
+            return Ok(Cow::Borrowed(&self.article));
+        }
+
+        context
+            .loader
+            .load::<ArticleModel>(self.article.id, &context.db_con)?
+            .map(Cow::Owned)
+            .ok_or(diesel::result::Error::NotFound)
+    }
+
+    /// The eager-loaded authors, or those of the loader when `authors` isn't
+    /// queried. Articles don't order their authors, they are ordered by id
+    /// like those of `authors` and of the bylines.
+    fn author_models(&self, context: &Context) -> QueryResult<Vec<AuthorModel>> {
+        use crate::schema::swp_article_author;
+
+        let mut authors = match self.authors.try_unwrap() {
+            Ok(authors) => authors.iter().map(|author| author.author.clone()).collect(),
+            Err(_) => {
+                let author_ids = context
+                    .loader
+                    .load_by(swp_article_author::article_id, self.article.id, &context.db_con)?
+                    .into_iter()
+                    .map(|article_author| article_author.author_id)
+                    .collect::<Vec<_>>();
+
+                context.loader.load_many::<AuthorModel>(&author_ids, &context.db_con)?
+            }
+        };
+        authors.sort_by_key(|author| author.id);
+
+        Ok(authors)
+    }
+
+    /// Slug of the article's route, loaded separately when the route was not
+    /// eager loaded.
+    fn route_slug(&self, context: &Context) -> QueryResult<String> {
+        match self.route.try_unwrap() {
+            Ok(route) => Ok(route.route.slug.clone()),
+            Err(_) => context
+                .loader
+                .load::<RouteModel>(self.article.route_id, &context.db_con)?
+                .map(|route| route.slug)
+                .ok_or(diesel::result::Error::NotFound),
+        }
+    }
+}
+
 impl AuthorFields for Author {
     fn field_id(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
         Ok(&self.author.id)
@@ -459,6 +826,10 @@ impl AuthorFields for Author {
         Ok(&self.author.slug)
     }
 
+    fn field_url(&self, executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        Ok(self.author.slug.as_ref().map(|slug| executor.context().urls.author_url(slug)))
+    }
+
     fn field_twitter(&self, _executor: &Executor<'_, Context>) -> FieldResult<&Option<String>> {
         Ok(&self.author.twitter)
     }
@@ -471,32 +842,18 @@ impl AuthorFields for Author {
         Ok(&self.author.instagram)
     }
 
-    fn field_avatar_url(&self, _executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
-        use crate::{graphql::generator::*};
-        use crate::schema::swp_author_media::dsl;
-        use crate::schema::swp_author_media::columns::author_id;
-        use crate::schema::swp_image::dsl as dsl_image;
-        use crate::schema::swp_image::columns::id;
-
-        let conn = &_executor.context().db_con;
-        let avatar = dsl::swp_author_media
-            .filter(author_id.eq(self.author.id))
-            .first::<AuthorAvatarModel>(conn)
-            .unwrap();
-
-        let image = dsl_image::swp_image
-            .filter(id.eq(avatar.image_id))
-            .first::<ImageModel>(conn)
-            .optional()
-            .unwrap();
-            
-        let mut url = String::new();
-
-        if let Some(i) = image {
-            url = generate_avatar_url(&i.asset_id, &i.file_extension);
-        }
+    fn field_avatar(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Image, Walked>,
+    ) -> FieldResult<Image> {
+        Ok(Image::Avatar(self.avatar(executor.context())?))
+    }
 
-        Ok(Some(url))
+    fn field_avatar_url(&self, executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        let context = executor.context();
+
+        Ok(Some(self.avatar(context)?.url(&context.urls, None)))
     }
 }
 
@@ -522,6 +879,18 @@ impl StatisticsFields for Statistics {
     fn field_page_views_number(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
         Ok(&self.statistics.page_views_number)
     }
+
+    fn field_impressions_number(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
+        Ok(&self.statistics.impressions_number)
+    }
+
+    fn field_internal_click_rate(&self, _executor: &Executor<'_, Context>) -> FieldResult<&Option<f64>> {
+        Ok(&self.statistics.internal_click_rate)
+    }
+
+    fn field_updated_at(&self, _executor: &Executor<'_, Context>) -> FieldResult<NaiveDateTime> {
+        Ok(self.statistics.updated_at.unwrap_or(self.statistics.created_at))
+    }
 }
 
 impl RouteFields for Route {
@@ -536,6 +905,10 @@ impl RouteFields for Route {
     fn field_slug(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
         Ok(&self.route.slug)
     }
+
+    fn field_url(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
+        Ok(executor.context().urls.route_url(&self.route.slug))
+    }
 }
 
 impl ArticleMediaFields for ArticleMedia {
@@ -573,10 +946,13 @@ impl ArticleMediaFields for ArticleMedia {
 
     fn field_article(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Article, Walked>,
     ) -> FieldResult<&Article> {
-        Ok(self.article.try_unwrap()?)
+        let article = self.article.try_unwrap()?;
+        executor.context().record(Some(article));
+
+        Ok(article)
     }
 
     fn field_image(
@@ -689,20 +1065,26 @@ impl ArticleSeoMediaFields for ArticleSeoMedia {
 impl RelatedArticleFields for RelatedArticle {
     fn field_article(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Article, Walked>,
     ) -> FieldResult<&Article> {
-        Ok(self.article.try_unwrap()?)
+        let article = self.article.try_unwrap()?;
+        executor.context().record(Some(article));
+
+        Ok(article)
     }
 }
 
 impl SlideshowFields for Slideshow {
     fn field_article(
         &self,
-        _executor: &Executor<'_, Context>,
+        executor: &Executor<'_, Context>,
         _trail: &QueryTrail<'_, Article, Walked>,
     ) -> FieldResult<&Article> {
-        Ok(self.article.try_unwrap()?)
+        let article = self.article.try_unwrap()?;
+        executor.context().record(Some(article));
+
+        Ok(article)
     }
 
     fn field_code(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
@@ -765,22 +1147,224 @@ impl QueryFields for Query {
         after: Option<Cursor>,
         first: i32,
         route: Option<i32>,
+        author: Option<i32>,
+        keyword: Option<i32>,
+        status: Option<String>,
     ) -> FieldResult<Option<ArticleConnection>> {
-        let conn = &executor.context().db_con;
-        let articles_connection = Some(articles_connections(after, first, route, trail, conn)?);
+        let context = executor.context();
+        let filter = ArticleFilter { route, author, keyword, status };
+        let articles_connection = articles_connections(after, first, &filter, trail, context)?;
+        context.record(articles_connection.edges.iter().map(|edge| &edge.node));
+        for key in filter.surrogate_keys() {
+            context.record_key(key);
+        }
+
+        Ok(Some(articles_connection))
+    }
+
+    fn field_article(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, Article, Walked>,
+        id: i32,
+    ) -> FieldResult<Option<Article>> {
+        load_article(id, executor, trail)
+    }
+
+    fn field_resolve_path(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, PathResult, Walked>,
+        path: String,
+    ) -> FieldResult<Option<PathResult>> {
+        use self::paths::{resolve_path, ResolvedPath};
+
+        let context = executor.context();
+        let resolved = resolve_path(&path, &context.urls, &context.db_con)?.map(|resolved| match resolved {
+            ResolvedPath::Route(route) => PathResult::RouteResult(RouteResult {
+                route: Route::new_from_model(&route),
+            }),
+            ResolvedPath::Article(article) => PathResult::ArticleResult(ArticleResult { article }),
+            ResolvedPath::Redirect { location, status_code } => {
+                PathResult::RedirectResult(RedirectResult { location, status_code })
+            }
+        });
+
+        Ok(resolved)
+    }
+
+    fn field_analytics(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, AnalyticsBucket, Walked>,
+        group_by: AnalyticsDimension,
+        order_by: Option<AnalyticsMetric>,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        route: Option<i32>,
+        first: Option<i32>,
+    ) -> FieldResult<Vec<AnalyticsBucket>> {
+        let context = executor.context();
+        context
+            .export_authentication
+            .as_ref()
+            .ok_or_else(|| FieldError::from("analytics require the export token"))?;
+
+        let query = analytics::AnalyticsQuery::new(group_by, order_by, from, to, route, first);
+
+        Ok(analytics::aggregate(&query, &context.db_con)?)
+    }
+}
+
+pub struct RouteResult {
+    route: Route,
+}
+
+impl RouteResultFields for RouteResult {
+    fn field_route(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Route, Walked>,
+    ) -> FieldResult<&Route> {
+        executor.context().record(Some(&self.route));
+
+        Ok(&self.route)
+    }
+}
+
+pub struct ArticleResult {
+    article: ArticleModel,
+}
+
+impl ArticleResultFields for ArticleResult {
+    fn field_article(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, Article, Walked>,
+    ) -> FieldResult<Article> {
+        let context = executor.context();
+        let article = map_models_to_graphql_nodes(&[self.article.clone()], trail, &context.db_con)?
+            .pop()
+            .ok_or_else(|| FieldError::from("article could not be loaded"))?;
+        context.record(Some(&article));
+
+        Ok(article)
+    }
+}
+
+pub struct RedirectResult {
+    location: String,
+    status_code: i32,
+}
+
+impl RedirectResultFields for RedirectResult {
+    fn field_location(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.location)
+    }
+
+    fn field_status_code(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
+        Ok(&self.status_code)
+    }
+}
+
+fn load_article(
+    id: i32,
+    executor: &Executor<'_, Context>,
+    trail: &QueryTrail<'_, Article, Walked>,
+) -> FieldResult<Option<Article>> {
+    let context = executor.context();
+    let conn = &context.db_con;
+    let article_model = context.loader.load::<ArticleModel>(id, conn)?;
+
+    let article = match article_model {
+        Some(article_model) => map_models_to_graphql_nodes(&[article_model], trail, conn)?.pop(),
+        None => None,
+    };
+
+    context.record(article.as_ref());
+
+    Ok(article)
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ArticleFilter {
+    pub route: Option<i32>,
+    pub author: Option<i32>,
+    pub keyword: Option<i32>,
+    pub status: Option<String>,
+}
+
+impl ArticleFilter {
+    /// Surrogate keys of the list, purged when an article is added to it.
+    /// Change events only name the article and its route, so lists of an
+    /// author or keyword are also purged by any article change (`articles`).
+    fn surrogate_keys(&self) -> Vec<String> {
+        match (self.route, self.author, self.keyword) {
+            (Some(route_id), _, _) => vec![format!("route-{}-articles", route_id)],
+            (None, Some(author_id), _) => vec![format!("author-{}-articles", author_id), "articles".to_string()],
+            (None, None, Some(keyword_id)) => vec![format!("keyword-{}-articles", keyword_id), "articles".to_string()],
+            (None, None, None) => vec!["articles".to_string()],
+        }
+    }
+}
 
-        Ok(articles_connection)
+/// Articles matching the filter, in no particular order.
+fn filtered_articles(filter: &ArticleFilter) -> crate::schema::swp_article::BoxedQuery<'_, diesel::pg::Pg> {
+    use crate::schema::{swp_article, swp_article_author, swp_article_keyword};
+
+    let mut query = swp_article::table.into_boxed();
+
+    if let Some(id) = filter.route {
+        query = query.filter(swp_article::route_id.eq(id));
+    }
+
+    if let Some(id) = filter.author {
+        query = query.filter(swp_article::id.eq_any(
+            swp_article_author::table
+                .select(swp_article_author::article_id)
+                .filter(swp_article_author::author_id.eq(id)),
+        ));
+    }
+
+    if let Some(id) = filter.keyword {
+        query = query.filter(swp_article::id.eq_any(
+            swp_article_keyword::table
+                .select(swp_article_keyword::article_id)
+                .filter(swp_article_keyword::keyword_id.eq(id)),
+        ));
+    }
+
+    if let Some(ref status) = filter.status {
+        query = query.filter(swp_article::status.eq(status));
     }
+
+    query
+}
+
+/// Number of articles from the table statistics when the list is not
+/// filtered, or else the planner's estimate for the filters.
+fn estimate_articles_count(filter: &ArticleFilter, conn: &PgConnection) -> QueryResult<i64> {
+    use crate::models::pagination::{estimated_table_rows, Explain};
+
+    if *filter == ArticleFilter::default() {
+        if let Some(rows) = estimated_table_rows("swp_article", conn)? {
+            return Ok(rows);
+        }
+    }
+
+    filtered_articles(filter).explain().estimated_rows(conn)
 }
 
 fn articles_connections(
     cursor: Option<Cursor>,
     page_size: i32,
-    route_id: Option<i32>,
+    filter: &ArticleFilter,
     trail: &QueryTrail<'_, ArticleConnection, Walked>,
-    conn: &PgConnection,
+    context: &Context,
 ) -> QueryResult<ArticleConnection> {
-    use crate::{models::pagination::*, schema::swp_article};
+    use crate::{models::pagination::*, schema::{swp_article, swp_article_author, swp_article_keyword}};
+
+    let conn = &context.db_con;
 
     let page_size = i64::from(page_size);
 
@@ -796,20 +1380,71 @@ fn articles_connections(
     let val = (page_number + 1).to_string();
     let next_page_cursor = Cursor(encode(&val));
 
-    let mut base_query = swp_article::table
-        .into_boxed()
-        .select(swp_article::all_columns);
+    let plan = ArticlesPlan::new(trail, context.config.articles_count);
+    let offset = (page_number - 1) * page_size;
 
-    if let Some(id) = route_id {
-        base_query = base_query.filter(swp_article::route_id.eq(id));
-    }
+    let (article_models, window_count, has_more) = match &plan.projection {
+        Some(projection) if plan.count == Count::Exact => {
+            let (article_models, total_count) = filtered_articles(filter)
+                .select(projection.columns())
+                .order(swp_article::published_at.desc())
+                .paginate(page_number)
+                .per_page(page_size)
+                .load_and_count_pages::<ArticleModel>(conn)?;
 
-    base_query = base_query.order(swp_article::published_at.desc());
+            (article_models, Some(total_count), total_count > offset + page_size)
+        }
+        Some(projection) => {
+            // one more row tells whether there is a next page
+            let mut article_models = filtered_articles(filter)
+                .select(projection.columns())
+                .order(swp_article::published_at.desc())
+                .limit(page_size + 1)
+                .offset(offset)
+                .load::<ArticleModel>(conn)?;
+            let has_more = article_models.len() as i64 > page_size;
+            article_models.truncate(page_size as usize);
+
+            (article_models, None, has_more)
+        }
+        None => (Vec::new(), None, false),
+    };
 
-    let (article_models, total_count) = base_query
-        .paginate(page_number)
-        .per_page(page_size)
-        .load_and_count_pages::<ArticleModel>(conn)?;
+    let total_count = match (window_count, plan.count) {
+        (Some(total_count), _) => total_count,
+        (None, Count::Exact) => filtered_articles(filter).count().get_result::<i64>(conn)?,
+        (None, Count::Estimate) => {
+            let seen = offset + article_models.len() as i64 + if has_more { 1 } else { 0 };
+            estimate_articles_count(filter, conn)?.max(seen)
+        }
+        (None, Count::Skip) => 0,
+    };
+    let has_next_page = match plan.projection {
+        Some(_) => has_more,
+        None => total_count > offset + page_size,
+    };
+
+    match plan.projection.as_ref() {
+        Some(projection) if !projection.is_complete() => {
+            let mut projections = context.article_projections.lock().expect("article projections lock poisoned");
+            for article in &article_models {
+                projections.insert(article.id, projection.clone());
+            }
+        }
+        _ => {}
+    }
+
+    if plan.projection.as_ref().map_or(false, ArticleProjection::is_complete) {
+        // fetched together by the computed fields of the first article needing them
+        let loader = &context.loader;
+        loader.prime(&article_models);
+        loader.defer::<RouteModel>(article_models.iter().map(|article| article.route_id));
+        loader.defer::<ArticleSeoMetadataModel>(article_models.iter().filter_map(|article| article.seo_metadata_id));
+        loader.defer::<ArticleMediaModel>(article_models.iter().filter_map(|article| article.feature_media));
+    }
+    // the join rows only need the ids, e.g. for the bylines of projected articles
+    context.loader.defer_by(swp_article_author::article_id, article_models.iter().map(|article| article.id));
+    context.loader.defer_by(swp_article_keyword::article_id, article_models.iter().map(|article| article.id));
 
     let articles = if let Some(article_trail) = trail.edges().node().walk() {
         map_models_to_graphql_nodes(&article_models, &article_trail, conn)?
@@ -817,6 +1452,8 @@ fn articles_connections(
         vec![]
     };
 
+    defer_avatars(articles.iter().filter_map(|article| article.authors.try_unwrap().ok()).flatten(), context);
+
     let edges = articles
         .into_iter()
         .map(|article| Edge {
@@ -830,8 +1467,8 @@ fn articles_connections(
     let page_info = PageInfo {
         start_cursor: edges.first().map(|edge| edge.cursor.clone()),
         end_cursor: edges.last().map(|edge| edge.cursor.clone()),
-        has_next_page: if total_count as i32 > (page_size as i32 * page_number as i32) { true } else { false },
-        has_previous_page: if page_number as i32 > 1 && page_size as i32 == total_count as i32 { true } else { false }
+        has_next_page,
+        has_previous_page: page_number > 1,
     };
 
     Ok(ArticleConnection {
@@ -842,22 +1479,71 @@ fn articles_connections(
 }
 
 impl ImageFields for Image {
-    fn field_id(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
-        Ok(&self.image.id)
+    fn field_id(&self, _executor: &Executor<'_, Context>) -> FieldResult<Option<i32>> {
+        Ok(self.stored().map(|image| image.id))
     }
 
-    fn field_asset_id(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.image.asset_id)
+    fn field_asset_id(&self, _executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        Ok(self.stored().map(|image| image.asset_id.clone()))
     }
 
-    fn field_file_extension(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
-        Ok(&self.image.file_extension)
+    fn field_file_extension(&self, _executor: &Executor<'_, Context>) -> FieldResult<Option<String>> {
+        Ok(self.stored().map(|image| image.file_extension.clone()))
     }
 
-    fn field_url(&self, _executor: &Executor<'_, Context>) -> FieldResult<String> {
+    fn field_url(&self, executor: &Executor<'_, Context>) -> FieldResult<String> {
         use crate::{graphql::generator::*};
 
-        Ok(generate_asset_url(&self.image.asset_id, &self.image.file_extension))
+        match self {
+            Image::Asset(image) => Ok(generate_asset_url(&image.asset_id, &image.file_extension)),
+            Image::Avatar(avatar) => Ok(avatar.url(&executor.context().urls, None)),
+        }
+    }
+
+    fn field_generated(&self, _executor: &Executor<'_, Context>) -> FieldResult<&bool> {
+        Ok(match self {
+            Image::Avatar(Avatar::Generated(_)) => &true,
+            _ => &false,
+        })
+    }
+
+    fn field_renditions(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, ImageVariant, Walked>,
+    ) -> FieldResult<Vec<ImageVariant>> {
+        let urls = &executor.context().urls;
+
+        Ok(match self {
+            Image::Avatar(avatar) => avatar.renditions(urls),
+            Image::Asset(_) => Vec::new(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageVariant {
+    pub name: String,
+    pub width: i32,
+    pub height: Option<i32>,
+    pub url: String,
+}
+
+impl ImageVariantFields for ImageVariant {
+    fn field_name(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.name)
+    }
+
+    fn field_width(&self, _executor: &Executor<'_, Context>) -> FieldResult<&i32> {
+        Ok(&self.width)
+    }
+
+    fn field_height(&self, _executor: &Executor<'_, Context>) -> FieldResult<&Option<i32>> {
+        Ok(&self.height)
+    }
+
+    fn field_url(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.url)
     }
 }
 
@@ -985,3 +1671,147 @@ impl ArticleEdgeFields for ArticleEdge {
         Ok(&self.cursor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use juniper::http::GraphQLRequest;
+    use crate::db::query_counter::QueryCounter;
+    use super::*;
+
+    const ARTICLES_QUERY: &str =
+        "{ articles(first: 20) { edges { node { id statistics { pageViewsNumber } authors { id avatarUrl } } } } }";
+
+    /// The tables of `ARTICLES_QUERY` as temporary tables, which shadow the
+    /// Publisher's. Each article has statistics and two authors, those with
+    /// an odd id have an avatar.
+    fn create_articles(conn: &PgConnection, articles: i32) {
+        conn.batch_execute(&format!(
+            r#"
+            CREATE TEMPORARY TABLE swp_article (
+                id integer PRIMARY KEY,
+                slug varchar NOT NULL,
+                status varchar NOT NULL,
+                title varchar NOT NULL,
+                body text NOT NULL,
+                lead text NOT NULL,
+                route_id integer NOT NULL,
+                comments_count integer NOT NULL,
+                extra text,
+                metadata text,
+                feature_media integer,
+                seo_metadata_id integer,
+                published_at timestamp,
+                updated_at timestamp
+            );
+            CREATE TEMPORARY TABLE swp_article_statistics (
+                id integer PRIMARY KEY,
+                article_id integer NOT NULL,
+                page_views_number integer NOT NULL,
+                impressions_number integer NOT NULL,
+                internal_click_rate double precision,
+                created_at timestamp NOT NULL,
+                updated_at timestamp
+            );
+            CREATE TEMPORARY TABLE swp_author (
+                id integer PRIMARY KEY,
+                name varchar NOT NULL,
+                role varchar NOT NULL,
+                job_title varchar NOT NULL,
+                biography text,
+                slug varchar,
+                twitter varchar,
+                facebook varchar,
+                instagram varchar,
+                author_media_id integer
+            );
+            CREATE TEMPORARY TABLE swp_article_author (
+                article_id integer NOT NULL,
+                author_id integer NOT NULL
+            );
+            CREATE TEMPORARY TABLE swp_image (
+                id integer PRIMARY KEY,
+                asset_id varchar NOT NULL,
+                file_extension varchar NOT NULL
+            );
+            CREATE TEMPORARY TABLE swp_author_media (
+                id integer PRIMARY KEY,
+                author_id integer NOT NULL,
+                image_id integer NOT NULL,
+                key varchar NOT NULL
+            );
+
+            INSERT INTO swp_article (id, slug, status, title, body, lead, route_id, comments_count, published_at)
+                SELECT id, 'article-' || id, 'published', 'Article ' || id, '', '', 1, 0, timestamp '2019-05-01' + id * interval '1 hour'
+                FROM generate_series(1, {articles}) AS id;
+            INSERT INTO swp_article_statistics (id, article_id, page_views_number, impressions_number, created_at)
+                SELECT id, id, id * 10, id * 100, timestamp '2019-05-01' FROM generate_series(1, {articles}) AS id;
+            INSERT INTO swp_author (id, name, role, job_title)
+                SELECT id, 'Author ' || id, 'writer', '' FROM generate_series(1, {articles} * 2) AS id;
+            INSERT INTO swp_article_author (article_id, author_id)
+                SELECT id, id * 2 - 1 FROM generate_series(1, {articles}) AS id
+                UNION ALL SELECT id, id * 2 FROM generate_series(1, {articles}) AS id;
+            INSERT INTO swp_image (id, asset_id, file_extension)
+                SELECT id, 'avatar-' || id, 'jpg' FROM generate_series(1, {articles} * 2, 2) AS id;
+            INSERT INTO swp_author_media (id, author_id, image_id, key)
+                SELECT id, id, id, 'avatar' FROM generate_series(1, {articles} * 2, 2) AS id;
+            "#,
+            articles = articles
+        ))
+        .expect("failed to create the articles");
+    }
+
+    /// Queries sent to the database for `ARTICLES_QUERY` with `articles`
+    /// articles.
+    fn articles_queries(articles: i32) -> usize {
+        dotenv::dotenv().ok();
+
+        let counter = QueryCounter::start();
+        let db_con = counter.pool().get().expect("failed to connect to the database");
+        create_articles(&db_con, articles);
+
+        let urls = generator::UrlBuilders::from_env().unwrap();
+        let context = Context::new(db_con, &urls, &RouteFormats::from_env(), &ContextConfig::from_env());
+        counter.reset();
+
+        let schema = Schema::new(Query, Mutation);
+        let request = GraphQLRequest::new(ARTICLES_QUERY.to_string(), None, None);
+        let response = request.execute(&schema, &context);
+        let queries = counter.queries();
+
+        let response = serde_json::to_value(&response).unwrap();
+        assert!(response.get("errors").is_none(), "{}", response);
+
+        let edges = response["data"]["articles"]["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), articles as usize);
+        for edge in edges {
+            let node = &edge["node"];
+            let id = node["id"].as_str().unwrap().parse::<i64>().unwrap();
+            assert_eq!(node["statistics"]["pageViewsNumber"], id * 10);
+
+            let authors = node["authors"].as_array().unwrap();
+            assert_eq!(authors.len(), 2);
+            for author in authors {
+                let id = author["id"].as_i64().unwrap();
+                let avatar = if id % 2 == 1 {
+                    format!("/avatar-{}.jpg", id)
+                } else {
+                    format!("/avatars/authors/{}.svg", id)
+                };
+                assert!(author["avatarUrl"].as_str().unwrap().ends_with(&avatar), "{}", author);
+            }
+        }
+
+        queries
+    }
+
+    // needs the database of DATABASE_URL, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn articles_list_needs_a_fixed_number_of_queries() {
+        // the articles, their statistics, the article authors, the authors,
+        // their media and the images of the avatars
+        assert_eq!(articles_queries(20), 6);
+        assert_eq!(articles_queries(2), 6);
+    }
+}
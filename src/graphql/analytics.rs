@@ -0,0 +1,181 @@
+//! Views and impressions of articles aggregated by route, author, keyword or
+//! day, counted from the `swp_article_events` the tracker inserts.
+
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Text};
+use juniper::{Executor, FieldResult};
+use crate::schema::{
+    swp_article, swp_article_author, swp_article_events, swp_article_keyword, swp_article_statistics, swp_author,
+    swp_keyword, swp_route,
+};
+use super::{AnalyticsBucketFields, AnalyticsDimension, AnalyticsMetric, Context};
+
+const DEFAULT_DAYS: i64 = 7;
+const MAX_BUCKETS: i32 = 1000;
+
+const VIEWS: &str = "count(*) filter (where swp_article_events.action = 'pageview')";
+const INTERNAL_VIEWS: &str =
+    "count(*) filter (where swp_article_events.action = 'pageview' and swp_article_events.page_view_source = 'internal')";
+const IMPRESSIONS: &str = "count(*) filter (where swp_article_events.action = 'impression')";
+const DAY: &str = "to_char(swp_article_events.created_at, 'YYYY-MM-DD')";
+
+#[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct AnalyticsBucket {
+    key: String,
+    label: String,
+    views: i64,
+    internal_views: i64,
+    impressions: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyticsQuery {
+    pub group_by: AnalyticsDimension,
+    pub order_by: AnalyticsMetric,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub route: Option<i32>,
+    /// `None` for all the buckets.
+    pub first: Option<i32>,
+}
+
+impl AnalyticsQuery {
+    /// The range defaults to the last 7 days. Without `first`, all the days
+    /// of the range and the top `MAX_BUCKETS` of the other dimensions are
+    /// returned.
+    pub fn new(
+        group_by: AnalyticsDimension,
+        order_by: Option<AnalyticsMetric>,
+        from: Option<NaiveDateTime>,
+        to: Option<NaiveDateTime>,
+        route: Option<i32>,
+        first: Option<i32>,
+    ) -> Self {
+        let to = to.unwrap_or_else(|| Utc::now().naive_utc());
+
+        AnalyticsQuery {
+            order_by: order_by.unwrap_or(AnalyticsMetric::Views),
+            from: from.unwrap_or_else(|| to - Duration::days(DEFAULT_DAYS)),
+            to,
+            route,
+            first: match (first, &group_by) {
+                (Some(first), _) => Some(first.max(0).min(MAX_BUCKETS)),
+                (None, AnalyticsDimension::Day) => None,
+                (None, _) => Some(MAX_BUCKETS),
+            },
+            group_by,
+        }
+    }
+}
+
+/// Applies the range and route filters, the ordering and the limit to the
+/// grouped query, and loads the buckets. Days are in chronological order.
+macro_rules! load_buckets {
+    ($query:expr, $analytics:expr, $conn:expr) => {{
+        let analytics = $analytics;
+        let mut query = $query
+            .filter(swp_article_events::created_at.ge(analytics.from))
+            .filter(swp_article_events::created_at.lt(analytics.to))
+            .into_boxed();
+
+        if let Some(first) = analytics.first {
+            query = query.limit(i64::from(first));
+        }
+
+        if let Some(route) = analytics.route {
+            query = query.filter(swp_article::route_id.eq(route));
+        }
+
+        query = match (&analytics.group_by, &analytics.order_by) {
+            (AnalyticsDimension::Day, _) => query.order(sql::<Text>(DAY).asc()),
+            (_, AnalyticsMetric::Views) => query.order((sql::<BigInt>(VIEWS).desc(), sql::<BigInt>(IMPRESSIONS).desc())),
+            (_, AnalyticsMetric::Impressions) => {
+                query.order((sql::<BigInt>(IMPRESSIONS).desc(), sql::<BigInt>(VIEWS).desc()))
+            }
+        };
+
+        query.load::<AnalyticsBucket>($conn)
+    }};
+}
+
+pub fn aggregate(analytics: &AnalyticsQuery, conn: &PgConnection) -> QueryResult<Vec<AnalyticsBucket>> {
+    let events = swp_article_events::table
+        .inner_join(
+            swp_article_statistics::table.on(swp_article_statistics::id.eq(swp_article_events::article_statistics_id)),
+        )
+        .inner_join(swp_article::table.on(swp_article::id.eq(swp_article_statistics::article_id)));
+    let counts = (sql::<BigInt>(VIEWS), sql::<BigInt>(INTERNAL_VIEWS), sql::<BigInt>(IMPRESSIONS));
+
+    match analytics.group_by {
+        AnalyticsDimension::Route => load_buckets!(
+            events
+                .inner_join(swp_route::table.on(swp_route::id.eq(swp_article::route_id)))
+                .group_by((swp_route::id, swp_route::name))
+                .select((sql::<Text>("swp_route.id::text"), swp_route::name, counts.0, counts.1, counts.2)),
+            analytics,
+            conn
+        ),
+        AnalyticsDimension::Author => load_buckets!(
+            events
+                .inner_join(swp_article_author::table.on(swp_article_author::article_id.eq(swp_article::id)))
+                .inner_join(swp_author::table.on(swp_author::id.eq(swp_article_author::author_id)))
+                .group_by((swp_author::id, swp_author::name))
+                .select((sql::<Text>("swp_author.id::text"), swp_author::name, counts.0, counts.1, counts.2)),
+            analytics,
+            conn
+        ),
+        AnalyticsDimension::Keyword => load_buckets!(
+            events
+                .inner_join(swp_article_keyword::table.on(swp_article_keyword::article_id.eq(swp_article::id)))
+                .inner_join(swp_keyword::table.on(swp_keyword::id.eq(swp_article_keyword::keyword_id)))
+                .group_by((swp_keyword::slug, swp_keyword::name))
+                .select((swp_keyword::slug, swp_keyword::name, counts.0, counts.1, counts.2)),
+            analytics,
+            conn
+        ),
+        AnalyticsDimension::Day => load_buckets!(
+            events
+                .group_by(sql::<Text>(DAY))
+                .select((sql::<Text>(DAY), sql::<Text>(DAY), counts.0, counts.1, counts.2)),
+            analytics,
+            conn
+        ),
+    }
+}
+
+fn to_int(count: i64) -> i32 {
+    count.min(i64::from(i32::max_value())) as i32
+}
+
+impl AnalyticsBucketFields for AnalyticsBucket {
+    fn field_key(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.key)
+    }
+
+    fn field_label(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.label)
+    }
+
+    fn field_views(&self, _executor: &Executor<'_, Context>) -> FieldResult<i32> {
+        Ok(to_int(self.views))
+    }
+
+    fn field_internal_views(&self, _executor: &Executor<'_, Context>) -> FieldResult<i32> {
+        Ok(to_int(self.internal_views))
+    }
+
+    fn field_impressions(&self, _executor: &Executor<'_, Context>) -> FieldResult<i32> {
+        Ok(to_int(self.impressions))
+    }
+
+    fn field_internal_click_rate(&self, _executor: &Executor<'_, Context>) -> FieldResult<Option<f64>> {
+        if self.impressions == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(self.internal_views as f64 / self.impressions as f64 * 100.0))
+    }
+}
@@ -0,0 +1,292 @@
+//! Authors grouped by role and bylines such as "By A, B and C; Photos by D",
+//! in the editorial order of the roles (`PUBLISHER_BYLINE_ROLES`).
+
+use juniper::{Executor, FieldResult};
+use super::{Author, AuthorRoleGroupFields, BylineFormat, Context, QueryTrail, Walked};
+
+const DEFAULT_ROLES: &str = "writer,editor,photographer,illustrator,videographer,translator";
+const DEFAULT_ROLE: &str = "writer";
+
+/// Conjunction and byline prefixes of the roles in a language.
+struct Language {
+    and: &'static str,
+    prefixes: &'static [(&'static str, &'static str)],
+}
+
+const ENGLISH: Language = Language {
+    and: "and",
+    prefixes: &[
+        ("writer", "By"),
+        ("editor", "Edited by"),
+        ("photographer", "Photos by"),
+        ("illustrator", "Illustrations by"),
+        ("videographer", "Video by"),
+        ("translator", "Translated by"),
+    ],
+};
+
+const GERMAN: Language = Language {
+    and: "und",
+    prefixes: &[
+        ("writer", "Von"),
+        ("editor", "Bearbeitet von"),
+        ("photographer", "Fotos von"),
+        ("illustrator", "Illustrationen von"),
+        ("videographer", "Video von"),
+        ("translator", "Übersetzt von"),
+    ],
+};
+
+const FRENCH: Language = Language {
+    and: "et",
+    prefixes: &[
+        ("writer", "Par"),
+        ("editor", "Édité par"),
+        ("photographer", "Photos de"),
+        ("illustrator", "Illustrations de"),
+        ("videographer", "Vidéo de"),
+        ("translator", "Traduit par"),
+    ],
+};
+
+const SPANISH: Language = Language {
+    and: "y",
+    prefixes: &[
+        ("writer", "Por"),
+        ("editor", "Editado por"),
+        ("photographer", "Fotos de"),
+        ("illustrator", "Ilustraciones de"),
+        ("videographer", "Vídeo de"),
+        ("translator", "Traducido por"),
+    ],
+};
+
+/// Language of the locale (`de`, `de-AT`, `fr_CH`, ...), English when it
+/// isn't supported.
+fn language(locale: &str) -> &'static Language {
+    let language = locale.split(|c| c == '-' || c == '_').next().unwrap_or("").to_lowercase();
+
+    match language.as_str() {
+        "de" => &GERMAN,
+        "fr" => &FRENCH,
+        "es" => &SPANISH,
+        _ => &ENGLISH,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorRoleGroup {
+    role: String,
+    authors: Vec<Author>,
+}
+
+/// Editorial order of the roles, `PUBLISHER_BYLINE_ROLES` read once at
+/// startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BylineRoles {
+    roles: Vec<String>,
+}
+
+impl Default for BylineRoles {
+    fn default() -> Self {
+        BylineRoles::new(DEFAULT_ROLES)
+    }
+}
+
+impl BylineRoles {
+    /// Comma separated roles.
+    pub fn new(roles: &str) -> Self {
+        BylineRoles {
+            roles: roles.split(',').map(normalize_role).collect(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        std::env::var("PUBLISHER_BYLINE_ROLES")
+            .map(|roles| BylineRoles::new(&roles))
+            .unwrap_or_default()
+    }
+
+    /// Roles that aren't configured come last.
+    fn position(&self, role: &str) -> usize {
+        self.roles.iter().position(|configured| configured == role).unwrap_or(self.roles.len())
+    }
+}
+
+/// Lowercased role, authors without role are writers.
+fn normalize_role(role: &str) -> String {
+    match role.trim() {
+        "" => DEFAULT_ROLE.to_string(),
+        role => role.to_lowercase(),
+    }
+}
+
+/// Groups of the authors, in the order of the roles followed by the other
+/// roles in the order of their first author. Articles don't order their
+/// authors, so authors are ordered by id (the order they were created in)
+/// for the same byline on every request.
+pub fn group_by_role(authors: &[Author], roles: &BylineRoles) -> Vec<AuthorRoleGroup> {
+    let mut authors = authors.iter().collect::<Vec<_>>();
+    authors.sort_by_key(|author| author.author.id);
+
+    let mut groups: Vec<AuthorRoleGroup> = Vec::new();
+    for author in authors {
+        let role = normalize_role(&author.author.role);
+
+        match groups.iter_mut().find(|group| group.role == role) {
+            Some(group) => group.authors.push(author.clone()),
+            None => groups.push(AuthorRoleGroup {
+                role,
+                authors: vec![author.clone()],
+            }),
+        }
+    }
+
+    groups.sort_by_key(|group| roles.position(&group.role));
+
+    groups
+}
+
+/// "A", "A and B", "A, B and C" (or "A, B, and C" with the Oxford comma).
+fn join_names(names: &[&str], and: &str, oxford_comma: bool) -> String {
+    match names.len() {
+        0 => String::new(),
+        1 => names[0].to_string(),
+        2 => format!("{} {} {}", names[0], and, names[1]),
+        count => format!(
+            "{}{} {} {}",
+            names[..count - 1].join(", "),
+            if oxford_comma { "," } else { "" },
+            and,
+            names[count - 1]
+        ),
+    }
+}
+
+/// `None` without authors. Roles without a translated prefix are written as
+/// "Role: A and B".
+pub fn byline(groups: &[AuthorRoleGroup], locale: &str, format: &BylineFormat, oxford_comma: bool) -> Option<String> {
+    if groups.is_empty() {
+        return None;
+    }
+
+    let language = language(locale);
+
+    let byline = match format {
+        BylineFormat::Full => groups
+            .iter()
+            .map(|group| {
+                let names = join_names(&names(&group.authors), language.and, oxford_comma);
+
+                match language.prefixes.iter().find(|(role, _)| *role == group.role) {
+                    Some((_, prefix)) => format!("{} {}", prefix, names),
+                    None => format!("{}: {}", capitalize(&group.role), names),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("; "),
+        BylineFormat::Names => {
+            let names = groups.iter().flat_map(|group| names(&group.authors)).collect::<Vec<_>>();
+
+            join_names(&names, language.and, oxford_comma)
+        }
+    };
+
+    Some(byline)
+}
+
+fn names(authors: &[Author]) -> Vec<&str> {
+    authors.iter().map(|author| author.author.name.as_str()).collect()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+impl AuthorRoleGroupFields for AuthorRoleGroup {
+    fn field_role(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.role)
+    }
+
+    fn field_authors(
+        &self,
+        executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Author, Walked>,
+    ) -> FieldResult<&Vec<Author>> {
+        executor.context().record(&self.authors);
+
+        Ok(&self.authors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syndication::fixtures::author;
+    use juniper_eager_loading::GraphqlNodeForModel;
+
+    fn authors(authors: &[(i32, &str, &str)]) -> Vec<Author> {
+        let models = authors
+            .iter()
+            .map(|(id, name, role)| author(*id, name, role, None))
+            .collect::<Vec<_>>();
+
+        Author::from_db_models(&models)
+    }
+
+    fn full_byline(authors: &[Author], locale: &str) -> Option<String> {
+        byline(&group_by_role(authors, &BylineRoles::default()), locale, &BylineFormat::Full, false)
+    }
+
+    #[test]
+    fn joins_names_with_and_without_the_oxford_comma() {
+        assert_eq!(join_names(&[], "and", false), "");
+        assert_eq!(join_names(&["A"], "and", true), "A");
+        assert_eq!(join_names(&["A", "B"], "and", true), "A and B");
+        assert_eq!(join_names(&["A", "B", "C"], "and", false), "A, B and C");
+        assert_eq!(join_names(&["A", "B", "C"], "and", true), "A, B, and C");
+    }
+
+    #[test]
+    fn writes_bylines_in_the_language_of_the_locale() {
+        let authors = authors(&[(1, "A", "writer"), (2, "B", "writer"), (3, "C", "photographer")]);
+
+        assert_eq!(full_byline(&authors, "en"), Some("By A and B; Photos by C".to_string()));
+        assert_eq!(full_byline(&authors, "de-AT"), Some("Von A und B; Fotos von C".to_string()));
+        assert_eq!(full_byline(&authors, "fr_CH"), Some("Par A et B; Photos de C".to_string()));
+        assert_eq!(full_byline(&authors, "es"), Some("Por A y B; Fotos de C".to_string()));
+        assert_eq!(full_byline(&authors, "nl"), Some("By A and B; Photos by C".to_string()));
+    }
+
+    #[test]
+    fn writes_roles_without_prefix_before_the_names() {
+        let authors = authors(&[(1, "A", "Cartographer"), (2, "B", "cartographer"), (3, "C", "")]);
+
+        assert_eq!(full_byline(&authors, "en"), Some("By C; Cartographer: A and B".to_string()));
+    }
+
+    #[test]
+    fn orders_groups_by_role_and_authors_by_id() {
+        let authors = authors(&[(3, "C", "photographer"), (2, "B", "writer"), (1, "A", "writer")]);
+        let roles = BylineRoles::new("photographer, writer");
+
+        let groups = group_by_role(&authors, &roles);
+        let names = groups
+            .iter()
+            .map(|group| (group.role.as_str(), names(&group.authors)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec![("photographer", vec!["C"]), ("writer", vec!["A", "B"])]);
+        assert_eq!(byline(&groups, "en", &BylineFormat::Names, true), Some("C, A, and B".to_string()));
+    }
+
+    #[test]
+    fn has_no_byline_without_authors() {
+        assert_eq!(full_byline(&[], "en"), None);
+    }
+}
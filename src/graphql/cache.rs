@@ -0,0 +1,627 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use juniper::InputValue;
+use rocket::{
+    request::{self, FromRequest, Request},
+    Outcome,
+};
+use sha2::{Digest, Sha256};
+use crate::changes::EventBus;
+use crate::tenant::Tenant;
+
+const DEFAULT_TTL: u64 = 60;
+const DEFAULT_CAPACITY: usize = 1000;
+const PREVIEW_HEADER: &str = "X-Publisher-Preview";
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Storage for cached responses. Implementations have to be safe to share
+/// between Rocket workers.
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+    /// Stores the value tagged with the surrogate keys of the response.
+    fn set(&self, key: &str, value: &str, ttl: Duration, surrogate_keys: &BTreeSet<String>);
+    /// Drops the values tagged with the surrogate key.
+    fn purge(&self, surrogate_key: &str);
+}
+
+/// In-memory backend evicting the least recently used entry once full.
+pub struct LruBackend {
+    state: Mutex<LruState>,
+}
+
+struct LruState {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, LruEntry>,
+    recency: BTreeMap<u64, String>,
+    /// Keys of the entries by surrogate key.
+    tagged: HashMap<String, HashSet<String>>,
+}
+
+struct LruEntry {
+    value: String,
+    expires_at: Instant,
+    last_used: u64,
+    surrogate_keys: BTreeSet<String>,
+}
+
+impl LruBackend {
+    pub fn new(capacity: usize) -> Self {
+        LruBackend {
+            state: Mutex::new(LruState {
+                capacity,
+                tick: 0,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tagged: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) -> u64 {
+        self.tick += 1;
+        let tick = self.tick;
+
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.recency.insert(tick, key.to_owned());
+        }
+
+        tick
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+
+            for surrogate_key in &entry.surrogate_keys {
+                let emptied = self.tagged.get_mut(surrogate_key).map_or(false, |keys| {
+                    keys.remove(key);
+                    keys.is_empty()
+                });
+                if emptied {
+                    self.tagged.remove(surrogate_key);
+                }
+            }
+        }
+    }
+}
+
+impl CacheBackend for LruBackend {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut state = self.state.lock().expect("response cache lock poisoned");
+
+        let expired = match state.entries.get(key) {
+            Some(entry) => entry.expires_at <= Instant::now(),
+            None => return None,
+        };
+
+        if expired {
+            state.remove(key);
+            return None;
+        }
+
+        state.touch(key);
+        state.entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration, surrogate_keys: &BTreeSet<String>) {
+        let mut state = self.state.lock().expect("response cache lock poisoned");
+
+        if state.capacity == 0 {
+            return;
+        }
+
+        state.remove(key);
+
+        while state.entries.len() >= state.capacity {
+            let oldest = state.recency.iter().next().map(|(_, key)| key.clone());
+            match oldest {
+                Some(oldest) => state.remove(&oldest),
+                None => break,
+            }
+        }
+
+        let tick = state.touch(key);
+        state.recency.insert(tick, key.to_owned());
+        for surrogate_key in surrogate_keys {
+            state.tagged.entry(surrogate_key.clone()).or_default().insert(key.to_owned());
+        }
+        state.entries.insert(key.to_owned(), LruEntry {
+            value: value.to_owned(),
+            expires_at: Instant::now() + ttl,
+            last_used: tick,
+            surrogate_keys: surrogate_keys.clone(),
+        });
+    }
+
+    fn purge(&self, surrogate_key: &str) {
+        let mut state = self.state.lock().expect("response cache lock poisoned");
+
+        for key in state.tagged.remove(surrogate_key).unwrap_or_default() {
+            state.remove(&key);
+        }
+    }
+}
+
+/// Shared backend talking the Redis protocol (RESP), so any Redis-compatible
+/// server can be used to share the cache between instances. Connections are
+/// taken from a pool of idle ones, so no lock is held while talking to the
+/// server. The keys of the entries are kept in a set per surrogate key,
+/// `graphql-tag:<surrogate key>`, which expires with the entries.
+pub struct RedisBackend {
+    address: String,
+    idle: Mutex<Vec<RedisConnection>>,
+}
+
+struct RedisConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+/// Reply to a command, errors are returned as `Err`.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Nil,
+    Value(Vec<u8>),
+    Array(Vec<Reply>),
+}
+
+impl RedisConnection {
+    fn open(address: &str) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(REDIS_TIMEOUT))?;
+        stream.set_write_timeout(Some(REDIS_TIMEOUT))?;
+
+        Ok(RedisConnection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn command(&mut self, args: &[&[u8]]) -> std::io::Result<Reply> {
+        send_command(&mut self.writer, args)?;
+
+        read_reply(&mut self.reader)
+    }
+}
+
+impl RedisBackend {
+    pub fn new(address: String) -> Self {
+        RedisBackend {
+            address,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs the commands on one connection, which is only returned to the pool
+    /// when all of them succeeded.
+    fn commands(&self, commands: &[&[&[u8]]]) -> std::io::Result<Vec<Reply>> {
+        let idle = self.idle.lock().expect("redis connections lock poisoned").pop();
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => RedisConnection::open(&self.address)?,
+        };
+
+        let replies = commands
+            .iter()
+            .map(|args| connection.command(args))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        self.idle.lock().expect("redis connections lock poisoned").push(connection);
+
+        Ok(replies)
+    }
+
+    fn command(&self, args: &[&[u8]]) -> std::io::Result<Reply> {
+        self.commands(&[args]).map(|mut replies| replies.remove(0))
+    }
+}
+
+fn tag_key(surrogate_key: &str) -> String {
+    format!("graphql-tag:{}", surrogate_key)
+}
+
+fn send_command(stream: &mut TcpStream, args: &[&[u8]]) -> std::io::Result<()> {
+    let mut buffer = format!("*{}\r\n", args.len()).into_bytes();
+
+    for arg in args {
+        buffer.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        buffer.extend_from_slice(arg);
+        buffer.extend_from_slice(b"\r\n");
+    }
+
+    stream.write_all(&buffer)
+}
+
+fn read_reply(reader: &mut impl BufRead) -> std::io::Result<Reply> {
+    use std::io::{Error, ErrorKind};
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let line = line.trim_end();
+    let length = || {
+        line[1..]
+            .parse::<i64>()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid redis reply length"))
+    };
+
+    match line.chars().next() {
+        Some('+') | Some(':') => Ok(Reply::Value(line[1..].as_bytes().to_vec())),
+        Some('-') => Err(Error::new(ErrorKind::Other, line[1..].to_owned())),
+        Some('$') => {
+            let length = length()?;
+            if length < 0 {
+                return Ok(Reply::Nil);
+            }
+
+            let mut value = vec![0; length as usize + 2];
+            reader.read_exact(&mut value)?;
+            value.truncate(length as usize);
+
+            Ok(Reply::Value(value))
+        }
+        Some('*') => {
+            let length = length()?;
+            if length < 0 {
+                return Ok(Reply::Nil);
+            }
+
+            (0..length).map(|_| read_reply(&mut *reader)).collect::<std::io::Result<_>>().map(Reply::Array)
+        }
+        _ => Err(Error::new(ErrorKind::InvalidData, "unsupported redis reply")),
+    }
+}
+
+impl CacheBackend for RedisBackend {
+    fn get(&self, key: &str) -> Option<String> {
+        match self.command(&[b"GET", key.as_bytes()]) {
+            Ok(Reply::Value(value)) => String::from_utf8(value).ok(),
+            _ => None,
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Duration, surrogate_keys: &BTreeSet<String>) {
+        let ttl = (ttl.as_millis() as u64).max(1).to_string();
+        let tag_keys = surrogate_keys.iter().map(|surrogate_key| tag_key(surrogate_key)).collect::<Vec<_>>();
+
+        let mut commands = vec![vec![&b"SET"[..], key.as_bytes(), value.as_bytes(), b"PX", ttl.as_bytes()]];
+        for tag_key in &tag_keys {
+            commands.push(vec![&b"SADD"[..], tag_key.as_bytes(), key.as_bytes()]);
+            commands.push(vec![&b"PEXPIRE"[..], tag_key.as_bytes(), ttl.as_bytes()]);
+        }
+
+        let _ = self.commands(&commands.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    }
+
+    fn purge(&self, surrogate_key: &str) {
+        let tag_key = tag_key(surrogate_key);
+
+        let keys = match self.command(&[b"SMEMBERS", tag_key.as_bytes()]) {
+            Ok(Reply::Array(keys)) => keys,
+            _ => return,
+        };
+
+        let mut args = vec![&b"DEL"[..], tag_key.as_bytes()];
+        args.extend(keys.iter().filter_map(|key| match key {
+            Reply::Value(key) => Some(key.as_slice()),
+            _ => None,
+        }));
+
+        let _ = self.command(&args);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+}
+
+/// Who is asking: responses are only shared between requests with the same
+/// scope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheScope {
+    pub tenant: String,
+    pub auth_scope: String,
+    pub preview: bool,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for CacheScope {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<CacheScope, ()> {
+        let tenant = request.guard::<Tenant>()?;
+        let headers = request.headers();
+
+        let auth_scope = match headers.get_one("Authorization") {
+            Some(authorization) => format!("{:x}", Sha256::digest(authorization.as_bytes())),
+            None => "public".to_string(),
+        };
+
+        let preview = headers.get_one(PREVIEW_HEADER).map_or(false, |value| value != "0" && value != "false")
+            || request.get_query_value::<bool>("preview").and_then(Result::ok).unwrap_or(false);
+
+        Outcome::Success(CacheScope {
+            tenant: tenant.code,
+            auth_scope,
+            preview,
+        })
+    }
+}
+
+pub struct ResponseCache {
+    backend: Option<Arc<dyn CacheBackend>>,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(backend: Option<Arc<dyn CacheBackend>>, ttl: Duration) -> Self {
+        ResponseCache { backend, ttl }
+    }
+
+    /// The cached responses are tagged with their surrogate keys and purged
+    /// on the change events touching them, as the CDN is.
+    pub fn spawn_from_env(bus: &EventBus) -> Self {
+        let ttl = std::env::var("PUBLISHER_RESPONSE_CACHE_TTL")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_TTL);
+
+        let capacity = std::env::var("PUBLISHER_RESPONSE_CACHE_CAPACITY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+
+        let backend: Option<Arc<dyn CacheBackend>> = match std::env::var("PUBLISHER_RESPONSE_CACHE").as_ref().map(String::as_str) {
+            Ok("disabled") => None,
+            Ok("redis") => {
+                let address = std::env::var("PUBLISHER_RESPONSE_CACHE_REDIS_ADDRESS")
+                    .expect("PUBLISHER_RESPONSE_CACHE_REDIS_ADDRESS must be set");
+                Some(Arc::new(RedisBackend::new(address)))
+            }
+            _ => Some(Arc::new(LruBackend::new(capacity))),
+        };
+
+        if let Some(backend) = backend.clone() {
+            let events = bus.subscribe();
+            thread::Builder::new()
+                .name("response-cache-purger".to_string())
+                .spawn(move || {
+                    for event in events {
+                        for surrogate_key in event.surrogate_keys() {
+                            backend.purge(&surrogate_key);
+                        }
+                    }
+                })
+                .expect("failed to spawn response cache purger thread");
+        }
+
+        ResponseCache::new(backend, Duration::from_secs(ttl))
+    }
+
+    /// Returns the cache key, or `None` when the request must not be cached.
+    pub fn key(
+        &self,
+        query: &str,
+        operation_name: Option<&String>,
+        variables: Option<&InputValue>,
+        scope: &CacheScope,
+    ) -> Option<String> {
+        if self.backend.is_none() || scope.preview || is_mutation(query, operation_name) {
+            return None;
+        }
+
+        let variables = variables
+            .map(|variables| serde_json::to_string(variables).unwrap_or_default())
+            .unwrap_or_default();
+
+        let mut hasher = Sha256::new();
+        hasher.input(normalize_query(query).as_bytes());
+        hasher.input(b"\0");
+        hasher.input(operation_name.map(String::as_str).unwrap_or("").as_bytes());
+        hasher.input(b"\0");
+        hasher.input(variables.as_bytes());
+        hasher.input(b"\0");
+        hasher.input(scope.tenant.as_bytes());
+        hasher.input(b"\0");
+        hasher.input(scope.auth_scope.as_bytes());
+
+        Some(format!("graphql:{:x}", hasher.result()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.backend.as_ref().and_then(|backend| backend.get(key))
+    }
+
+    pub fn set(&self, key: &str, response: &str, surrogate_keys: &BTreeSet<String>) {
+        if let Some(backend) = &self.backend {
+            backend.set(key, response, self.ttl, surrogate_keys);
+        }
+    }
+}
+
+/// Collapses insignificant whitespace and strips comments, so formatting
+/// differences between clients do not produce different cache entries.
+pub fn normalize_query(query: &str) -> String {
+    let mut normalized = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                if pending_space && !normalized.is_empty() {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+                normalized.push(c);
+
+                while let Some(c) = chars.next() {
+                    normalized.push(c);
+                    match c {
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                normalized.push(escaped);
+                            }
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '#' => {
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+                pending_space = true;
+            }
+            c if c.is_whitespace() || c == ',' => pending_space = true,
+            c => {
+                let is_punctuator = "{}()[]:=!$@|&".contains(c);
+                let follows_punctuator = normalized
+                    .chars()
+                    .last()
+                    .map_or(true, |last| "{}()[]:=!$@|&".contains(last));
+
+                if pending_space && !is_punctuator && !follows_punctuator {
+                    normalized.push(' ');
+                }
+                pending_space = false;
+                normalized.push(c);
+            }
+        }
+    }
+
+    normalized
+}
+
+/// Checks whether the operation that is going to be executed is a mutation.
+pub fn is_mutation(query: &str, operation_name: Option<&String>) -> bool {
+    let normalized = normalize_query(query);
+    let mut depth = 0;
+    let mut header = String::new();
+
+    for c in normalized.chars() {
+        match c {
+            '{' => {
+                if depth == 0 {
+                    let mut words = header.split(|c: char| !(c.is_alphanumeric() || c == '_')).filter(|w| !w.is_empty());
+                    let kind = words.next();
+                    let name = words.next();
+                    let selected = operation_name.map_or(true, |operation_name| name == Some(operation_name.as_str()));
+
+                    if kind == Some("mutation") && selected {
+                        return true;
+                    }
+                    header.clear();
+                }
+                depth += 1;
+            }
+            '}' => depth -= 1,
+            c if depth == 0 => header.push(c),
+            _ => {}
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> BTreeSet<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn normalizes_whitespace_commas_and_comments() {
+        let query = "
+            # the latest articles
+            query Latest($first: Int) {
+              articles(first: $first, route: 1) { edges { node { id, title } } }
+            }
+        ";
+
+        assert_eq!(
+            normalize_query(query),
+            "query Latest($first:Int){articles(first:$first route:1){edges{node{id title}}}}"
+        );
+        assert_eq!(normalize_query("{ a  b }"), normalize_query("{a,\n b}"));
+    }
+
+    #[test]
+    fn keeps_strings_as_they_are() {
+        assert_eq!(
+            normalize_query(r#"{ search(text: "a,  b # \" c") { id } }"#),
+            r#"{search(text: "a,  b # \" c"){id}}"#
+        );
+    }
+
+    #[test]
+    fn detects_mutations() {
+        assert!(is_mutation("mutation { recordPageView(articleId: 1) }", None));
+        assert!(!is_mutation("{ articles { totalCount } }", None));
+        assert!(!is_mutation("query { articles { totalCount } }", None));
+        assert!(!is_mutation(r#"{ search(text: "mutation {") { id } }"#, None));
+    }
+
+    #[test]
+    fn detects_the_selected_mutation_operation() {
+        let document = "query Articles { articles { totalCount } } mutation Record { recordPageView(articleId: 1) }";
+
+        assert!(is_mutation(document, Some(&"Record".to_string())));
+        assert!(!is_mutation(document, Some(&"Articles".to_string())));
+        assert!(is_mutation(document, None));
+    }
+
+    #[test]
+    fn purges_the_entries_tagged_with_a_surrogate_key() {
+        let backend = LruBackend::new(10);
+        let ttl = Duration::from_secs(60);
+        backend.set("list", "[1, 2]", ttl, &keys(&["articles", "article-1", "article-2"]));
+        backend.set("article", "1", ttl, &keys(&["article-1"]));
+        backend.set("route", "news", ttl, &keys(&["route-1"]));
+
+        backend.purge("article-1");
+
+        assert_eq!(backend.get("list"), None);
+        assert_eq!(backend.get("article"), None);
+        assert_eq!(backend.get("route"), Some("news".to_string()));
+        assert!(backend.state.lock().unwrap().tagged.get("articles").is_none());
+    }
+
+    #[test]
+    fn reads_redis_replies() {
+        let mut reply = &b"*3\r\n$3\r\nkey\r\n$-1\r\n:1\r\n+OK\r\n"[..];
+
+        assert_eq!(
+            read_reply(&mut reply).unwrap(),
+            Reply::Array(vec![Reply::Value(b"key".to_vec()), Reply::Nil, Reply::Value(b"1".to_vec())])
+        );
+        assert_eq!(read_reply(&mut reply).unwrap(), Reply::Value(b"OK".to_vec()));
+        assert!(read_reply(&mut &b"-ERR wrong type\r\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use graphql_parser::query::{self, Definition, OperationDefinition, Selection, SelectionSet, TypeCondition};
+use graphql_parser::schema::{self, TypeDefinition};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CacheControlScope {
+    Public,
+    Private,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheControlPolicy {
+    pub max_age: u32,
+    pub scope: CacheControlScope,
+}
+
+impl CacheControlPolicy {
+    pub fn no_store() -> Self {
+        CacheControlPolicy { max_age: 0, scope: CacheControlScope::Private }
+    }
+
+    fn restrict(&mut self, hint: &CacheHint) {
+        if let Some(max_age) = hint.max_age {
+            self.max_age = self.max_age.min(max_age);
+        }
+
+        if hint.scope == Some(CacheControlScope::Private) {
+            self.scope = CacheControlScope::Private;
+        }
+    }
+
+    /// Value of the `Cache-Control` header.
+    pub fn header_value(&self) -> String {
+        let scope = match self.scope {
+            CacheControlScope::Public => "public",
+            CacheControlScope::Private => "private",
+        };
+
+        if self.max_age == 0 {
+            format!("{}, no-cache", scope)
+        } else {
+            format!("{}, max-age={}", scope, self.max_age)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct CacheHint {
+    max_age: Option<u32>,
+    scope: Option<CacheControlScope>,
+}
+
+#[derive(Debug, Clone)]
+struct FieldInfo {
+    type_name: String,
+    hint: Option<CacheHint>,
+}
+
+/// Cache hints of the types and fields of `schema.graphql`, used to compute
+/// the cache policy of a query from the fields it selects. The hints are kept
+/// in `cache_control.json`, keyed by type (`Article`) or field
+/// (`Query.articles`), as juniper-from-schema rejects unknown directives.
+pub struct CacheControl {
+    types: HashMap<String, Option<CacheHint>>,
+    fields: HashMap<String, HashMap<String, FieldInfo>>,
+    query_type: String,
+    default_max_age: u32,
+}
+
+impl CacheControl {
+    /// Panics when the hints aren't valid or name a type or field the schema
+    /// doesn't have.
+    pub fn new(schema_source: &str, hints_source: &str, default_max_age: u32) -> Self {
+        let document = schema::parse_schema(schema_source).expect("invalid schema.graphql");
+        let mut hints: HashMap<String, CacheHint> =
+            serde_json::from_str(hints_source).expect("invalid cache_control.json");
+        let mut types = HashMap::new();
+        let mut fields = HashMap::new();
+        let mut query_type = "Query".to_string();
+
+        for definition in document.definitions {
+            match definition {
+                schema::Definition::SchemaDefinition(schema_definition) => {
+                    if let Some(query) = schema_definition.query {
+                        query_type = query;
+                    }
+                }
+                schema::Definition::TypeDefinition(TypeDefinition::Object(object)) => {
+                    let object_fields = object
+                        .fields
+                        .iter()
+                        .map(|field| {
+                            (field.name.clone(), FieldInfo {
+                                type_name: named_type(&field.field_type).to_owned(),
+                                hint: hints.remove(&format!("{}.{}", object.name, field.name)),
+                            })
+                        })
+                        .collect();
+
+                    types.insert(object.name.clone(), hints.remove(&object.name));
+                    fields.insert(object.name, object_fields);
+                }
+                schema::Definition::TypeDefinition(TypeDefinition::Union(union)) => {
+                    types.insert(union.name.clone(), hints.remove(&union.name));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(name) = hints.keys().next() {
+            panic!("cache_control.json has hints of {}, which isn't in schema.graphql", name);
+        }
+
+        CacheControl { types, fields, query_type, default_max_age }
+    }
+
+    pub fn from_env() -> Self {
+        let default_max_age = std::env::var("PUBLISHER_CACHE_CONTROL_DEFAULT_MAX_AGE")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        CacheControl::new(
+            include_str!("../../schema.graphql"),
+            include_str!("../../cache_control.json"),
+            default_max_age,
+        )
+    }
+
+    /// Computes the policy for the operation that is going to be executed:
+    /// the lowest `maxAge` of all selected fields, private if any of them is.
+    pub fn policy(&self, query_source: &str, operation_name: Option<&String>) -> CacheControlPolicy {
+        let document = match query::parse_query(query_source) {
+            Ok(document) => document,
+            Err(_) => return CacheControlPolicy::no_store(),
+        };
+
+        let fragments: HashMap<&str, &query::FragmentDefinition> = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+                _ => None,
+            })
+            .collect();
+
+        let selection_set = document.definitions.iter().find_map(|definition| match definition {
+            Definition::Operation(OperationDefinition::SelectionSet(selection_set)) => Some(Ok(selection_set)),
+            Definition::Operation(OperationDefinition::Query(query)) => {
+                let selected = operation_name.map_or(true, |name| query.name.as_ref() == Some(name));
+                if selected { Some(Ok(&query.selection_set)) } else { None }
+            }
+            Definition::Operation(OperationDefinition::Mutation(mutation)) => {
+                let selected = operation_name.map_or(true, |name| mutation.name.as_ref() == Some(name));
+                if selected { Some(Err(())) } else { None }
+            }
+            Definition::Operation(OperationDefinition::Subscription(_)) => Some(Err(())),
+            Definition::Fragment(_) => None,
+        });
+
+        let selection_set = match selection_set {
+            Some(Ok(selection_set)) => selection_set,
+            _ => return CacheControlPolicy::no_store(),
+        };
+
+        let mut policy = CacheControlPolicy { max_age: u32::max_value(), scope: CacheControlScope::Public };
+        self.walk(&self.query_type, selection_set, true, &fragments, &mut policy);
+
+        if policy.max_age == u32::max_value() {
+            policy.max_age = self.default_max_age;
+        }
+
+        policy
+    }
+
+    fn walk(
+        &self,
+        type_name: &str,
+        selection_set: &SelectionSet,
+        is_root: bool,
+        fragments: &HashMap<&str, &query::FragmentDefinition>,
+        policy: &mut CacheControlPolicy,
+    ) {
+        for selection in &selection_set.items {
+            match selection {
+                Selection::Field(field) => {
+                    if field.name.starts_with("__") {
+                        continue;
+                    }
+
+                    let info = match self.fields.get(type_name).and_then(|fields| fields.get(&field.name)) {
+                        Some(info) => info,
+                        None => continue,
+                    };
+
+                    let type_hint = self.types.get(&info.type_name).cloned().unwrap_or(None);
+                    let is_composite = self.types.contains_key(&info.type_name);
+
+                    match info.hint.or(type_hint) {
+                        Some(hint) => policy.restrict(&hint),
+                        None if is_root || is_composite => policy.restrict(&CacheHint {
+                            max_age: Some(self.default_max_age),
+                            scope: None,
+                        }),
+                        None => {}
+                    }
+
+                    if is_composite {
+                        self.walk(&info.type_name, &field.selection_set, false, fragments, policy);
+                    }
+                }
+                Selection::FragmentSpread(spread) => {
+                    if let Some(fragment) = fragments.get(spread.fragment_name.as_str()) {
+                        let TypeCondition::On(ref on) = fragment.type_condition;
+                        self.walk(on, &fragment.selection_set, is_root, fragments, policy);
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    let on = match inline.type_condition {
+                        Some(TypeCondition::On(ref on)) => on.as_str(),
+                        None => type_name,
+                    };
+                    self.walk(on, &inline.selection_set, is_root, fragments, policy);
+                }
+            }
+        }
+    }
+}
+
+fn named_type(field_type: &schema::Type) -> &str {
+    match field_type {
+        schema::Type::NamedType(name) => name,
+        schema::Type::ListType(inner) | schema::Type::NonNullType(inner) => named_type(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_control() -> CacheControl {
+        CacheControl::new(include_str!("../../schema.graphql"), include_str!("../../cache_control.json"), 0)
+    }
+
+    #[test]
+    fn hints_name_types_and_fields_of_the_schema() {
+        cache_control();
+    }
+
+    #[test]
+    fn takes_the_lowest_max_age_of_the_selected_fields() {
+        let policy = cache_control().policy("{ articles { edges { node { id title } } } }", None);
+
+        assert_eq!(policy, CacheControlPolicy { max_age: 60, scope: CacheControlScope::Public });
+        assert_eq!(policy.header_value(), "public, max-age=60");
+    }
+
+    #[test]
+    fn private_fields_make_the_response_private() {
+        let policy = cache_control().policy("{ apiVersion analytics(groupBy: ROUTE) { views } }", None);
+
+        assert_eq!(policy.scope, CacheControlScope::Private);
+    }
+
+    #[test]
+    #[should_panic(expected = "Query.unknown")]
+    fn rejects_hints_of_unknown_fields() {
+        CacheControl::new(include_str!("../../schema.graphql"), r#"{ "Query.unknown": { "maxAge": 60 } }"#, 0);
+    }
+}
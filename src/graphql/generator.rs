@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use chrono::prelude::*;
+
 pub fn generate_asset_url(asset_id: &String, file_extension: &String) -> String {
     let cdn_url = std::env::var("PUBLISHER_CDN_URL").expect("PUBLISHER_CDN_URL must be set");
 
@@ -23,3 +26,247 @@ fn generate_url(mut cdn_url: String, asset_id: &String, file_extension: &String)
 
     url
 }
+
+const DEFAULT_ARTICLE_URL_PATTERN: &str = "/{route_path}/{article_slug}";
+const DEFAULT_ROUTE_URL_PATTERN: &str = "/{route_path}";
+const DEFAULT_AUTHOR_URL_PATTERN: &str = "/author/{author_slug}";
+const DEFAULT_API_URL: &str = "http://localhost:8000";
+
+/// Builds the public URLs of articles, routes and authors on a tenant's
+/// website, and of this API (`PUBLISHER_CONTENT_API_URL`).
+///
+/// Patterns are paths with placeholders: `{route_path}`, `{article_slug}`
+/// (or `{slug}`), `{id}`, `{yyyy}`, `{mm}` and `{dd}` (publishing date) for
+/// articles and `{author_slug}` for authors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlBuilder {
+    base_url: String,
+    canonical_base_url: String,
+    article_pattern: String,
+    route_pattern: String,
+    author_pattern: String,
+    api_url: String,
+}
+
+/// URL builders of the tenants, configured once at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlBuilders {
+    default: UrlBuilder,
+    tenants: HashMap<String, UrlBuilder>,
+}
+
+impl UrlBuilders {
+    /// The base URLs are taken from `PUBLISHER_TENANT_BASE_URLS` (`tenant=url`
+    /// pairs separated by commas), `PUBLISHER_BASE_URL` for the other
+    /// tenants. The canonical base URLs likewise from
+    /// `PUBLISHER_TENANT_CANONICAL_BASE_URLS` and `PUBLISHER_CANONICAL_BASE_URL`,
+    /// a tenant's base URL when neither names it. Fails when
+    /// `PUBLISHER_BASE_URL` is missing or a URL is invalid.
+    pub fn from_env() -> Result<Self, String> {
+        let default_base_url = std::env::var("PUBLISHER_BASE_URL")
+            .map_err(|_| "PUBLISHER_BASE_URL must be set".to_string())?;
+        let default_canonical_base_url = std::env::var("PUBLISHER_CANONICAL_BASE_URL").ok();
+        let base_urls = tenant_urls_from_env("PUBLISHER_TENANT_BASE_URLS")?;
+        let canonical_base_urls = tenant_urls_from_env("PUBLISHER_TENANT_CANONICAL_BASE_URLS")?;
+
+        let mut tenants = HashMap::new();
+        for code in base_urls.keys().chain(canonical_base_urls.keys()) {
+            let base_url = base_urls.get(code).unwrap_or(&default_base_url);
+            let canonical_base_url = canonical_base_urls.get(code).map(String::as_str);
+            tenants.insert(code.clone(), UrlBuilder::new(base_url, canonical_base_url)?);
+        }
+
+        Ok(UrlBuilders {
+            default: UrlBuilder::new(&default_base_url, default_canonical_base_url.as_ref().map(String::as_str))?,
+            tenants,
+        })
+    }
+
+    pub fn for_tenant(&self, tenant: &str) -> UrlBuilder {
+        self.tenants.get(&tenant.to_lowercase()).unwrap_or(&self.default).clone()
+    }
+}
+
+impl UrlBuilder {
+    /// The canonical base URL is the base URL unless given.
+    pub fn new(base_url: &str, canonical_base_url: Option<&str>) -> Result<Self, String> {
+        let canonical_base_url = canonical_base_url.unwrap_or(base_url).to_string();
+        let api_url = std::env::var("PUBLISHER_CONTENT_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string());
+
+        for url in [base_url, canonical_base_url.as_str(), api_url.as_str()].iter() {
+            url::Url::parse(url).map_err(|error| format!("invalid base URL {:?}: {}", url, error))?;
+        }
+
+        Ok(UrlBuilder {
+            base_url: trim_base_url(base_url.to_string()),
+            canonical_base_url: trim_base_url(canonical_base_url),
+            article_pattern: pattern_from_env("PUBLISHER_ARTICLE_URL_PATTERN", DEFAULT_ARTICLE_URL_PATTERN),
+            route_pattern: pattern_from_env("PUBLISHER_ROUTE_URL_PATTERN", DEFAULT_ROUTE_URL_PATTERN),
+            author_pattern: pattern_from_env("PUBLISHER_AUTHOR_URL_PATTERN", DEFAULT_AUTHOR_URL_PATTERN),
+            api_url: trim_base_url(api_url),
+        })
+    }
+
+    /// URL of this API, e.g. of the embed cards or the AMP pages.
+    pub fn api_url(&self, path: &str) -> String {
+        join_url(&self.api_url, path)
+    }
+
+    pub fn site_url(&self, path: &str) -> String {
+        join_url(&self.base_url, path)
+    }
+
+    pub fn article_url(&self, route_path: &str, article_slug: &str, id: i32, published_at: Option<NaiveDateTime>) -> String {
+        join_url(&self.base_url, &self.article_path(route_path, article_slug, id, published_at))
+    }
+
+    /// Article URL on the tenant's canonical website, the same as
+    /// `article_url` unless content is syndicated between tenants.
+    pub fn canonical_article_url(
+        &self,
+        route_path: &str,
+        article_slug: &str,
+        id: i32,
+        published_at: Option<NaiveDateTime>,
+    ) -> String {
+        join_url(&self.canonical_base_url, &self.article_path(route_path, article_slug, id, published_at))
+    }
+
+    pub fn route_url(&self, route_path: &str) -> String {
+        join_url(&self.base_url, &self.route_path(route_path))
+    }
+
+    pub fn author_url(&self, author_slug: &str) -> String {
+        join_url(&self.base_url, &self.author_pattern.replace("{author_slug}", author_slug))
+    }
+
+    /// Path of the route on the website, without the base URL.
+    pub fn route_path(&self, route_path: &str) -> String {
+        self.route_pattern.replace("{route_path}", route_path.trim_matches('/'))
+    }
+
+    /// Path of the article on the website, without the base URL. The date
+    /// segments are left out of the paths of unpublished articles.
+    pub fn article_path(&self, route_path: &str, article_slug: &str, id: i32, published_at: Option<NaiveDateTime>) -> String {
+        let (year, month, day) = match published_at {
+            Some(date) => (format!("{:04}", date.year()), format!("{:02}", date.month()), format!("{:02}", date.day())),
+            None => (String::new(), String::new(), String::new()),
+        };
+
+        let path = self
+            .article_pattern
+            .replace("{route_path}", route_path.trim_matches('/'))
+            .replace("{article_slug}", article_slug)
+            .replace("{slug}", article_slug)
+            .replace("{id}", &id.to_string())
+            .replace("{yyyy}", &year)
+            .replace("{mm}", &month)
+            .replace("{dd}", &day);
+
+        collapse_slashes(&path)
+    }
+}
+
+/// URLs by tenant code of `tenant=url` pairs separated by commas.
+fn tenant_urls_from_env(name: &str) -> Result<HashMap<String, String>, String> {
+    let pairs = std::env::var(name).unwrap_or_default();
+
+    let mut urls = HashMap::new();
+    for pair in pairs.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(code), Some(url)) if !code.trim().is_empty() => {
+                urls.insert(code.trim().to_lowercase(), url.trim().to_string());
+            }
+            _ => return Err(format!("invalid {} pair {:?}", name, pair)),
+        }
+    }
+
+    Ok(urls)
+}
+
+fn pattern_from_env(name: &str, default: &str) -> String {
+    std::env::var(name)
+        .ok()
+        .filter(|pattern| !pattern.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+fn trim_base_url(mut base_url: String) -> String {
+    while base_url.ends_with('/') {
+        base_url.pop();
+    }
+
+    base_url
+}
+
+fn collapse_slashes(path: &str) -> String {
+    let mut collapsed = String::with_capacity(path.len());
+    for c in path.chars() {
+        if !(c == '/' && collapsed.ends_with('/')) {
+            collapsed.push(c);
+        }
+    }
+
+    collapsed
+}
+
+fn join_url(base_url: &str, path: &str) -> String {
+    let mut url = base_url.to_string();
+    if !path.starts_with('/') {
+        url.push_str("/");
+    }
+    url.push_str(path);
+
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(article_pattern: &str) -> UrlBuilder {
+        UrlBuilder {
+            base_url: "https://www.example.com".to_string(),
+            canonical_base_url: "https://www.example.org".to_string(),
+            article_pattern: article_pattern.to_string(),
+            route_pattern: DEFAULT_ROUTE_URL_PATTERN.to_string(),
+            author_pattern: DEFAULT_AUTHOR_URL_PATTERN.to_string(),
+            api_url: DEFAULT_API_URL.to_string(),
+        }
+    }
+
+    fn published_at() -> Option<NaiveDateTime> {
+        Some(NaiveDate::from_ymd(2019, 5, 1).and_hms(12, 0, 0))
+    }
+
+    #[test]
+    fn replaces_the_placeholders_of_article_paths() {
+        let urls = urls("/{route_path}/{yyyy}/{mm}/{dd}/{article_slug}-{id}");
+
+        assert_eq!(urls.article_path("/news/local/", "storm", 42, published_at()), "/news/local/2019/05/01/storm-42");
+        assert_eq!(urls("/{slug}").article_path("news", "storm", 42, None), "/storm");
+    }
+
+    #[test]
+    fn leaves_the_date_out_of_paths_of_unpublished_articles() {
+        let urls = urls("/{route_path}/{yyyy}/{mm}/{dd}/{article_slug}");
+
+        assert_eq!(urls.article_path("news", "storm", 42, None), "/news/storm");
+    }
+
+    #[test]
+    fn joins_article_paths_to_the_base_urls() {
+        let urls = urls(DEFAULT_ARTICLE_URL_PATTERN);
+
+        assert_eq!(urls.article_url("news", "storm", 42, None), "https://www.example.com/news/storm");
+        assert_eq!(urls.canonical_article_url("news", "storm", 42, None), "https://www.example.org/news/storm");
+    }
+
+    #[test]
+    fn collapses_slashes() {
+        assert_eq!(collapse_slashes("//news///storm/"), "/news/storm/");
+        assert_eq!(collapse_slashes("/news/storm"), "/news/storm");
+        assert_eq!(collapse_slashes(""), "");
+    }
+}
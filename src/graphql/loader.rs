@@ -0,0 +1,290 @@
+//! Per-request loader of the models, kept in the `Context`.
+//!
+//! Eager loading only follows the associations walked from the root of the
+//! query, so computed fields (URLs, SEO, structured data, ...) and fields
+//! reached through unions load the models they need through the loader. It
+//! is an identity map: rows are cached by primary key, and by foreign key for
+//! the associations, so each is fetched at most once per request. Loads are
+//! batched by deferring keys (e.g. the routes of a page of articles), which
+//! are fetched along with the next load of the same model or foreign key.
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use diesel::prelude::*;
+use crate::models::{
+    Article, ArticleAuthor, ArticleKeyword, ArticleMedia, ArticleSeoMedia, ArticleSeoMetadata, ArticleSource, Author,
+    AuthorAvatar, Image, ImageRendition, Keyword, RelatedArticle, Route, Slideshow, SlideshowItem, Source, Statistics,
+};
+use crate::schema::{
+    swp_article, swp_article_author, swp_article_keyword, swp_article_media, swp_article_related,
+    swp_article_seo_media, swp_article_seo_metadata, swp_article_source, swp_article_sources, swp_article_statistics,
+    swp_author, swp_author_media, swp_image, swp_image_rendition, swp_keyword, swp_route, swp_slideshow,
+    swp_slideshow_item,
+};
+
+/// Model loaded by its `id`.
+pub trait Loadable: Clone + Send + 'static {
+    fn id(&self) -> i32;
+
+    fn load(ids: &[i32], conn: &PgConnection) -> QueryResult<Vec<Self>>;
+}
+
+/// Foreign key column of a model, e.g. `swp_article_media::article_id`.
+pub trait ForeignKey: Copy + 'static {
+    type Model: Clone + Send + 'static;
+
+    fn key(model: &Self::Model) -> i32;
+
+    fn load(keys: &[i32], conn: &PgConnection) -> QueryResult<Vec<Self::Model>>;
+
+    /// Called with the rows loaded by foreign key, which are cached by id
+    /// (or, for join tables, whose other side is deferred).
+    fn prime(_loader: &Loader, _models: &[Self::Model]) {}
+}
+
+macro_rules! loadable {
+    ($($model:ident => $table:ident,)*) => {
+        $(
+            impl Loadable for $model {
+                fn id(&self) -> i32 {
+                    self.id
+                }
+
+                fn load(ids: &[i32], conn: &PgConnection) -> QueryResult<Vec<Self>> {
+                    $table::table.filter($table::id.eq_any(ids.to_vec())).load(conn)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! foreign_keys {
+    (@impl $table:ident :: $column:ident => $model:ident { $($prime:tt)* }) => {
+        impl ForeignKey for $table::$column {
+            type Model = $model;
+
+            fn key(model: &$model) -> i32 {
+                model.$column
+            }
+
+            fn load(keys: &[i32], conn: &PgConnection) -> QueryResult<Vec<$model>> {
+                $table::table.filter($table::$column.eq_any(keys.to_vec())).load(conn)
+            }
+
+            $($prime)*
+        }
+    };
+    (
+        rows: { $($table:ident :: $column:ident => $model:ident,)* }
+        joins: { $($join_table:ident :: $join_column:ident => $join_model:ident ($other:ident -> $other_model:ident),)* }
+    ) => {
+        $(
+            foreign_keys!(@impl $table::$column => $model {
+                fn prime(loader: &Loader, models: &[$model]) {
+                    loader.prime(models);
+                }
+            });
+        )*
+        $(
+            foreign_keys!(@impl $join_table::$join_column => $join_model {
+                fn prime(loader: &Loader, models: &[$join_model]) {
+                    loader.defer::<$other_model>(models.iter().map(|model| model.$other));
+                }
+            });
+        )*
+    };
+}
+
+loadable! {
+    Article => swp_article,
+    Route => swp_route,
+    Statistics => swp_article_statistics,
+    ArticleMedia => swp_article_media,
+    Image => swp_image,
+    ImageRendition => swp_image_rendition,
+    Author => swp_author,
+    AuthorAvatar => swp_author_media,
+    Keyword => swp_keyword,
+    ArticleSeoMetadata => swp_article_seo_metadata,
+    ArticleSeoMedia => swp_article_seo_media,
+    RelatedArticle => swp_article_related,
+    Source => swp_article_source,
+    Slideshow => swp_slideshow,
+    SlideshowItem => swp_slideshow_item,
+}
+
+foreign_keys! {
+    rows: {
+        swp_article_statistics::article_id => Statistics,
+        swp_article_media::article_id => ArticleMedia,
+        swp_image_rendition::media_id => ImageRendition,
+        swp_article_related::article_id => RelatedArticle,
+        swp_article_related::relates_to_id => RelatedArticle,
+        swp_slideshow::article_id => Slideshow,
+        swp_slideshow_item::slideshow_id => SlideshowItem,
+    }
+    joins: {
+        swp_article_author::article_id => ArticleAuthor (author_id -> Author),
+        swp_article_author::author_id => ArticleAuthor (article_id -> Article),
+        swp_article_keyword::article_id => ArticleKeyword (keyword_id -> Keyword),
+        swp_article_keyword::keyword_id => ArticleKeyword (article_id -> Article),
+        swp_article_sources::article_id => ArticleSource (source_id -> Source),
+        swp_article_sources::source_id => ArticleSource (article_id -> Article),
+    }
+}
+
+// the images of the avatars are fetched together with the first of them
+foreign_keys!(@impl swp_author_media::author_id => AuthorAvatar {
+    fn prime(loader: &Loader, models: &[AuthorAvatar]) {
+        loader.prime(models);
+        loader.defer::<Image>(models.iter().map(|model| model.image_id));
+    }
+});
+
+/// Loaded values by key, `None` or an empty list for keys without rows.
+struct Cache<V> {
+    values: HashMap<i32, V>,
+    deferred: BTreeSet<i32>,
+}
+
+impl<V> Default for Cache<V> {
+    fn default() -> Self {
+        Cache {
+            values: HashMap::new(),
+            deferred: BTreeSet::new(),
+        }
+    }
+}
+
+/// Caches keyed by the model (loads by id) or the column (loads by foreign
+/// key).
+#[derive(Default)]
+pub struct Loader {
+    caches: Mutex<HashMap<TypeId, Box<dyn Any + Send>>>,
+}
+
+impl Loader {
+    pub fn load<T: Loadable>(&self, id: i32, conn: &PgConnection) -> QueryResult<Option<T>> {
+        Ok(self.fetch(TypeId::of::<T>(), &[id], |ids| load_by_id::<T>(ids, conn))?.pop().and_then(|row| row))
+    }
+
+    /// Rows in the order of `ids`, without the ids which don't exist.
+    pub fn load_many<T: Loadable>(&self, ids: &[i32], conn: &PgConnection) -> QueryResult<Vec<T>> {
+        Ok(self
+            .fetch(TypeId::of::<T>(), ids, |ids| load_by_id::<T>(ids, conn))?
+            .into_iter()
+            .filter_map(|row| row)
+            .collect())
+    }
+
+    /// Rows whose `column` equals `key`.
+    pub fn load_by<K: ForeignKey>(&self, column: K, key: i32, conn: &PgConnection) -> QueryResult<Vec<K::Model>> {
+        Ok(self.load_many_by(column, &[key], conn)?.pop().unwrap_or_default())
+    }
+
+    /// Rows of each of the `keys`.
+    pub fn load_many_by<K: ForeignKey>(
+        &self,
+        _column: K,
+        keys: &[i32],
+        conn: &PgConnection,
+    ) -> QueryResult<Vec<Vec<K::Model>>> {
+        self.fetch(TypeId::of::<K>(), keys, |keys| {
+            let rows = K::load(keys, conn)?;
+            K::prime(self, &rows);
+
+            let mut grouped: HashMap<i32, Vec<K::Model>> = HashMap::new();
+            for row in rows {
+                grouped.entry(K::key(&row)).or_insert_with(Vec::new).push(row);
+            }
+
+            Ok(grouped)
+        })
+    }
+
+    /// Ids to fetch with the next load of `T`.
+    pub fn defer<T: Loadable>(&self, ids: impl IntoIterator<Item = i32>) {
+        self.with_cache(TypeId::of::<T>(), |cache: &mut Cache<Option<T>>| defer(cache, ids));
+    }
+
+    /// Keys to fetch with the next load by `column`.
+    pub fn defer_by<K: ForeignKey>(&self, _column: K, keys: impl IntoIterator<Item = i32>) {
+        self.with_cache(TypeId::of::<K>(), |cache: &mut Cache<Vec<K::Model>>| defer(cache, keys));
+    }
+
+    /// Caches rows loaded elsewhere, e.g. the articles of a connection.
+    pub fn prime<T: Loadable>(&self, models: &[T]) {
+        self.with_cache(TypeId::of::<T>(), |cache: &mut Cache<Option<T>>| {
+            for model in models {
+                cache.deferred.remove(&model.id());
+                cache.values.insert(model.id(), Some(model.clone()));
+            }
+        });
+    }
+
+    /// Forgets the cached rows, for contexts living longer than a request.
+    pub fn clear(&self) {
+        self.caches.lock().expect("loader lock poisoned").clear();
+    }
+
+    /// Cached values of `keys`, the missing ones are fetched along with the
+    /// deferred keys. The lock is not held while querying, so `fetch` can
+    /// prime other caches.
+    fn fetch<V, F>(&self, type_id: TypeId, keys: &[i32], fetch: F) -> QueryResult<Vec<V>>
+    where
+        V: Clone + Default + Send + 'static,
+        F: FnOnce(&[i32]) -> QueryResult<HashMap<i32, V>>,
+    {
+        let missing = self.with_cache(type_id, |cache: &mut Cache<V>| {
+            let mut missing = keys
+                .iter()
+                .filter(|key| !cache.values.contains_key(key))
+                .cloned()
+                .collect::<BTreeSet<_>>();
+
+            if !missing.is_empty() {
+                missing.append(&mut cache.deferred);
+            }
+
+            missing.into_iter().collect::<Vec<_>>()
+        });
+
+        if !missing.is_empty() {
+            let mut fetched = fetch(&missing)?;
+
+            self.with_cache(type_id, |cache: &mut Cache<V>| {
+                for key in missing {
+                    cache.values.insert(key, fetched.remove(&key).unwrap_or_default());
+                }
+            });
+        }
+
+        Ok(self.with_cache(type_id, |cache: &mut Cache<V>| {
+            keys.iter().map(|key| cache.values.get(key).cloned().unwrap_or_default()).collect()
+        }))
+    }
+
+    fn with_cache<V: Send + 'static, R>(&self, type_id: TypeId, f: impl FnOnce(&mut Cache<V>) -> R) -> R {
+        let mut caches = self.caches.lock().expect("loader lock poisoned");
+        let cache = caches
+            .entry(type_id)
+            .or_insert_with(|| Box::new(Cache::<V>::default()))
+            .downcast_mut::<Cache<V>>()
+            .expect("loader cache of another type");
+
+        f(cache)
+    }
+}
+
+fn load_by_id<T: Loadable>(ids: &[i32], conn: &PgConnection) -> QueryResult<HashMap<i32, Option<T>>> {
+    Ok(T::load(ids, conn)?.into_iter().map(|row| (row.id(), Some(row))).collect())
+}
+
+fn defer<V>(cache: &mut Cache<V>, keys: impl IntoIterator<Item = i32>) {
+    for key in keys {
+        if !cache.values.contains_key(&key) {
+            cache.deferred.insert(key);
+        }
+    }
+}
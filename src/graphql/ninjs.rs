@@ -0,0 +1,483 @@
+//! ninjs (IPTC's News in JSON, https://iptc.org/standards/ninjs/)
+//! representation of articles, versions 1.3 and 2.0.
+
+use std::collections::{HashMap, HashSet};
+use diesel::prelude::*;
+use rocket::http::Status;
+use serde_json::{json, Map, Value};
+use crate::dates::format_date;
+use crate::response::CacheableResponse;
+use crate::models::{
+    Article as ArticleModel, ArticleMedia as ArticleMediaModel, Author as AuthorModel, Image as ImageModel,
+    Keyword as KeywordModel, Route as RouteModel,
+};
+use crate::schema::{swp_article_author, swp_article_keyword, swp_article_media, swp_image_rendition};
+use super::generator::generate_asset_url;
+use super::seo::plain_text;
+use super::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NinjsVersion {
+    V1_3,
+    V2_0,
+}
+
+impl NinjsVersion {
+    pub fn parse(version: Option<&str>) -> Option<Self> {
+        match version {
+            None | Some("1.3") | Some("1") => Some(NinjsVersion::V1_3),
+            Some("2.0") | Some("2") => Some(NinjsVersion::V2_0),
+            Some(_) => None,
+        }
+    }
+}
+
+struct Rendition {
+    name: String,
+    href: String,
+    width: i32,
+    height: i32,
+}
+
+struct Media {
+    media: ArticleMediaModel,
+    href: String,
+    renditions: Vec<Rendition>,
+}
+
+/// What the document shows besides the article, loaded through the loader.
+struct NinjsContent {
+    uri: String,
+    route_name: String,
+    route_slug: String,
+    authors: Vec<String>,
+    keywords: Vec<(String, String)>,
+    /// In the order of their ids.
+    media: Vec<Media>,
+}
+
+impl NinjsContent {
+    fn load(article: &ArticleModel, context: &Context) -> QueryResult<Self> {
+        let conn = &context.db_con;
+        let loader = &context.loader;
+
+        let route = loader
+            .load::<RouteModel>(article.route_id, conn)?
+            .ok_or(diesel::result::Error::NotFound)?;
+
+        let author_ids = loader
+            .load_by(swp_article_author::article_id, article.id, conn)?
+            .iter()
+            .map(|article_author| article_author.author_id)
+            .collect::<Vec<_>>();
+        let authors = loader
+            .load_many::<AuthorModel>(&author_ids, conn)?
+            .into_iter()
+            .map(|author| author.name)
+            .collect();
+
+        let keyword_ids = loader
+            .load_by(swp_article_keyword::article_id, article.id, conn)?
+            .iter()
+            .map(|article_keyword| article_keyword.keyword_id)
+            .collect::<Vec<_>>();
+        let keywords = loader
+            .load_many::<KeywordModel>(&keyword_ids, conn)?
+            .into_iter()
+            .map(|keyword| (keyword.name, keyword.slug))
+            .collect();
+
+        Ok(NinjsContent {
+            uri: context.urls.canonical_article_url(&route.slug, &article.slug, article.id, article.published_at),
+            route_name: route.name,
+            route_slug: route.slug,
+            authors,
+            keywords,
+            media: load_media(article.id, context)?,
+        })
+    }
+}
+
+pub fn article_ninjs(article: &ArticleModel, version: NinjsVersion, context: &Context) -> QueryResult<Value> {
+    let content = NinjsContent::load(article, context)?;
+
+    Ok(render(article, &content, version))
+}
+
+fn render(article: &ArticleModel, content: &NinjsContent, version: NinjsVersion) -> Value {
+    let byline = content.authors.join(", ");
+    let description = plain_text(&article.lead);
+    let association_names = association_names(&content.media, article);
+    let associations = association_names.into_iter().zip(&content.media);
+
+    let mut ninjs = Map::new();
+    ninjs.insert("uri".to_string(), json!(content.uri));
+    ninjs.insert("type".to_string(), json!("text"));
+    ninjs.insert("pubstatus".to_string(), json!(pubstatus(&article.status)));
+
+    if let Some(published_at) = article.published_at {
+        ninjs.insert("firstcreated".to_string(), json!(format_date(published_at)));
+    }
+
+    if let Some(version_created) = article.updated_at.or(article.published_at) {
+        ninjs.insert("versioncreated".to_string(), json!(format_date(version_created)));
+    }
+
+    match version {
+        NinjsVersion::V1_3 => {
+            ninjs.insert("version".to_string(), json!("1"));
+            ninjs.insert("headline".to_string(), json!(article.title));
+            ninjs.insert("body_html".to_string(), json!(article.body));
+
+            if !description.is_empty() {
+                ninjs.insert("description_text".to_string(), json!(description));
+            }
+
+            if !byline.is_empty() {
+                ninjs.insert("byline".to_string(), json!(byline));
+            }
+
+            let mut subjects = content
+                .keywords
+                .iter()
+                .map(|(name, slug)| json!({ "name": name, "code": slug, "scheme": "keyword" }))
+                .collect::<Vec<_>>();
+            subjects.push(json!({ "name": content.route_name, "code": content.route_slug, "scheme": "route" }));
+            ninjs.insert("subject".to_string(), Value::Array(subjects));
+
+            if !content.media.is_empty() {
+                let associations = associations
+                    .map(|(name, media)| (name, association_v1_3(media)))
+                    .collect::<Map<_, _>>();
+                ninjs.insert("associations".to_string(), Value::Object(associations));
+            }
+        }
+        NinjsVersion::V2_0 => {
+            ninjs.insert("headlines".to_string(), json!([{ "value": article.title }]));
+            ninjs.insert("bodies".to_string(), json!([{ "value": article.body, "contenttype": "text/html" }]));
+
+            if !description.is_empty() {
+                ninjs.insert("descriptions".to_string(), json!([{ "value": description, "contenttype": "text/plain" }]));
+            }
+
+            if !byline.is_empty() {
+                ninjs.insert("by".to_string(), json!(byline));
+            }
+
+            let mut subjects = content
+                .keywords
+                .iter()
+                .map(|(name, slug)| json!({ "name": name, "uri": format!("keyword:{}", slug) }))
+                .collect::<Vec<_>>();
+            subjects.push(json!({ "name": content.route_name, "uri": format!("route:{}", content.route_slug) }));
+            ninjs.insert("subjects".to_string(), Value::Array(subjects));
+
+            if !content.media.is_empty() {
+                let associations = associations
+                    .map(|(name, media)| association_v2_0(name, media))
+                    .collect::<Vec<_>>();
+                ninjs.insert("associations".to_string(), Value::Array(associations));
+            }
+        }
+    }
+
+    Value::Object(ninjs)
+}
+
+/// ninjs document of a published article, 404 when there is no such
+/// article.
+pub fn ninjs_response(id: i32, version: Option<&str>, context: &Context) -> Result<CacheableResponse, Status> {
+    let version = NinjsVersion::parse(version).ok_or(Status::BadRequest)?;
+    let internal_error = |error: diesel::result::Error| {
+        eprintln!("ninjs: {}", error);
+        Status::InternalServerError
+    };
+
+    let article = context
+        .loader
+        .load::<ArticleModel>(id, &context.db_con)
+        .map_err(internal_error)?
+        .filter(|article| article.status == "published")
+        .ok_or(Status::NotFound)?;
+
+    let body = article_ninjs(&article, version, context).map_err(internal_error)?.to_string();
+    context.record_key(format!("article-{}", article.id));
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .etag_from(&body)
+        .cache_control("public, max-age=300".to_string())
+        .last_modified(article.updated_at.or(article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+fn load_media(article_id: i32, context: &Context) -> QueryResult<Vec<Media>> {
+    let conn = &context.db_con;
+    let loader = &context.loader;
+
+    let mut media = loader.load_by(swp_article_media::article_id, article_id, conn)?;
+    media.sort_by_key(|media| media.id);
+
+    let media_ids = media.iter().map(|media| media.id).collect::<Vec<_>>();
+    let renditions = loader.load_many_by(swp_image_rendition::media_id, &media_ids, conn)?;
+
+    let image_ids = media
+        .iter()
+        .map(|media| media.image_id)
+        .chain(renditions.iter().flatten().map(|rendition| rendition.image_id))
+        .collect::<Vec<_>>();
+    let images = loader
+        .load_many::<ImageModel>(&image_ids, conn)?
+        .into_iter()
+        .map(|image| (image.id, generate_asset_url(&image.asset_id, &image.file_extension)))
+        .collect::<HashMap<_, _>>();
+
+    Ok(media
+        .into_iter()
+        .zip(renditions)
+        .filter_map(|(media, renditions)| {
+            Some(Media {
+                href: images.get(&media.image_id)?.clone(),
+                renditions: renditions
+                    .into_iter()
+                    .filter_map(|rendition| {
+                        Some(Rendition {
+                            href: images.get(&rendition.image_id)?.clone(),
+                            name: rendition.name,
+                            width: rendition.width,
+                            height: rendition.height,
+                        })
+                    })
+                    .collect(),
+                media,
+            })
+        })
+        .collect())
+}
+
+/// Names of the media's associations: `featuremedia` for the feature media,
+/// the Publisher's media key otherwise, followed by a number from 2 on when
+/// several media have the same key.
+fn association_names(media: &[Media], article: &ArticleModel) -> Vec<String> {
+    let mut names = HashSet::new();
+
+    media
+        .iter()
+        .map(|media| {
+            let name = if article.feature_media == Some(media.media.id) {
+                "featuremedia"
+            } else if media.media.key.is_empty() {
+                "media"
+            } else {
+                media.media.key.as_str()
+            };
+
+            let mut unique = name.to_string();
+            let mut number = 1;
+            while !names.insert(unique.clone()) {
+                number += 1;
+                unique = format!("{}{}", name, number);
+            }
+
+            unique
+        })
+        .collect()
+}
+
+fn media_type(mimetype: &str) -> &'static str {
+    match mimetype.split('/').next() {
+        Some("image") => "picture",
+        Some("video") => "video",
+        Some("audio") => "audio",
+        _ => "composite",
+    }
+}
+
+fn association_v1_3(media: &Media) -> Value {
+    let mut association = json!({
+        "uri": media.href,
+        "type": media_type(&media.media.mimetype),
+        "mimetype": media.media.mimetype,
+    });
+
+    optional(&mut association, "headline", &media.media.description);
+    optional(&mut association, "description_text", &plain_text(&media.media.body));
+    optional(&mut association, "byline", &media.media.by_line);
+    optional(&mut association, "located", &media.media.located);
+    optional(&mut association, "usageterms", &media.media.usage_terms);
+
+    let mut renditions = media
+        .renditions
+        .iter()
+        .map(|rendition| {
+            let value = json!({
+                "href": rendition.href,
+                "width": rendition.width,
+                "height": rendition.height,
+                "mimetype": media.media.mimetype,
+            });
+            (rendition.name.clone(), value)
+        })
+        .collect::<Map<_, _>>();
+    renditions.insert("original".to_string(), json!({ "href": media.href, "mimetype": media.media.mimetype }));
+    association["renditions"] = Value::Object(renditions);
+
+    association
+}
+
+fn association_v2_0(name: String, media: &Media) -> Value {
+    let mut association = json!({
+        "name": name,
+        "uri": media.href,
+        "type": media_type(&media.media.mimetype),
+    });
+
+    if !media.media.description.is_empty() {
+        association["headlines"] = json!([{ "value": media.media.description }]);
+    }
+
+    let description = plain_text(&media.media.body);
+    if !description.is_empty() {
+        association["descriptions"] = json!([{ "value": description, "contenttype": "text/plain" }]);
+    }
+
+    optional(&mut association, "by", &media.media.by_line);
+    optional(&mut association, "located", &media.media.located);
+    optional(&mut association, "usageterms", &media.media.usage_terms);
+
+    let mut renditions = media
+        .renditions
+        .iter()
+        .map(|rendition| {
+            json!({
+                "name": rendition.name,
+                "href": rendition.href,
+                "width": rendition.width,
+                "height": rendition.height,
+                "contenttype": media.media.mimetype,
+            })
+        })
+        .collect::<Vec<_>>();
+    renditions.push(json!({ "name": "original", "href": media.href, "contenttype": media.media.mimetype }));
+    association["renditions"] = Value::Array(renditions);
+
+    association
+}
+
+fn optional(object: &mut Value, name: &str, value: &str) {
+    if !value.trim().is_empty() {
+        object[name] = json!(value);
+    }
+}
+
+fn pubstatus(status: &str) -> &'static str {
+    match status {
+        "published" => "usable",
+        "canceled" => "canceled",
+        _ => "withheld",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonschema::JSONSchema;
+    use crate::syndication::fixtures;
+    use super::*;
+
+    fn media(id: i32, key: &str) -> Media {
+        Media {
+            media: ArticleMediaModel {
+                id,
+                article_id: 42,
+                image_id: id,
+                key: key.to_string(),
+                body: "<p>The <b>storm</b> over the harbour</p>".to_string(),
+                description: "Storm".to_string(),
+                located: "Berlin".to_string(),
+                by_line: "Photographer".to_string(),
+                mimetype: "image/jpeg".to_string(),
+                usage_terms: String::new(),
+            },
+            href: format!("https://cdn.example.com/{}.jpg", id),
+            renditions: vec![Rendition {
+                name: "thumbnail".to_string(),
+                href: format!("https://cdn.example.com/{}-thumbnail.jpg", id),
+                width: 200,
+                height: 100,
+            }],
+        }
+    }
+
+    /// The feature media and two images with the same key.
+    fn content() -> NinjsContent {
+        NinjsContent {
+            uri: "https://www.example.com/news/storm".to_string(),
+            route_name: "News".to_string(),
+            route_slug: "news".to_string(),
+            authors: vec!["Jane Doe".to_string(), "John Roe".to_string()],
+            keywords: vec![("Weather".to_string(), "weather".to_string())],
+            media: vec![media(1, "feature"), media(2, "image"), media(3, "image")],
+        }
+    }
+
+    /// The IPTC schema of the version, vendored by `schemas/ninjs/fetch.sh`.
+    fn schema(version: &str) -> Value {
+        let path = format!("{}/schemas/ninjs/ninjs-schema_{}.json", env!("CARGO_MANIFEST_DIR"), version);
+        let schema = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("{}: {}, vendor it with schemas/ninjs/fetch.sh", path, error));
+
+        serde_json::from_str(&schema).unwrap()
+    }
+
+    fn assert_valid(version: &str, document: &Value) {
+        let schema = schema(version);
+        let schema = JSONSchema::compile(&schema).unwrap();
+
+        if let Err(errors) = schema.validate(document) {
+            let errors = errors.map(|error| format!("{} at {}", error, error.instance_path)).collect::<Vec<_>>();
+            panic!("invalid ninjs document: {:?}\n{}", errors, document);
+        }
+    }
+
+    #[test]
+    fn version_1_3_is_valid() {
+        let article = fixtures::article_content("<p>Body</p>").article;
+
+        assert_valid("1.3", &render(&article, &content(), NinjsVersion::V1_3));
+    }
+
+    #[test]
+    fn version_2_0_is_valid() {
+        let article = fixtures::article_content("<p>Body</p>").article;
+
+        assert_valid("2.0", &render(&article, &content(), NinjsVersion::V2_0));
+    }
+
+    #[test]
+    fn numbers_associations_with_the_same_key() {
+        let article = fixtures::article_content("<p>Body</p>").article;
+        let content = content();
+
+        assert_eq!(association_names(&content.media, &article), vec!["featuremedia", "image", "image2"]);
+
+        let ninjs = render(&article, &content, NinjsVersion::V1_3);
+        let names = ninjs["associations"].as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(names, vec!["featuremedia", "image", "image2"]);
+
+        let ninjs = render(&article, &content, NinjsVersion::V2_0);
+        let names = ninjs["associations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|association| association["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["featuremedia", "image", "image2"]);
+    }
+
+    #[test]
+    fn numbers_keys_that_collide_with_numbered_names() {
+        let article = fixtures::article_content("<p>Body</p>").article;
+        let media = vec![media(2, "image2"), media(3, "image"), media(4, "image")];
+
+        assert_eq!(association_names(&media, &article), vec!["image2", "image", "image3"]);
+    }
+}
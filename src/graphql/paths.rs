@@ -0,0 +1,139 @@
+//! Resolution of website paths to routes and articles, for frontends which
+//! get a request path and need to know what to render.
+
+use diesel::prelude::*;
+use crate::models::{Article as ArticleModel, Route as RouteModel};
+use crate::schema::{swp_article, swp_article_previous_relative_url, swp_redirect_route, swp_route};
+use super::generator::UrlBuilder;
+
+const PUBLISHED: &str = "published";
+const MOVED_PERMANENTLY: i32 = 301;
+const FOUND: i32 = 302;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolvedPath {
+    Route(RouteModel),
+    Article(ArticleModel),
+    Redirect { location: String, status_code: i32 },
+}
+
+/// Resolves the path, in order, to a Publisher redirect route, a route, a
+/// published article or a previous URL of a published article. Articles
+/// requested under an outdated path (e.g. after they were moved to another
+/// route) are redirected to their current URL.
+pub fn resolve_path(path: &str, urls: &UrlBuilder, conn: &PgConnection) -> QueryResult<Option<ResolvedPath>> {
+    let path = normalize_path(path);
+
+    if let Some(redirect) = resolve_redirect_route(&path, urls, conn)? {
+        return Ok(Some(redirect));
+    }
+
+    let last_segment = path.rsplit('/').next().unwrap_or("").to_string();
+
+    let routes = swp_route::table
+        .filter(swp_route::staticprefix.eq(&path).or(swp_route::slug.eq(&last_segment)))
+        .load::<RouteModel>(conn)?;
+    let route = routes.into_iter().find(|route| {
+        route.staticprefix.as_ref() == Some(&path) || normalize_path(&urls.route_path(&route.slug)) == path
+    });
+
+    if let Some(route) = route {
+        return Ok(Some(ResolvedPath::Route(route)));
+    }
+
+    if !last_segment.is_empty() {
+        // the route slugs are joined, the paths of all the candidates are built
+        let mut query = swp_article::table
+            .inner_join(swp_route::table)
+            .select((swp_article::all_columns, swp_route::slug))
+            .into_boxed()
+            .filter(swp_article::status.eq(PUBLISHED))
+            .filter(swp_article::slug.eq(&last_segment));
+
+        if let Ok(id) = last_segment.parse::<i32>() {
+            query = query.or_filter(swp_article::status.eq(PUBLISHED).and(swp_article::id.eq(id)));
+        }
+
+        let articles = query.order(swp_article::id.desc()).load::<(ArticleModel, String)>(conn)?;
+        let mut outdated = None;
+
+        for (article, route_slug) in articles {
+            let article_path = urls.article_path(&route_slug, &article.slug, article.id, article.published_at);
+
+            if normalize_path(&article_path) == path {
+                return Ok(Some(ResolvedPath::Article(article)));
+            }
+
+            if outdated.is_none() {
+                outdated = Some(urls.article_url(&route_slug, &article.slug, article.id, article.published_at));
+            }
+        }
+
+        if let Some(location) = outdated {
+            return Ok(Some(ResolvedPath::Redirect { location, status_code: MOVED_PERMANENTLY }));
+        }
+    }
+
+    let previous = swp_article_previous_relative_url::table
+        .inner_join(swp_article::table.inner_join(swp_route::table))
+        .filter(swp_article_previous_relative_url::relative_url.eq_any(vec![path.clone(), format!("{}/", path)]))
+        .filter(swp_article::status.eq(PUBLISHED))
+        .select((swp_article::all_columns, swp_route::slug))
+        .order(swp_article_previous_relative_url::id.desc())
+        .first::<(ArticleModel, String)>(conn)
+        .optional()?;
+
+    if let Some((article, route_slug)) = previous {
+        return Ok(Some(ResolvedPath::Redirect {
+            location: urls.article_url(&route_slug, &article.slug, article.id, article.published_at),
+            status_code: MOVED_PERMANENTLY,
+        }));
+    }
+
+    Ok(None)
+}
+
+fn resolve_redirect_route(path: &str, urls: &UrlBuilder, conn: &PgConnection) -> QueryResult<Option<ResolvedPath>> {
+    let redirect = swp_redirect_route::table
+        .left_join(swp_route::table.on(swp_redirect_route::route_target_id.eq(swp_route::id.nullable())))
+        .filter(swp_redirect_route::static_prefix.eq_any(vec![path.to_string(), format!("{}/", path)]))
+        .select((swp_route::slug.nullable(), swp_redirect_route::uri_target, swp_redirect_route::permanent))
+        .first::<(Option<String>, Option<String>, bool)>(conn)
+        .optional()?;
+
+    let (route_target_slug, uri_target, permanent) = match redirect {
+        Some(redirect) => redirect,
+        None => return Ok(None),
+    };
+
+    let location = match (uri_target.filter(|uri| !uri.is_empty()), route_target_slug) {
+        (Some(uri), _) if uri.contains("://") => uri,
+        (Some(uri), _) => urls.site_url(&uri),
+        (None, Some(route_slug)) => urls.route_url(&route_slug),
+        (None, None) => return Ok(None),
+    };
+
+    Ok(Some(ResolvedPath::Redirect {
+        location,
+        status_code: if permanent { MOVED_PERMANENTLY } else { FOUND },
+    }))
+}
+
+/// Path without the scheme, host, query string, fragment and trailing slash,
+/// always starting with a slash.
+pub fn normalize_path(path: &str) -> String {
+    let path = match path.find("://") {
+        Some(index) => {
+            let rest = &path[index + 3..];
+            rest.find('/').map(|index| &rest[index..]).unwrap_or("/")
+        }
+        None => path,
+    };
+    let path = path.split(|c| c == '?' || c == '#').next().unwrap_or("").trim_end_matches('/');
+
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
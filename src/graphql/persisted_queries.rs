@@ -0,0 +1,192 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use sha2::{Digest, Sha256};
+use super::request::Extensions;
+
+const SUPPORTED_VERSION: i32 = 1;
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PersistedQueriesMode {
+    /// Apollo-style automatic persisted queries, any operation may run.
+    Automatic,
+    /// Only operations listed in the manifest file may run.
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PersistedQueryError {
+    NotFound,
+    NotAllowed,
+    HashMismatch,
+    UnsupportedVersion(i32),
+    MissingQuery,
+}
+
+impl PersistedQueryError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PersistedQueryError::NotFound => "PERSISTED_QUERY_NOT_FOUND",
+            PersistedQueryError::NotAllowed => "PERSISTED_QUERY_NOT_ALLOWED",
+            PersistedQueryError::HashMismatch => "PERSISTED_QUERY_HASH_MISMATCH",
+            PersistedQueryError::UnsupportedVersion(_) => "PERSISTED_QUERY_UNSUPPORTED_VERSION",
+            PersistedQueryError::MissingQuery => "BAD_REQUEST",
+        }
+    }
+}
+
+impl fmt::Display for PersistedQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PersistedQueryError::NotFound => write!(f, "PersistedQueryNotFound"),
+            PersistedQueryError::NotAllowed => write!(f, "Operation is not in the persisted queries manifest"),
+            PersistedQueryError::HashMismatch => write!(f, "Provided sha does not match query"),
+            PersistedQueryError::UnsupportedVersion(version) => write!(f, "Unsupported persisted query version: {}", version),
+            PersistedQueryError::MissingQuery => write!(f, "Must provide a query string"),
+        }
+    }
+}
+
+/// Registry of queries addressable by their sha256 hash.
+///
+/// In automatic mode clients register queries on the fly (the "not found"
+/// retry protocol), in strict mode only the manifest loaded at startup is used.
+/// Clones share the registered queries.
+#[derive(Clone)]
+pub struct PersistedQueries {
+    mode: PersistedQueriesMode,
+    manifest: Arc<HashMap<String, String>>,
+    registered: Arc<RwLock<RegisteredQueries>>,
+}
+
+struct RegisteredQueries {
+    queries: HashMap<String, String>,
+    insertion_order: VecDeque<String>,
+    max_entries: usize,
+}
+
+impl PersistedQueries {
+    pub fn new(mode: PersistedQueriesMode, manifest: HashMap<String, String>, max_entries: usize) -> Self {
+        PersistedQueries {
+            mode,
+            manifest: Arc::new(manifest),
+            registered: Arc::new(RwLock::new(RegisteredQueries {
+                queries: HashMap::new(),
+                insertion_order: VecDeque::new(),
+                max_entries,
+            })),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("PUBLISHER_PERSISTED_QUERIES_MODE") {
+            Ok(ref mode) if mode == "strict" => PersistedQueriesMode::Strict,
+            _ => PersistedQueriesMode::Automatic,
+        };
+
+        let manifest = match std::env::var("PUBLISHER_PERSISTED_QUERIES_MANIFEST") {
+            Ok(path) => load_manifest(&path),
+            Err(_) if mode == PersistedQueriesMode::Strict => {
+                panic!("PUBLISHER_PERSISTED_QUERIES_MANIFEST must be set in strict mode")
+            }
+            Err(_) => HashMap::new(),
+        };
+
+        let max_entries = std::env::var("PUBLISHER_PERSISTED_QUERIES_MAX_ENTRIES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ENTRIES);
+
+        PersistedQueries::new(mode, manifest, max_entries)
+    }
+
+    /// Returns the query document to execute for the given request.
+    pub fn resolve(
+        &self,
+        query: Option<&String>,
+        extensions: Option<&Extensions>,
+    ) -> Result<String, PersistedQueryError> {
+        let persisted_query = extensions.and_then(|extensions| extensions.persisted_query.as_ref());
+
+        let persisted_query = match persisted_query {
+            Some(persisted_query) => persisted_query,
+            None => {
+                let query = query.ok_or(PersistedQueryError::MissingQuery)?;
+
+                if self.mode == PersistedQueriesMode::Strict && !self.manifest.contains_key(&hash_query(query)) {
+                    return Err(PersistedQueryError::NotAllowed);
+                }
+
+                return Ok(query.to_owned());
+            }
+        };
+
+        if persisted_query.version != SUPPORTED_VERSION {
+            return Err(PersistedQueryError::UnsupportedVersion(persisted_query.version));
+        }
+
+        let hash = persisted_query.sha256_hash.to_lowercase();
+
+        if let Some(query) = query {
+            if hash_query(query) != hash {
+                return Err(PersistedQueryError::HashMismatch);
+            }
+        }
+
+        if let Some(manifest_query) = self.manifest.get(&hash) {
+            return Ok(manifest_query.to_owned());
+        }
+
+        match self.mode {
+            PersistedQueriesMode::Strict => Err(PersistedQueryError::NotAllowed),
+            PersistedQueriesMode::Automatic => match query {
+                Some(query) => {
+                    self.register(hash, query.to_owned());
+                    Ok(query.to_owned())
+                }
+                None => self.lookup(&hash).ok_or(PersistedQueryError::NotFound),
+            },
+        }
+    }
+
+    fn lookup(&self, hash: &str) -> Option<String> {
+        let registered = self.registered.read().expect("persisted queries lock poisoned");
+
+        registered.queries.get(hash).cloned()
+    }
+
+    fn register(&self, hash: String, query: String) {
+        let mut registered = self.registered.write().expect("persisted queries lock poisoned");
+
+        if registered.queries.contains_key(&hash) || registered.max_entries == 0 {
+            return;
+        }
+
+        if registered.queries.len() >= registered.max_entries {
+            if let Some(oldest) = registered.insertion_order.pop_front() {
+                registered.queries.remove(&oldest);
+            }
+        }
+
+        registered.insertion_order.push_back(hash.clone());
+        registered.queries.insert(hash, query);
+    }
+}
+
+pub fn hash_query(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+/// Loads a manifest in the `{"<sha256>": "<query>"}` format.
+fn load_manifest(path: &str) -> HashMap<String, String> {
+    let contents = fs::read_to_string(path).expect("failed to read persisted queries manifest");
+    let manifest: HashMap<String, String> =
+        serde_json::from_str(&contents).expect("invalid persisted queries manifest");
+
+    manifest
+        .into_iter()
+        .map(|(hash, query)| (hash.to_lowercase(), query))
+        .collect()
+}
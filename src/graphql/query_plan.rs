@@ -0,0 +1,161 @@
+//! What `articles` runs, decided from the query trail: which columns of the
+//! articles are selected, whether the articles are loaded at all and how
+//! they are counted.
+
+use diesel::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::sql_types::{Nullable, Text};
+use crate::schema::swp_article;
+use super::{Article, ArticleConnection, QueryTrail, Walked};
+
+/// Columns of `models::Article`, the large ones replaced by empty values when
+/// they are not needed.
+pub type ArticleColumns = (
+    swp_article::id,
+    swp_article::slug,
+    swp_article::status,
+    SqlLiteral<Text>,
+    SqlLiteral<Text>,
+    SqlLiteral<Text>,
+    swp_article::route_id,
+    swp_article::comments_count,
+    SqlLiteral<Nullable<Text>>,
+    SqlLiteral<Nullable<Text>>,
+    swp_article::feature_media,
+    swp_article::seo_metadata_id,
+    swp_article::published_at,
+    swp_article::updated_at,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Count {
+    /// Neither `totalCount` nor `pageInfo` is queried.
+    Skip,
+    Exact,
+    /// From the planner's statistics, see `PUBLISHER_ARTICLES_COUNT`.
+    Estimate,
+}
+
+impl Count {
+    /// `PUBLISHER_ARTICLES_COUNT`, read once at startup into `ContextConfig`.
+    pub fn from_env() -> Self {
+        match std::env::var("PUBLISHER_ARTICLES_COUNT").as_ref().map(String::as_str) {
+            Ok("estimate") => Count::Estimate,
+            _ => Count::Exact,
+        }
+    }
+}
+
+/// Columns of the articles that are only selected when needed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArticleColumn {
+    Title,
+    Body,
+    Lead,
+    Extra,
+    Metadata,
+}
+
+impl ArticleColumn {
+    pub const ALL: &'static [ArticleColumn] = &[
+        ArticleColumn::Title,
+        ArticleColumn::Body,
+        ArticleColumn::Lead,
+        ArticleColumn::Extra,
+        ArticleColumn::Metadata,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleProjection {
+    title: bool,
+    body: bool,
+    lead: bool,
+    extra: bool,
+    metadata: bool,
+}
+
+impl ArticleProjection {
+    /// Fields computed from the whole article (SEO, structured data, ninjs)
+    /// need all the columns.
+    pub fn new(trail: &QueryTrail<'_, Article, Walked>) -> Self {
+        let computed = trail.structured_data()
+            || trail.structured_data_script()
+            || trail.ninjs()
+            || trail.effective_seo().walk().is_some();
+
+        ArticleProjection {
+            title: computed || trail.title(),
+            body: computed || trail.body(),
+            lead: computed || trail.lead(),
+            extra: computed || trail.extra(),
+            metadata: computed || trail.metadata(),
+        }
+    }
+
+    /// Whether the loaded models are complete, only those are cached by the
+    /// loader.
+    pub fn is_complete(&self) -> bool {
+        ArticleColumn::ALL.iter().all(|column| self.selects(*column))
+    }
+
+    pub fn selects(&self, column: ArticleColumn) -> bool {
+        match column {
+            ArticleColumn::Title => self.title,
+            ArticleColumn::Body => self.body,
+            ArticleColumn::Lead => self.lead,
+            ArticleColumn::Extra => self.extra,
+            ArticleColumn::Metadata => self.metadata,
+        }
+    }
+
+    pub fn columns(&self) -> ArticleColumns {
+        (
+            swp_article::id,
+            swp_article::slug,
+            swp_article::status,
+            column(self.title, "title", "''::text"),
+            column(self.body, "body", "''::text"),
+            column(self.lead, "lead", "''::text"),
+            swp_article::route_id,
+            swp_article::comments_count,
+            column(self.extra, "extra", "NULL::text"),
+            column(self.metadata, "metadata", "NULL::text"),
+            swp_article::feature_media,
+            swp_article::seo_metadata_id,
+            swp_article::published_at,
+            swp_article::updated_at,
+        )
+    }
+}
+
+fn column<ST>(selected: bool, name: &str, placeholder: &str) -> SqlLiteral<ST> {
+    if selected {
+        sql(&format!("swp_article.{}", name))
+    } else {
+        sql(&format!("{} AS {}", placeholder, name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticlesPlan {
+    /// `None` when no article is queried, only the count is then run.
+    pub projection: Option<ArticleProjection>,
+    pub count: Count,
+}
+
+impl ArticlesPlan {
+    /// `count` is how lists are counted, when they are.
+    pub fn new(trail: &QueryTrail<'_, ArticleConnection, Walked>, count: Count) -> Self {
+        let count = if trail.total_count() || trail.page_info().walk().is_some() {
+            count
+        } else {
+            Count::Skip
+        };
+
+        ArticlesPlan {
+            projection: trail.edges().node().walk().map(|node| ArticleProjection::new(&node)),
+            count,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::Read;
+use juniper::{http, InputValue};
+use rocket::{
+    data::{self, FromDataSimple},
+    http::Status,
+    request::{FromQuery, Query as QueryItems},
+    Data, Outcome, Request,
+};
+use super::cache::{CacheScope, CacheStatus, ResponseCache};
+use super::cache_control::{CacheControl, CacheControlPolicy};
+use super::persisted_queries::{PersistedQueries, PersistedQueryError};
+use super::response::{GraphQLResponse, ResponseMetadata};
+use super::{Context, Schema};
+
+const BODY_LIMIT: u64 = 1024 * 100;
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PersistedQueryExtension {
+    pub version: i32,
+    #[serde(rename = "sha256Hash")]
+    pub sha256_hash: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Extensions {
+    #[serde(rename = "persistedQuery")]
+    pub persisted_query: Option<PersistedQueryExtension>,
+}
+
+/// GraphQL request which, unlike `juniper_rocket::GraphQLRequest`, keeps the
+/// `extensions` object so persisted queries can be resolved before execution.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GraphQLRequest {
+    pub query: Option<String>,
+    #[serde(rename = "operationName")]
+    pub operation_name: Option<String>,
+    pub variables: Option<InputValue>,
+    pub extensions: Option<Extensions>,
+}
+
+impl GraphQLRequest {
+    pub fn execute(
+        &self,
+        schema: &Schema,
+        context: &Context,
+        persisted_queries: &PersistedQueries,
+        cache: &ResponseCache,
+        scope: &CacheScope,
+        cache_control: &CacheControl,
+    ) -> GraphQLResponse {
+        let query = match persisted_queries.resolve(self.query.as_ref(), self.extensions.as_ref()) {
+            Ok(query) => query,
+            Err(error) => return error.into_response(),
+        };
+
+        let policy = if scope.preview {
+            CacheControlPolicy::no_store()
+        } else {
+            cache_control.policy(&query, self.operation_name.as_ref())
+        };
+        let cache_key = cache.key(&query, self.operation_name.as_ref(), self.variables.as_ref(), scope);
+
+        if let Some(cached) = cache_key.as_ref().and_then(|key| cache.get(key)) {
+            if let Ok(cached) = serde_json::from_str::<CachedResponse>(&cached) {
+                return build_response(Status::Ok, cached.body, cached.metadata, policy, CacheStatus::Hit);
+            }
+        }
+
+        let request = http::GraphQLRequest::new(
+            query,
+            self.operation_name.clone(),
+            self.variables.clone(),
+        );
+        let response = request.execute(schema, context);
+        let status = if response.is_ok() { Status::Ok } else { Status::BadRequest };
+        let body = serde_json::to_value(&response).expect("failed to serialize GraphQL response");
+        let metadata = context.response_metadata();
+
+        let cache_status = match cache_key {
+            Some(ref key) if response.is_ok() => {
+                let cached = CachedResponse { body: body.clone(), metadata: metadata.clone() };
+                cache.set(
+                    key,
+                    &serde_json::to_string(&cached).expect("failed to serialize cached response"),
+                    &metadata.surrogate_keys,
+                );
+                CacheStatus::Miss
+            }
+            _ => CacheStatus::Bypass,
+        };
+
+        let policy = if response.is_ok() { policy } else { CacheControlPolicy::no_store() };
+
+        build_response(status, body, metadata, policy, cache_status)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    body: serde_json::Value,
+    metadata: ResponseMetadata,
+}
+
+fn build_response(
+    status: Status,
+    mut body: serde_json::Value,
+    metadata: ResponseMetadata,
+    policy: CacheControlPolicy,
+    cache_status: CacheStatus,
+) -> GraphQLResponse {
+    // the ETag has to be the same for cache hits and misses
+    let representation = body.to_string();
+
+    if let Some(object) = body.as_object_mut() {
+        object.insert("extensions".to_string(), serde_json::json!({
+            "cache": { "status": cache_status.as_str() }
+        }));
+    }
+
+    GraphQLResponse::new(status, body.to_string())
+        .etag_from(&representation)
+        .cache_control(policy.header_value())
+        .last_modified(metadata.last_modified)
+        .surrogate_keys(metadata.surrogate_keys)
+}
+
+impl PersistedQueryError {
+    fn into_response(self) -> GraphQLResponse {
+        let status = match self {
+            PersistedQueryError::NotFound => Status::Ok,
+            _ => Status::BadRequest,
+        };
+
+        GraphQLResponse::error(status, serde_json::json!({
+            "errors": [{
+                "message": self.to_string(),
+                "extensions": { "code": self.code() },
+            }]
+        }))
+    }
+}
+
+impl<'q> FromQuery<'q> for GraphQLRequest {
+    type Error = String;
+
+    fn from_query(query_items: QueryItems<'q>) -> Result<Self, Self::Error> {
+        let mut query = None;
+        let mut operation_name = None;
+        let mut variables = None;
+        let mut extensions = None;
+
+        for item in query_items {
+            let (key, value) = item.key_value_decoded();
+
+            match key.as_str() {
+                "query" => query = Some(value),
+                "operationName" => operation_name = Some(value),
+                "variables" => variables = Some(parse_json_param::<InputValue>("variables", &value)?),
+                "extensions" => extensions = Some(parse_json_param::<Extensions>("extensions", &value)?),
+                _ => {}
+            }
+        }
+
+        if query.is_none() && extensions.is_none() {
+            return Err("Either `query` or `extensions` parameter is required".to_string());
+        }
+
+        Ok(GraphQLRequest { query, operation_name, variables, extensions })
+    }
+}
+
+impl FromDataSimple for GraphQLRequest {
+    type Error = String;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        if !request.content_type().map_or(false, |ct| ct.is_json()) {
+            return Outcome::Forward(data);
+        }
+
+        let mut body = String::new();
+        if let Err(error) = data.open().take(BODY_LIMIT).read_to_string(&mut body) {
+            return Outcome::Failure((Status::InternalServerError, format!("{:?}", error)));
+        }
+
+        match serde_json::from_str(&body) {
+            Ok(request) => Outcome::Success(request),
+            Err(error) => Outcome::Failure((Status::BadRequest, format!("{}", error))),
+        }
+    }
+}
+
+fn parse_json_param<T: serde::de::DeserializeOwned>(name: &str, value: &str) -> Result<T, String> {
+    serde_json::from_str(value)
+        .map_err(|error| format!("invalid `{}` parameter: {}", name, error))
+}
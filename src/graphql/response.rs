@@ -0,0 +1,68 @@
+use std::collections::BTreeSet;
+use chrono::prelude::*;
+use super::{Article, Author, Keyword, Route};
+
+pub type GraphQLResponse = crate::response::CacheableResponse;
+
+/// Entity which can be part of a response and purged from the CDN by its
+/// surrogate key.
+pub trait ResponseEntity {
+    fn surrogate_key(&self) -> String;
+
+    fn updated_at(&self) -> Option<NaiveDateTime> {
+        None
+    }
+}
+
+impl ResponseEntity for Article {
+    fn surrogate_key(&self) -> String {
+        format!("article-{}", self.article.id)
+    }
+
+    fn updated_at(&self) -> Option<NaiveDateTime> {
+        self.article.updated_at
+    }
+}
+
+impl ResponseEntity for Route {
+    fn surrogate_key(&self) -> String {
+        format!("route-{}", self.route.id)
+    }
+}
+
+impl ResponseEntity for Author {
+    fn surrogate_key(&self) -> String {
+        format!("author-{}", self.author.id)
+    }
+}
+
+impl ResponseEntity for Keyword {
+    fn surrogate_key(&self) -> String {
+        format!("keyword-{}", self.keyword.id)
+    }
+}
+
+/// Facts about the returned entities collected while resolving a query, used
+/// to build the HTTP caching headers.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ResponseMetadata {
+    pub last_modified: Option<NaiveDateTime>,
+    pub surrogate_keys: BTreeSet<String>,
+}
+
+impl ResponseMetadata {
+    pub fn record<T: ResponseEntity>(&mut self, entity: &T) {
+        self.surrogate_keys.insert(entity.surrogate_key());
+        self.record_updated_at(entity.updated_at());
+    }
+
+    pub fn record_key(&mut self, key: String) {
+        self.surrogate_keys.insert(key);
+    }
+
+    pub fn record_updated_at(&mut self, updated_at: Option<NaiveDateTime>) {
+        if updated_at > self.last_modified {
+            self.last_modified = updated_at;
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! SEO metadata of articles with the fallbacks applied, so clients don't have
+//! to invent their own when a Publisher field is empty.
+
+use chrono::prelude::*;
+use diesel::prelude::*;
+use juniper::{Executor, FieldResult};
+use juniper_eager_loading::GraphqlNodeForModel;
+use crate::dates::format_date;
+use crate::models::{
+    Article as ArticleModel, ArticleMedia as ArticleMediaModel, ArticleSeoMedia as ArticleSeoMediaModel,
+    ArticleSeoMetadata as ArticleSeoMetadataModel, Image as ImageModel, Route as RouteModel,
+};
+use crate::syndication::RouteFormat;
+use crate::xml::{decode_entities, escape};
+use super::generator::generate_asset_url;
+use super::{Context, EffectiveSeoFields, Image, QueryTrail, Walked};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveSeo {
+    title: String,
+    description: String,
+    og_title: String,
+    og_description: String,
+    twitter_title: String,
+    twitter_description: String,
+    canonical_url: String,
+    url: String,
+    amp_url: Option<String>,
+    oembed_url: String,
+    og_image: Option<ImageModel>,
+    twitter_image: Option<ImageModel>,
+    published_at: Option<NaiveDateTime>,
+    updated_at: Option<NaiveDateTime>,
+    site_name: Option<String>,
+}
+
+impl EffectiveSeo {
+    /// Titles fall back from Open Graph/Twitter to the meta title and then to
+    /// the article's title, descriptions likewise to the meta description and
+    /// the lead. Images fall back from the Open Graph/Twitter media to the meta
+    /// media and then to the feature media.
+    pub fn load(article: &ArticleModel, context: &Context) -> QueryResult<Self> {
+        let conn = &context.db_con;
+        let loader = &context.loader;
+
+        let metadata = match article.seo_metadata_id {
+            Some(id) => loader.load::<ArticleSeoMetadataModel>(id, conn)?,
+            None => None,
+        };
+        let metadata = metadata.as_ref();
+
+        let field = |value: Option<&Option<String>>| value.and_then(|value| non_empty(value.as_ref()));
+        let meta_title = field(metadata.map(|metadata| &metadata.meta_title));
+        let meta_description = field(metadata.map(|metadata| &metadata.meta_description));
+
+        let title = meta_title.clone().unwrap_or_else(|| article.title.clone());
+        let description = meta_description.clone().unwrap_or_else(|| plain_text(&article.lead));
+
+        let seo_image = |media_id: Option<i32>| -> QueryResult<Option<ImageModel>> {
+            match media_id {
+                Some(media_id) => match loader.load::<ArticleSeoMediaModel>(media_id, conn)? {
+                    Some(media) => loader.load::<ImageModel>(media.image_id, conn),
+                    None => Ok(None),
+                },
+                None => Ok(None),
+            }
+        };
+
+        let meta_image = seo_image(metadata.and_then(|metadata| metadata.seo_meta_media_id))?;
+        let feature_image = match article.feature_media {
+            Some(media_id) => match loader.load::<ArticleMediaModel>(media_id, conn)? {
+                Some(media) => loader.load::<ImageModel>(media.image_id, conn)?,
+                None => None,
+            },
+            None => None,
+        };
+        let fallback_image = meta_image.or(feature_image);
+
+        let route_slug = loader
+            .load::<RouteModel>(article.route_id, conn)?
+            .map(|route| route.slug)
+            .ok_or(diesel::result::Error::NotFound)?;
+        let url = context.urls.article_url(&route_slug, &article.slug, article.id, article.published_at);
+
+        Ok(EffectiveSeo {
+            og_title: field(metadata.map(|metadata| &metadata.og_title)).unwrap_or_else(|| title.clone()),
+            og_description: field(metadata.map(|metadata| &metadata.og_description))
+                .unwrap_or_else(|| description.clone()),
+            twitter_title: field(metadata.map(|metadata| &metadata.twitter_title)).unwrap_or_else(|| title.clone()),
+            twitter_description: field(metadata.map(|metadata| &metadata.twitter_description))
+                .unwrap_or_else(|| description.clone()),
+            og_image: seo_image(metadata.and_then(|metadata| metadata.seo_og_media_id))?.or_else(|| fallback_image.clone()),
+            twitter_image: seo_image(metadata.and_then(|metadata| metadata.seo_twitter_media_id))?
+                .or_else(|| fallback_image.clone()),
+            title,
+            description,
+            canonical_url: context.urls.canonical_article_url(&route_slug, &article.slug, article.id, article.published_at),
+            oembed_url: context.urls.api_url(&format!("/oembed?url={}", encode_query_value(&url))),
+            url,
+            amp_url: if article.status == "published" && context.route_formats.enabled_for(RouteFormat::Amp, article.route_id, &route_slug) {
+                Some(context.urls.api_url(&format!("/amp/articles/{}", article.id)))
+            } else {
+                None
+            },
+            published_at: article.published_at,
+            updated_at: article.updated_at,
+            site_name: context.organization.as_ref().map(|organization| organization.name.clone()),
+        })
+    }
+
+    /// `<title>`, description, canonical, AMP and oEmbed discovery links, Open Graph
+    /// and Twitter Card tags, one per line.
+    pub fn head_tags(&self) -> String {
+        let mut tags = vec![
+            format!("<title>{}</title>", escape(&self.title)),
+            meta("name", "description", &self.description),
+            format!(r#"<link rel="canonical" href="{}">"#, escape(&self.canonical_url)),
+            meta("property", "og:type", "article"),
+            meta("property", "og:title", &self.og_title),
+            meta("property", "og:description", &self.og_description),
+            meta("property", "og:url", &self.url),
+            format!(
+                r#"<link rel="alternate" type="application/json+oembed" href="{}">"#,
+                escape(&self.oembed_url)
+            ),
+        ];
+
+        if let Some(amp_url) = &self.amp_url {
+            tags.push(format!(r#"<link rel="amphtml" href="{}">"#, escape(amp_url)));
+        }
+
+        if let Some(site_name) = &self.site_name {
+            tags.push(meta("property", "og:site_name", site_name));
+        }
+
+        if let Some(image) = &self.og_image {
+            tags.push(meta("property", "og:image", &image_url(image)));
+        }
+
+        if let Some(published_at) = self.published_at {
+            tags.push(meta("property", "article:published_time", &format_date(published_at)));
+        }
+
+        if let Some(updated_at) = self.updated_at {
+            tags.push(meta("property", "article:modified_time", &format_date(updated_at)));
+        }
+
+        let card = if self.twitter_image.is_some() { "summary_large_image" } else { "summary" };
+        tags.push(meta("name", "twitter:card", card));
+        tags.push(meta("name", "twitter:title", &self.twitter_title));
+        tags.push(meta("name", "twitter:description", &self.twitter_description));
+
+        if let Some(image) = &self.twitter_image {
+            tags.push(meta("name", "twitter:image", &image_url(image)));
+        }
+
+        tags.join("\n")
+    }
+}
+
+impl EffectiveSeoFields for EffectiveSeo {
+    fn field_title(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.title)
+    }
+
+    fn field_description(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.description)
+    }
+
+    fn field_og_title(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.og_title)
+    }
+
+    fn field_og_description(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.og_description)
+    }
+
+    fn field_og_image(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Image, Walked>,
+    ) -> FieldResult<Option<Image>> {
+        Ok(self.og_image.as_ref().map(Image::new_from_model))
+    }
+
+    fn field_twitter_title(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.twitter_title)
+    }
+
+    fn field_twitter_description(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.twitter_description)
+    }
+
+    fn field_twitter_image(
+        &self,
+        _executor: &Executor<'_, Context>,
+        _trail: &QueryTrail<'_, Image, Walked>,
+    ) -> FieldResult<Option<Image>> {
+        Ok(self.twitter_image.as_ref().map(Image::new_from_model))
+    }
+
+    fn field_canonical_url(&self, _executor: &Executor<'_, Context>) -> FieldResult<&String> {
+        Ok(&self.canonical_url)
+    }
+
+    fn field_head_tags(&self, _executor: &Executor<'_, Context>) -> FieldResult<String> {
+        Ok(self.head_tags())
+    }
+}
+
+fn meta(attribute: &str, name: &str, content: &str) -> String {
+    format!(r#"<meta {}="{}" content="{}">"#, attribute, name, escape(content))
+}
+
+fn encode_query_value(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn image_url(image: &ImageModel) -> String {
+    generate_asset_url(&image.asset_id, &image.file_extension)
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|value| value.trim()).filter(|value| !value.is_empty()).map(str::to_string)
+}
+
+/// Text of an HTML fragment (leads may contain markup) with whitespace
+/// collapsed and character references decoded, so it can be escaped again.
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    decode_entities(&text).split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,180 @@
+//! schema.org `NewsArticle` documents (JSON-LD) describing articles, for
+//! search engines' rich results.
+
+use diesel::prelude::*;
+use serde_json::{json, Map, Value};
+use crate::dates::format_date;
+use crate::models::{
+    Article as ArticleModel, Author as AuthorModel, Image as ImageModel, Keyword as KeywordModel, Route as RouteModel,
+};
+use crate::schema::{swp_article_author, swp_article_keyword, swp_image_rendition};
+use super::generator::generate_asset_url;
+use super::seo::plain_text;
+use super::Context;
+
+/// Google truncates longer headlines in rich results.
+const MAX_HEADLINE_LENGTH: usize = 110;
+
+pub fn news_article(article: &ArticleModel, context: &Context) -> QueryResult<Value> {
+    let conn = &context.db_con;
+    let loader = &context.loader;
+
+    let route = loader
+        .load::<RouteModel>(article.route_id, conn)?
+        .ok_or(diesel::result::Error::NotFound)?;
+    let (route_name, route_slug) = (route.name, route.slug);
+
+    let author_ids = loader
+        .load_by(swp_article_author::article_id, article.id, conn)?
+        .iter()
+        .map(|article_author| article_author.author_id)
+        .collect::<Vec<_>>();
+    let authors = loader.load_many::<AuthorModel>(&author_ids, conn)?;
+
+    let keyword_ids = loader
+        .load_by(swp_article_keyword::article_id, article.id, conn)?
+        .iter()
+        .map(|article_keyword| article_keyword.keyword_id)
+        .collect::<Vec<_>>();
+    let keywords = loader
+        .load_many::<KeywordModel>(&keyword_ids, conn)?
+        .into_iter()
+        .map(|keyword| keyword.name)
+        .collect::<Vec<_>>();
+
+    let mut images = Vec::new();
+    if let Some(media_id) = article.feature_media {
+        let mut renditions = loader.load_by(swp_image_rendition::media_id, media_id, conn)?;
+        renditions.sort_by(|a, b| b.width.cmp(&a.width));
+
+        let image_ids = renditions.iter().map(|rendition| rendition.image_id).collect::<Vec<_>>();
+        let rendition_images = loader.load_many::<ImageModel>(&image_ids, conn)?;
+
+        for rendition in renditions {
+            if let Some(image) = rendition_images.iter().find(|image| image.id == rendition.image_id) {
+                images.push((image.asset_id.clone(), image.file_extension.clone(), rendition.width, rendition.height));
+            }
+        }
+    }
+
+    let url = context.urls.article_url(&route_slug, &article.slug, article.id, article.published_at);
+    let canonical_url = context.urls.canonical_article_url(&route_slug, &article.slug, article.id, article.published_at);
+
+    let mut document = Map::new();
+    document.insert("@context".to_string(), json!("https://schema.org"));
+    document.insert("@type".to_string(), json!("NewsArticle"));
+    document.insert("mainEntityOfPage".to_string(), json!({ "@type": "WebPage", "@id": canonical_url }));
+    document.insert("url".to_string(), json!(url));
+    document.insert("headline".to_string(), json!(headline(&article.title)));
+
+    let description = plain_text(&article.lead);
+    if !description.is_empty() {
+        document.insert("description".to_string(), json!(description));
+    }
+
+    if let Some(published_at) = article.published_at {
+        document.insert("datePublished".to_string(), json!(format_date(published_at)));
+    }
+
+    if let Some(modified_at) = article.updated_at.or(article.published_at) {
+        document.insert("dateModified".to_string(), json!(format_date(modified_at)));
+    }
+
+    if !authors.is_empty() {
+        let authors = authors.iter().map(|author| person(author, context)).collect::<Vec<_>>();
+        document.insert("author".to_string(), Value::Array(authors));
+    }
+
+    if !images.is_empty() {
+        let images = images
+            .iter()
+            .map(|(asset_id, file_extension, width, height)| {
+                json!({
+                    "@type": "ImageObject",
+                    "url": generate_asset_url(asset_id, file_extension),
+                    "width": width,
+                    "height": height,
+                })
+            })
+            .collect::<Vec<_>>();
+        document.insert("image".to_string(), Value::Array(images));
+    }
+
+    document.insert("articleSection".to_string(), json!(route_name));
+
+    if !keywords.is_empty() {
+        document.insert("keywords".to_string(), json!(keywords));
+    }
+
+    if let Some(organization) = &context.organization {
+        let mut publisher = json!({ "@type": "Organization", "name": organization.name });
+        if let Some(logo) = &organization.logo {
+            publisher["logo"] = json!({ "@type": "ImageObject", "url": logo });
+        }
+        document.insert("publisher".to_string(), publisher);
+    }
+
+    Ok(Value::Object(document))
+}
+
+/// `<script type="application/ld+json">` element containing the document.
+/// `<`, `>` and `&` are escaped so the content can't close the element.
+pub fn script_tag(document: &Value) -> String {
+    let json = document
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026");
+
+    format!(r#"<script type="application/ld+json">{}</script>"#, json)
+}
+
+fn person(author: &AuthorModel, context: &Context) -> Value {
+    let mut person = json!({ "@type": "Person", "name": author.name });
+
+    if let Some(slug) = &author.slug {
+        person["url"] = json!(context.urls.author_url(slug));
+    }
+
+    if !author.job_title.is_empty() {
+        person["jobTitle"] = json!(author.job_title);
+    }
+
+    let same_as = [
+        (&author.twitter, "https://twitter.com/"),
+        (&author.facebook, "https://www.facebook.com/"),
+        (&author.instagram, "https://www.instagram.com/"),
+    ]
+    .iter()
+    .filter_map(|(account, profile_url)| account.as_ref().map(|account| social_url(account, profile_url)))
+    .filter(|url| !url.is_empty())
+    .collect::<Vec<_>>();
+
+    if !same_as.is_empty() {
+        person["sameAs"] = json!(same_as);
+    }
+
+    person
+}
+
+/// Authors' social accounts are stored either as URLs or as handles.
+pub fn social_url(account: &str, profile_url: &str) -> String {
+    let account = account.trim();
+
+    if account.is_empty() || account.starts_with("http://") || account.starts_with("https://") {
+        account.to_string()
+    } else {
+        format!("{}{}", profile_url, account.trim_start_matches('@'))
+    }
+}
+
+fn headline(title: &str) -> String {
+    if title.chars().count() <= MAX_HEADLINE_LENGTH {
+        return title.to_string();
+    }
+
+    let mut headline = title.chars().take(MAX_HEADLINE_LENGTH - 1).collect::<String>();
+    headline.push('…');
+
+    headline
+}
@@ -0,0 +1,129 @@
+use graphql_parser::query::{parse_query, Definition, OperationDefinition, Query as QueryDefinition};
+use juniper::{http, Executor, FieldResult, InputValue, RootNode};
+use crate::changes::{ChangeEvent, ChangeKind, Entity};
+use super::{load_article, Article, Context, Mutation, QueryTrail, SubscriptionFields, Walked};
+
+/// Root of subscription operations. juniper can not execute subscriptions, so
+/// every change event runs the subscription document as a query against this
+/// root, with the event available in `Context::change_event`.
+pub struct Subscription;
+
+pub type SubscriptionSchema = RootNode<'static, Subscription, Mutation>;
+
+pub fn subscription_schema() -> SubscriptionSchema {
+    SubscriptionSchema::new(Subscription, Mutation)
+}
+
+impl SubscriptionFields for Subscription {
+    fn field_article_published(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, Article, Walked>,
+        route: Option<i32>,
+    ) -> FieldResult<Option<Article>> {
+        match matching_event(executor, ChangeKind::Published) {
+            Some(event) if route.is_none() || route == event.route_id => load_article(event.id, executor, trail),
+            _ => Ok(None),
+        }
+    }
+
+    fn field_article_updated(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, Article, Walked>,
+        id: Option<i32>,
+    ) -> FieldResult<Option<Article>> {
+        match matching_event(executor, ChangeKind::Updated) {
+            Some(event) if id.is_none() || id == Some(event.id) => load_article(event.id, executor, trail),
+            _ => Ok(None),
+        }
+    }
+
+    fn field_article_unpublished(
+        &self,
+        executor: &Executor<'_, Context>,
+        trail: &QueryTrail<'_, Article, Walked>,
+    ) -> FieldResult<Option<Article>> {
+        match matching_event(executor, ChangeKind::Unpublished) {
+            Some(event) => load_article(event.id, executor, trail),
+            None => Ok(None),
+        }
+    }
+}
+
+fn matching_event<'a>(executor: &'a Executor<'_, Context>, kind: ChangeKind) -> Option<&'a ChangeEvent> {
+    executor
+        .context()
+        .change_event
+        .as_ref()
+        .filter(|event| event.entity == Entity::Article && event.kind == kind)
+}
+
+/// A subscription document sent by a client, prepared to be executed for
+/// every change event.
+#[derive(Debug, Clone)]
+pub struct LiveSubscription {
+    query: String,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+}
+
+impl LiveSubscription {
+    pub fn new(
+        query: &str,
+        operation_name: Option<String>,
+        variables: Option<InputValue>,
+    ) -> Result<Self, String> {
+        let document = parse_query(query).map_err(|error| error.to_string())?;
+        let mut has_subscription = false;
+
+        let definitions = document
+            .definitions
+            .into_iter()
+            .map(|definition| match definition {
+                Definition::Operation(OperationDefinition::Subscription(subscription)) => {
+                    has_subscription = true;
+                    Definition::Operation(OperationDefinition::Query(QueryDefinition {
+                        position: subscription.position,
+                        name: subscription.name,
+                        variable_definitions: subscription.variable_definitions,
+                        directives: subscription.directives,
+                        selection_set: subscription.selection_set,
+                    }))
+                }
+                definition => definition,
+            })
+            .collect::<Vec<_>>();
+
+        if !has_subscription {
+            return Err("Only subscription operations are supported".to_string());
+        }
+
+        let document = graphql_parser::query::Document { definitions };
+
+        Ok(LiveSubscription {
+            query: document.to_string(),
+            operation_name,
+            variables,
+        })
+    }
+
+    /// Executes the subscription in the context of a change event, see
+    /// `Context::for_event`. Returns `None` when none of the subscribed fields
+    /// matched the event.
+    pub fn execute(&self, schema: &SubscriptionSchema, context: &Context) -> Option<serde_json::Value> {
+        let request = http::GraphQLRequest::new(
+            self.query.clone(),
+            self.operation_name.clone(),
+            self.variables.clone(),
+        );
+        let response = serde_json::to_value(&request.execute(schema, context)).ok()?;
+
+        let matched = match response.get("data").and_then(|data| data.as_object()) {
+            Some(data) => data.values().any(|value| !value.is_null()),
+            None => true,
+        };
+
+        if matched { Some(response) } else { None }
+    }
+}
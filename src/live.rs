@@ -0,0 +1,400 @@
+//! Live article updates, served next to Rocket (which can neither upgrade
+//! connections to WebSockets nor flush partial responses) on
+//! `PUBLISHER_LIVE_ADDRESS`:
+//!
+//! - `ws://<address>/graphql` speaks the graphql-ws protocol,
+//! - `http://<address>/events?query=...` streams the same results as
+//!   Server-Sent Events.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use juniper::InputValue;
+use tungstenite::handshake::server::{ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse};
+use tungstenite::http::HeaderValue;
+use tungstenite::{Message, WebSocket};
+use crate::changes::{ChangeEvent, EventBus};
+use crate::db::DbConnPool;
+use crate::graphql::{Context, ContextConfig};
+use crate::graphql::generator::UrlBuilders;
+use crate::graphql::persisted_queries::PersistedQueries;
+use crate::graphql::request::Extensions;
+use crate::syndication::RouteFormats;
+use crate::graphql::subscriptions::{subscription_schema, LiveSubscription, SubscriptionSchema};
+use crate::tenant::{Tenant, TenantLanguages, TENANT_HEADER};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8001";
+const DEFAULT_MAX_CONNECTIONS: usize = 1000;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct LiveServer {
+    schema: SubscriptionSchema,
+    pool: DbConnPool,
+    urls: UrlBuilders,
+    languages: TenantLanguages,
+    route_formats: RouteFormats,
+    config: ContextConfig,
+    persisted_queries: PersistedQueries,
+    bus: EventBus,
+    max_connections: usize,
+    connections: AtomicUsize,
+}
+
+/// Every connection has its own thread, connections beyond
+/// `PUBLISHER_LIVE_MAX_CONNECTIONS` are answered with a 503.
+pub fn spawn_from_env(
+    bus: EventBus,
+    pool: DbConnPool,
+    urls: UrlBuilders,
+    languages: TenantLanguages,
+    route_formats: RouteFormats,
+    config: ContextConfig,
+    persisted_queries: PersistedQueries,
+) {
+    let address = std::env::var("PUBLISHER_LIVE_ADDRESS").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string());
+
+    if address.is_empty() {
+        return;
+    }
+
+    let max_connections = std::env::var("PUBLISHER_LIVE_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+
+    let listener = TcpListener::bind(&address).expect("failed to bind PUBLISHER_LIVE_ADDRESS");
+    let server = Arc::new(LiveServer {
+        schema: subscription_schema(),
+        pool,
+        urls,
+        languages,
+        route_formats,
+        config,
+        persisted_queries,
+        bus,
+        max_connections,
+        connections: AtomicUsize::new(0),
+    });
+
+    thread::Builder::new()
+        .name("live-server".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                if server.connections.fetch_add(1, Ordering::SeqCst) >= server.max_connections {
+                    server.connections.fetch_sub(1, Ordering::SeqCst);
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                    continue;
+                }
+
+                let server = server.clone();
+                thread::spawn(move || {
+                    if let Err(error) = server.handle(stream) {
+                        eprintln!("live server: {}", error);
+                    }
+                    server.connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        })
+        .expect("failed to spawn live server thread");
+}
+
+impl LiveServer {
+    /// Executes the subscription of the tenant's client for the event.
+    fn execute(
+        &self,
+        subscription: &LiveSubscription,
+        tenant: &Tenant,
+        event: &ChangeEvent,
+    ) -> Option<serde_json::Value> {
+        let db_con = match self.pool.get() {
+            Ok(db_con) => db_con,
+            Err(error) => return Some(serde_json::json!({ "errors": [{ "message": error.to_string() }] })),
+        };
+
+        let context = Context::for_tenant(db_con, tenant, &self.urls, &self.route_formats, &self.config)
+            .for_event(event.clone());
+
+        subscription.execute(&self.schema, &context)
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut head = [0; 4096];
+        let length = stream.peek(&mut head)?;
+        let head = String::from_utf8_lossy(&head[..length]).to_lowercase();
+
+        if head.contains("upgrade: websocket") {
+            self.handle_websocket(stream)
+        } else {
+            self.handle_event_stream(stream)
+        }
+    }
+
+    fn handle_websocket(&self, stream: TcpStream) -> io::Result<()> {
+        let mut tenant = None;
+        let accept_protocol = |request: &HandshakeRequest, mut response: HandshakeResponse| -> Result<HandshakeResponse, ErrorResponse> {
+            let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+            tenant = Some(self.languages.resolve(header(TENANT_HEADER), header("Host")));
+
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("graphql-ws"));
+            Ok(response)
+        };
+
+        let mut websocket = tungstenite::accept_hdr(stream, accept_protocol)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+        websocket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+        GraphQLWsConnection {
+            server: self,
+            tenant: tenant.unwrap_or_else(|| self.languages.resolve(None, None)),
+            websocket: &mut websocket,
+            events: self.bus.subscribe(),
+            subscriptions: HashMap::new(),
+        }
+        .run()
+    }
+
+    fn handle_event_stream(&self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+
+            if let Some(index) = header.find(':') {
+                headers.insert(header[..index].trim().to_lowercase(), header[index + 1..].trim().to_string());
+            }
+        }
+        let header = |name: &str| headers.get(&name.to_lowercase()).map(String::as_str);
+        let tenant = self.languages.resolve(header(TENANT_HEADER), header("Host"));
+
+        let mut stream = stream;
+        let target = request_line.split_whitespace().nth(1).unwrap_or("");
+        let (path, query) = match target.find('?') {
+            Some(index) => (&target[..index], &target[index + 1..]),
+            None => (target, ""),
+        };
+
+        if !request_line.starts_with("GET ") || path != "/events" {
+            return stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        }
+
+        let params: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        let subscription = match self.event_stream_subscription(&params) {
+            Ok(subscription) => subscription,
+            Err(error) => {
+                let body = serde_json::json!({ "errors": [{ "message": error }] }).to_string();
+                return write!(
+                    stream,
+                    "HTTP/1.1 400 Bad Request\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        };
+
+        let events = self.bus.subscribe();
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\nAccess-Control-Allow-Origin: *\r\n\r\n",
+        )?;
+        stream.flush()?;
+
+        loop {
+            match events.recv_timeout(KEEP_ALIVE_INTERVAL) {
+                Ok(event) => {
+                    if let Some(result) = self.execute(&subscription, &tenant, &event) {
+                        write!(stream, "event: next\ndata: {}\n\n", result)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => stream.write_all(b": keep-alive\n\n")?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            stream.flush()?;
+        }
+    }
+
+    /// The subscription of the `query`, `operationName`, `variables` and
+    /// `extensions` query parameters.
+    fn event_stream_subscription(&self, params: &HashMap<String, String>) -> Result<LiveSubscription, String> {
+        let variables = match params.get("variables") {
+            Some(variables) => Some(
+                serde_json::from_str::<InputValue>(variables)
+                    .map_err(|error| format!("invalid `variables` parameter: {}", error))?,
+            ),
+            None => None,
+        };
+        let extensions = match params.get("extensions") {
+            Some(extensions) => Some(
+                serde_json::from_str::<Extensions>(extensions)
+                    .map_err(|error| format!("invalid `extensions` parameter: {}", error))?,
+            ),
+            None => None,
+        };
+
+        self.subscription(params.get("query"), params.get("operationName").cloned(), variables, extensions.as_ref())
+    }
+
+    /// The subscription of a document allowed by the persisted queries.
+    fn subscription(
+        &self,
+        query: Option<&String>,
+        operation_name: Option<String>,
+        variables: Option<InputValue>,
+        extensions: Option<&Extensions>,
+    ) -> Result<LiveSubscription, String> {
+        let query = self.persisted_queries.resolve(query, extensions).map_err(|error| error.to_string())?;
+
+        LiveSubscription::new(&query, operation_name, variables)
+    }
+}
+
+/// One client of the graphql-ws (subscriptions-transport-ws) protocol.
+struct GraphQLWsConnection<'a> {
+    server: &'a LiveServer,
+    /// Resolved from the handshake's headers.
+    tenant: Tenant,
+    websocket: &'a mut WebSocket<TcpStream>,
+    events: Receiver<crate::changes::ChangeEvent>,
+    subscriptions: HashMap<String, LiveSubscription>,
+}
+
+#[derive(Deserialize)]
+struct ClientMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    id: Option<String>,
+    payload: Option<StartPayload>,
+}
+
+#[derive(Deserialize)]
+struct StartPayload {
+    query: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    extensions: Option<Extensions>,
+}
+
+impl<'a> GraphQLWsConnection<'a> {
+    fn run(mut self) -> io::Result<()> {
+        let mut last_keep_alive = Instant::now();
+
+        loop {
+            match self.websocket.read_message() {
+                Ok(Message::Text(text)) => {
+                    if !self.handle_message(&text)? {
+                        return Ok(());
+                    }
+                }
+                Ok(Message::Close(_)) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(ref error))
+                    if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut => {}
+                Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            }
+
+            loop {
+                match self.events.try_recv() {
+                    Ok(event) => {
+                        let results = self
+                            .subscriptions
+                            .iter()
+                            .filter_map(|(id, subscription)| {
+                                self.server
+                                    .execute(subscription, &self.tenant, &event)
+                                    .map(|result| (id.clone(), result))
+                            })
+                            .collect::<Vec<_>>();
+
+                        for (id, result) in results {
+                            self.send(serde_json::json!({ "type": "data", "id": id, "payload": result }))?;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+
+            if last_keep_alive.elapsed() >= KEEP_ALIVE_INTERVAL {
+                self.send(serde_json::json!({ "type": "ka" }))?;
+                last_keep_alive = Instant::now();
+            }
+        }
+    }
+
+    /// Returns `false` when the client terminated the connection.
+    fn handle_message(&mut self, text: &str) -> io::Result<bool> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(error) => {
+                self.send(serde_json::json!({ "type": "connection_error", "payload": { "message": error.to_string() } }))?;
+                return Ok(true);
+            }
+        };
+
+        match (message.kind.as_str(), message.id) {
+            ("connection_init", _) => {
+                self.send(serde_json::json!({ "type": "connection_ack" }))?;
+                self.send(serde_json::json!({ "type": "ka" }))?;
+            }
+            ("start", Some(id)) => {
+                let subscription = message
+                    .payload
+                    .ok_or_else(|| "missing payload".to_string())
+                    .and_then(|payload| {
+                        self.server.subscription(
+                            payload.query.as_ref(),
+                            payload.operation_name,
+                            payload.variables,
+                            payload.extensions.as_ref(),
+                        )
+                    });
+
+                match subscription {
+                    Ok(subscription) => {
+                        self.subscriptions.insert(id, subscription);
+                    }
+                    Err(error) => {
+                        self.send(serde_json::json!({ "type": "error", "id": id, "payload": { "message": error } }))?;
+                    }
+                }
+            }
+            ("stop", Some(id)) => {
+                if self.subscriptions.remove(&id).is_some() {
+                    self.send(serde_json::json!({ "type": "complete", "id": id }))?;
+                }
+            }
+            ("connection_terminate", _) => return Ok(false),
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn send(&mut self, message: serde_json::Value) -> io::Result<()> {
+        self.websocket
+            .write_message(Message::Text(message.to_string()))
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+    }
+}
@@ -8,23 +8,119 @@ extern crate serde_json;
 #[macro_use]
 extern crate diesel;
 
+mod avatars;
+mod changes;
+mod dates;
 mod graphql;
 mod db;
+mod feeds;
+mod live;
 mod routes;
 mod models;
+mod oembed;
+mod page_views;
+mod response;
 mod schema;
+mod sitemaps;
+mod syndication;
+mod tenant;
+mod webhooks;
+mod xml;
 
+use crate::changes::EventBus;
 use crate::graphql::*;
+use crate::graphql::cache::ResponseCache;
+use crate::graphql::cache_control::CacheControl;
+use crate::graphql::generator::UrlBuilders;
+use crate::graphql::persisted_queries::PersistedQueries;
+use crate::page_views::PageViewTracker;
+use crate::sitemaps::SitemapConfig;
+use crate::syndication::RouteFormats;
+use crate::syndication::apple_news::AnfLayouts;
+use crate::syndication::wxr::ExportToken;
+use crate::tenant::TenantLanguages;
 
 fn main() {
     dotenv::dotenv().ok();
+
+    let db_pool = db::db_pool();
+    let urls = UrlBuilders::from_env().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let languages = TenantLanguages::from_env();
+    let route_formats = RouteFormats::from_env();
+    let persisted_queries = PersistedQueries::from_env();
+    let context_config = ContextConfig::from_env();
+
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("export-wxr") {
+        if let Err(error) =
+            syndication::wxr::export_command(&args[2..], &db_pool, &urls, &languages, &route_formats, &context_config)
+        {
+            eprintln!("export-wxr: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let event_bus = EventBus::new();
+    changes::listener::spawn_from_env(event_bus.clone());
+    live::spawn_from_env(
+        event_bus.clone(),
+        db_pool.clone(),
+        urls.clone(),
+        languages.clone(),
+        route_formats.clone(),
+        context_config.clone(),
+        persisted_queries.clone(),
+    );
+    webhooks::spawn_from_env(
+        event_bus.clone(),
+        db_pool.clone(),
+        urls.clone(),
+        route_formats.clone(),
+        context_config.clone(),
+    );
+    let page_views = PageViewTracker::spawn_from_env(db_pool.clone());
+    let response_cache = ResponseCache::spawn_from_env(&event_bus);
+
     rocket::ignite()
-        .manage(db::db_pool())
+        .manage(db_pool)
+        .manage(urls)
+        .manage(languages)
+        .manage(route_formats)
+        .manage(context_config)
+        .manage(SitemapConfig::from_env())
+        .manage(AnfLayouts::from_env())
+        .manage(ExportToken::from_env())
         .manage(Schema::new(Query, Mutation))
+        .manage(persisted_queries)
+        .manage(response_cache)
+        .manage(CacheControl::from_env())
+        .manage(event_bus)
+        .manage(page_views)
         .mount("/", routes![
             routes::graphiql,
             routes::post_graphql_handler,
-            routes::get_graphql_handler
+            routes::get_graphql_handler,
+            routes::publisher_webhook_handler,
+            routes::route_feed,
+            routes::author_feed,
+            routes::keyword_feed,
+            routes::sitemap_index,
+            routes::sitemap,
+            routes::oembed,
+            routes::embed_card,
+            routes::author_avatar,
+            routes::article_ninjs,
+            routes::apple_news_article,
+            routes::apple_news_bundle,
+            routes::instant_article,
+            routes::instant_articles_feed,
+            routes::amp_article,
+            routes::wxr_export,
+            routes::page_view_pixel
         ])
         .launch();
 }
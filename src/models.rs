@@ -50,6 +50,7 @@ pub struct Route {
     pub id: i32,
     pub name: String,
     pub slug: String,
+    pub staticprefix: Option<String>,
     // pub r#type: String,
 }
 
@@ -59,6 +60,12 @@ pub struct Statistics {
     pub id: i32,
     pub article_id: i32,
     pub page_views_number: i32,
+    pub impressions_number: i32,
+    /// Percentage of impressions (in lists of articles of the website)
+    /// followed by a view.
+    pub internal_click_rate: Option<f64>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
 }
 
 #[derive(Identifiable, Queryable, Debug, Clone, PartialEq)]
@@ -244,7 +251,7 @@ impl_load_from_for_diesel_pg! {
         Keyword.id-> (swp_article_keyword.keyword_id, ArticleKeyword),
         Article.id-> (swp_article_keyword.article_id, ArticleKeyword),
 
-        //Article.id-> (swp_article_statistics.article_id, Article),
+        Article.id-> (swp_article_statistics.article_id, Statistics),
 
         ArticleKeyword.keyword_id-> (swp_keyword.id, Keyword),
         ArticleKeyword.article_id-> (swp_article.id, Article),
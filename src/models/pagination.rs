@@ -2,7 +2,7 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::*;
 use diesel::query_dsl::methods::LoadQuery;
-use diesel::sql_types::BigInt;
+use diesel::sql_types::{BigInt, Text};
 
 pub trait Paginate: Sized {
     fn paginate(self, page: i64) -> Paginated<Self>;
@@ -64,3 +64,67 @@ where
         Ok(())
     }
 }
+
+pub trait Explain: Sized {
+    fn explain(self) -> Explained<Self>;
+}
+
+impl<T> Explain for T {
+    fn explain(self) -> Explained<Self> {
+        Explained { query: self }
+    }
+}
+
+/// `EXPLAIN (FORMAT JSON)` of a query, the plan isn't executed.
+#[derive(Debug, Clone, Copy, QueryId)]
+pub struct Explained<T> {
+    query: T,
+}
+
+impl<T> Explained<T> {
+    /// Number of rows the planner expects the query to return, from the
+    /// table statistics.
+    pub fn estimated_rows(self, conn: &PgConnection) -> QueryResult<i64>
+    where
+        Self: LoadQuery<PgConnection, String>,
+    {
+        let plan = self.get_result::<String>(conn)?;
+        let plan = serde_json::from_str::<serde_json::Value>(&plan)
+            .map_err(|error| diesel::result::Error::DeserializationError(Box::new(error)))?;
+
+        Ok(plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or(0.0) as i64)
+    }
+}
+
+impl<T: Query> Query for Explained<T> {
+    type SqlType = Text;
+}
+
+impl<T> RunQueryDsl<PgConnection> for Explained<T> {}
+
+impl<T> QueryFragment<Pg> for Explained<T>
+where
+    T: QueryFragment<Pg>,
+{
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql("EXPLAIN (FORMAT JSON) ");
+        self.query.walk_ast(out.reborrow())
+    }
+}
+
+#[derive(QueryableByName)]
+struct EstimatedRows {
+    #[sql_type = "BigInt"]
+    rows: i64,
+}
+
+/// Number of rows of the table according to the statistics of the last
+/// `ANALYZE`, `None` when the table was never analyzed.
+pub fn estimated_table_rows(table_name: &str, conn: &PgConnection) -> QueryResult<Option<i64>> {
+    let estimate = diesel::sql_query("SELECT reltuples::bigint AS rows FROM pg_class WHERE oid = to_regclass($1)")
+        .bind::<Text, _>(table_name)
+        .get_result::<EstimatedRows>(conn)
+        .optional()?;
+
+    Ok(estimate.map(|estimate| estimate.rows).filter(|rows| *rows >= 0))
+}
@@ -0,0 +1,329 @@
+//! oEmbed provider (https://oembed.com) for article URLs, answering with
+//! `rich` embeds rendered as an iframe of the article's embed card.
+
+use diesel::prelude::*;
+use rocket::http::{ContentType, Status};
+use crate::graphql::generator::generate_asset_url;
+use crate::graphql::paths::{resolve_path, ResolvedPath};
+use crate::graphql::seo::plain_text;
+use crate::graphql::Context;
+use crate::models::Article as ArticleModel;
+use crate::response::CacheableResponse;
+use crate::schema::{swp_article, swp_article_author, swp_author, swp_image, swp_image_rendition, swp_route};
+use crate::xml::escape;
+
+const DEFAULT_WIDTH: u32 = 600;
+const DEFAULT_HEIGHT: u32 = 400;
+const DEFAULT_CACHE_AGE: u32 = 3600;
+const PUBLISHED: &str = "published";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OEmbedFormat {
+    Json,
+    Xml,
+}
+
+impl OEmbedFormat {
+    pub fn parse(format: Option<&str>) -> Option<Self> {
+        match format {
+            None | Some("json") => Some(OEmbedFormat::Json),
+            Some("xml") => Some(OEmbedFormat::Xml),
+            Some(_) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OEmbed {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    version: &'static str,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider_name: Option<String>,
+    provider_url: String,
+    cache_age: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_width: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail_height: Option<i32>,
+    html: String,
+    width: u32,
+    height: u32,
+}
+
+impl OEmbed {
+    pub fn render(&self, format: OEmbedFormat) -> String {
+        let value = serde_json::to_value(self).expect("failed to serialize oEmbed response");
+
+        match format {
+            OEmbedFormat::Json => value.to_string(),
+            OEmbedFormat::Xml => {
+                let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?><oembed>"#);
+                for (name, value) in value.as_object().expect("oEmbed response is an object") {
+                    let text = match value {
+                        serde_json::Value::String(text) => text.clone(),
+                        value => value.to_string(),
+                    };
+                    xml.push_str(&format!("<{0}>{1}</{0}>", name, escape(&text)));
+                }
+                xml.push_str("</oembed>");
+
+                xml
+            }
+        }
+    }
+}
+
+fn cache_age() -> u32 {
+    std::env::var("PUBLISHER_OEMBED_CACHE_AGE")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_CACHE_AGE)
+}
+
+/// Finds the published article the URL points at, following redirects of
+/// moved articles.
+fn find_article(url: &str, context: &Context) -> QueryResult<Option<ArticleModel>> {
+    match resolve_path(url, &context.urls, &context.db_con)? {
+        Some(ResolvedPath::Article(article)) => Ok(Some(article)),
+        Some(ResolvedPath::Redirect { location, .. }) => match resolve_path(&location, &context.urls, &context.db_con)? {
+            Some(ResolvedPath::Article(article)) => Ok(Some(article)),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// URL, width and height of a rendition.
+type Rendition = (String, i32, i32);
+
+/// Renditions of the feature media, the widest first.
+fn renditions(article: &ArticleModel, conn: &PgConnection) -> QueryResult<Vec<Rendition>> {
+    let media_id = match article.feature_media {
+        Some(media_id) => media_id,
+        None => return Ok(Vec::new()),
+    };
+
+    let renditions = swp_image_rendition::table
+        .inner_join(swp_image::table)
+        .filter(swp_image_rendition::media_id.eq(media_id))
+        .select((swp_image::asset_id, swp_image::file_extension, swp_image_rendition::width, swp_image_rendition::height))
+        .order(swp_image_rendition::width.desc())
+        .load::<(String, String, i32, i32)>(conn)?;
+
+    Ok(renditions
+        .into_iter()
+        .map(|(asset_id, file_extension, width, height)| (generate_asset_url(&asset_id, &file_extension), width, height))
+        .collect())
+}
+
+/// Largest of the renditions (the widest first) fitting the size, `None`
+/// when none does.
+fn fitting_rendition(renditions: &[Rendition], max_width: Option<u32>, max_height: Option<u32>) -> Option<&Rendition> {
+    let fits = |width: i32, height: i32| {
+        max_width.map_or(true, |max_width| width as u32 <= max_width)
+            && max_height.map_or(true, |max_height| height as u32 <= max_height)
+    };
+
+    renditions.iter().find(|(_, width, height)| fits(*width, *height))
+}
+
+/// Size of the embed, never larger than the consumer's maximum.
+fn frame_size(max_size: Option<u32>, default_size: u32) -> u32 {
+    max_size.map_or(default_size, |max_size| max_size.min(default_size))
+}
+
+fn author_names(article_id: i32, conn: &PgConnection) -> QueryResult<Vec<String>> {
+    swp_article_author::table
+        .inner_join(swp_author::table)
+        .filter(swp_article_author::article_id.eq(article_id))
+        .select(swp_author::name)
+        .load::<String>(conn)
+}
+
+pub fn oembed_response(
+    url: &str,
+    format: Option<&str>,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    context: &Context,
+) -> Result<CacheableResponse, Status> {
+    let format = OEmbedFormat::parse(format).ok_or(Status::NotImplemented)?;
+    let article = find_article(url, context).map_err(internal_error)?.ok_or(Status::NotFound)?;
+    let conn = &context.db_con;
+
+    let width = frame_size(max_width, DEFAULT_WIDTH);
+    let height = frame_size(max_height, DEFAULT_HEIGHT);
+    let renditions = renditions(&article, conn).map_err(internal_error)?;
+    let thumbnail = fitting_rendition(&renditions, max_width, max_height);
+    let authors = author_names(article.id, conn).map_err(internal_error)?;
+
+    let html = format!(
+        r#"<iframe src="{}" width="{}" height="{}" frameborder="0" scrolling="no" title="{}"></iframe>"#,
+        escape(&context.urls.api_url(&format!("/embed/articles/{}", article.id))),
+        width,
+        height,
+        escape(&article.title)
+    );
+
+    let oembed = OEmbed {
+        kind: "rich",
+        version: "1.0",
+        title: article.title.clone(),
+        author_name: if authors.is_empty() { None } else { Some(authors.join(", ")) },
+        provider_name: context.organization.as_ref().map(|organization| organization.name.clone()),
+        provider_url: context.urls.site_url("/"),
+        cache_age: cache_age(),
+        thumbnail_url: thumbnail.map(|(url, _, _)| url.clone()),
+        thumbnail_width: thumbnail.map(|(_, width, _)| *width),
+        thumbnail_height: thumbnail.map(|(_, _, height)| *height),
+        html,
+        width,
+        height,
+    };
+
+    let body = oembed.render(format);
+    let content_type = match format {
+        OEmbedFormat::Json => ContentType::JSON,
+        OEmbedFormat::Xml => ContentType::new("text", "xml"),
+    };
+
+    context.record_key(format!("article-{}", article.id));
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(content_type)
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", cache_age()))
+        .last_modified(article.updated_at.or(article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+/// Embed card of the article, the document framed by oEmbed consumers.
+pub fn embed_card_response(id: i32, context: &Context) -> Result<CacheableResponse, Status> {
+    let conn = &context.db_con;
+    let article = swp_article::table
+        .find(id)
+        .filter(swp_article::status.eq(PUBLISHED))
+        .first::<ArticleModel>(conn)
+        .optional()
+        .map_err(internal_error)?
+        .ok_or(Status::NotFound)?;
+
+    let route_slug = swp_route::table
+        .find(article.route_id)
+        .select(swp_route::slug)
+        .first::<String>(conn)
+        .map_err(internal_error)?;
+    let url = context.urls.article_url(&route_slug, &article.slug, article.id, article.published_at);
+    let renditions = renditions(&article, conn).map_err(internal_error)?;
+    // the card scales its image down, so it falls back to the smallest rendition
+    let thumbnail = fitting_rendition(&renditions, Some(DEFAULT_WIDTH), None).or_else(|| renditions.last());
+    let authors = author_names(article.id, conn).map_err(internal_error)?;
+    let provider = context
+        .organization
+        .as_ref()
+        .map(|organization| organization.name.clone())
+        .unwrap_or_else(|| context.urls.site_url("/"));
+
+    let image = thumbnail
+        .map(|(src, _, _)| format!(r#"<img src="{}" alt="">"#, escape(src)))
+        .unwrap_or_default();
+    let byline = if authors.is_empty() {
+        String::new()
+    } else {
+        format!(r#"<p class="byline">{}</p>"#, escape(&authors.join(", ")))
+    };
+
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<link rel="canonical" href="{url}">
+<title>{title}</title>
+<style>
+body {{ margin: 0; font-family: sans-serif; color: #222; }}
+a {{ color: inherit; text-decoration: none; display: block; border: 1px solid #ddd; border-radius: 4px; overflow: hidden; }}
+img {{ width: 100%; max-height: 55vh; object-fit: cover; display: block; }}
+.content {{ padding: 12px 16px; }}
+h1 {{ font-size: 20px; margin: 0 0 8px; }}
+p {{ margin: 0 0 8px; font-size: 14px; line-height: 1.4; }}
+.byline, .provider {{ color: #666; font-size: 12px; }}
+</style>
+</head>
+<body>
+<a href="{url}" target="_blank" rel="noopener">
+{image}
+<div class="content">
+<h1>{title}</h1>
+<p>{lead}</p>
+{byline}
+<p class="provider">{provider}</p>
+</div>
+</a>
+</body>
+</html>
+"#,
+        url = escape(&url),
+        title = escape(&article.title),
+        image = image,
+        lead = escape(&plain_text(&article.lead)),
+        byline = byline,
+        provider = escape(&provider),
+    );
+
+    context.record_key(format!("article-{}", article.id));
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(ContentType::HTML)
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", cache_age()))
+        .last_modified(article.updated_at.or(article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+fn internal_error(error: diesel::result::Error) -> Status {
+    eprintln!("oembed: {}", error);
+    Status::InternalServerError
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renditions() -> Vec<Rendition> {
+        [(1200, 800), (800, 533), (400, 267)]
+            .iter()
+            .map(|(width, height)| (format!("https://cdn.example.com/{}.jpg", width), *width, *height))
+            .collect()
+    }
+
+    #[test]
+    fn frame_is_never_larger_than_the_maximum() {
+        assert_eq!(frame_size(None, DEFAULT_WIDTH), DEFAULT_WIDTH);
+        assert_eq!(frame_size(Some(1000), DEFAULT_WIDTH), DEFAULT_WIDTH);
+        assert_eq!(frame_size(Some(320), DEFAULT_WIDTH), 320);
+        assert_eq!(frame_size(Some(100), DEFAULT_HEIGHT), 100);
+    }
+
+    #[test]
+    fn picks_the_largest_fitting_rendition() {
+        let renditions = renditions();
+
+        assert_eq!(fitting_rendition(&renditions, None, None).map(|(_, width, _)| *width), Some(1200));
+        assert_eq!(fitting_rendition(&renditions, Some(1000), None).map(|(_, width, _)| *width), Some(800));
+        assert_eq!(fitting_rendition(&renditions, Some(1000), Some(300)).map(|(_, width, _)| *width), Some(400));
+    }
+
+    #[test]
+    fn leaves_out_the_thumbnail_when_no_rendition_fits() {
+        assert_eq!(fitting_rendition(&renditions(), Some(300), None), None);
+        assert_eq!(fitting_rendition(&renditions(), None, Some(200)), None);
+    }
+}
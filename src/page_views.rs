@@ -0,0 +1,452 @@
+//! Page view tracking, replacing Publisher's PHP tracker.
+//!
+//! Views are recorded through the `recordPageView` mutation or the tracking
+//! pixel, buffered in memory and written to Postgres in batches: the
+//! articles' `page_views_number` is incremented and a `pageview` event is
+//! inserted per view. Bots are ignored, and a visitor viewing the same
+//! article again within the deduplication window is only counted once.
+//! Visitors are only known by a salted hash kept in memory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor as IoCursor;
+use std::process;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use chrono::prelude::*;
+use diesel::prelude::*;
+use rocket::{
+    http::{ContentType, Header},
+    request::{self, FromRequest, Request},
+    response::{self, Responder, Response},
+    Outcome, State,
+};
+use sha2::{Digest, Sha256};
+use crate::db::DbConnPool;
+use crate::schema::{swp_article, swp_article_events, swp_article_statistics};
+
+const DEFAULT_FLUSH_INTERVAL: u64 = 10;
+const DEFAULT_BATCH_SIZE: usize = 500;
+const DEFAULT_MAX_BUFFERED: usize = 10_000;
+const DEFAULT_MAX_VISITORS: usize = 100_000;
+const DEFAULT_DEDUPLICATION_WINDOW: u64 = 30 * 60;
+const PAGE_VIEW_ACTION: &str = "pageview";
+
+/// Transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff,
+    0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00,
+    0x00, 0x02, 0x01, 0x44, 0x00, 0x3b,
+];
+
+/// Lowercased fragments of the user agents of crawlers, link previews and
+/// headless browsers.
+const BOT_USER_AGENTS: &[&str] = &[
+    "bot", "crawl", "spider", "slurp", "preview", "facebookexternalhit", "embedly", "headless", "lighthouse",
+    "pingdom", "monitor", "curl/", "wget/", "python-requests", "go-http-client", "java/", "okhttp", "feedfetcher",
+];
+
+/// Where the reader came from, stored as the event's `page_view_source`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageViewSource {
+    /// From another page of the website.
+    Internal,
+    External,
+}
+
+impl PageViewSource {
+    pub fn from_referrer(referrer: Option<&str>, site_url: &str) -> Self {
+        let host = |url: &str| url::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_lowercase));
+
+        match (referrer.and_then(host), host(site_url)) {
+            (Some(referrer), Some(site)) if referrer == site => PageViewSource::Internal,
+            _ => PageViewSource::External,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            PageViewSource::Internal => "internal",
+            PageViewSource::External => "external",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hit {
+    article_id: i32,
+    source: PageViewSource,
+    at: NaiveDateTime,
+}
+
+#[derive(Default)]
+struct Buffer {
+    hits: Vec<Hit>,
+    /// Salted hashes of article and visitor, with the time they were counted.
+    seen: HashMap<Vec<u8>, Instant>,
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    batch_ready: Condvar,
+}
+
+/// Handle on the buffer, managed by Rocket. The writer thread is started by
+/// `spawn_from_env`.
+#[derive(Clone)]
+pub struct PageViewTracker {
+    shared: Arc<Shared>,
+    salt: String,
+    batch_size: usize,
+    max_buffered: usize,
+    max_visitors: usize,
+    deduplication_window: Duration,
+}
+
+impl PageViewTracker {
+    pub fn spawn_from_env(pool: DbConnPool) -> Self {
+        let env = |name: &str, default: u64| {
+            std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+        };
+
+        let tracker = PageViewTracker {
+            shared: Arc::new(Shared {
+                buffer: Mutex::new(Buffer::default()),
+                batch_ready: Condvar::new(),
+            }),
+            salt: std::env::var("PUBLISHER_PAGE_VIEWS_SALT").unwrap_or_else(|_| random_salt()),
+            batch_size: env("PUBLISHER_PAGE_VIEWS_BATCH_SIZE", DEFAULT_BATCH_SIZE as u64) as usize,
+            max_buffered: env("PUBLISHER_PAGE_VIEWS_MAX_BUFFERED", DEFAULT_MAX_BUFFERED as u64) as usize,
+            max_visitors: env("PUBLISHER_PAGE_VIEWS_MAX_VISITORS", DEFAULT_MAX_VISITORS as u64) as usize,
+            deduplication_window: Duration::from_secs(env(
+                "PUBLISHER_PAGE_VIEWS_DEDUPLICATION_WINDOW",
+                DEFAULT_DEDUPLICATION_WINDOW,
+            )),
+        };
+        let flush_interval = Duration::from_secs(env("PUBLISHER_PAGE_VIEWS_FLUSH_INTERVAL", DEFAULT_FLUSH_INTERVAL));
+
+        let writer = tracker.clone();
+        thread::Builder::new()
+            .name("page-views-writer".to_string())
+            .spawn(move || writer.write(pool, flush_interval))
+            .expect("failed to spawn page views writer thread");
+
+        tracker
+    }
+
+    /// Buffers the view, returns whether it was counted. Views are dropped
+    /// while the deduplication window holds `max_visitors` visitors.
+    pub fn record(&self, article_id: i32, source: PageViewSource, visitor: &str) -> bool {
+        let key = Sha256::new()
+            .chain(self.salt.as_bytes())
+            .chain(article_id.to_string().as_bytes())
+            .chain(b":")
+            .chain(visitor.as_bytes())
+            .result()
+            .to_vec();
+        let now = Instant::now();
+
+        let mut buffer = self.shared.buffer.lock().expect("page views buffer lock poisoned");
+
+        if buffer.hits.len() >= self.max_buffered {
+            return false;
+        }
+
+        match buffer.seen.get(&key) {
+            Some(seen) if now.duration_since(*seen) < self.deduplication_window => return false,
+            Some(_) => {}
+            None if buffer.seen.len() >= self.max_visitors => {
+                let window = self.deduplication_window;
+                buffer.seen.retain(|_, seen| now.duration_since(*seen) < window);
+
+                if buffer.seen.len() >= self.max_visitors {
+                    return false;
+                }
+            }
+            None => {}
+        }
+        buffer.seen.insert(key, now);
+
+        buffer.hits.push(Hit {
+            article_id,
+            source,
+            at: Utc::now().naive_utc(),
+        });
+
+        if buffer.hits.len() >= self.batch_size {
+            self.shared.batch_ready.notify_one();
+        }
+
+        true
+    }
+
+    fn write(&self, pool: DbConnPool, flush_interval: Duration) {
+        loop {
+            let hits = {
+                let buffer = self.shared.buffer.lock().expect("page views buffer lock poisoned");
+                let (mut buffer, _) = self
+                    .shared
+                    .batch_ready
+                    .wait_timeout(buffer, flush_interval)
+                    .expect("page views buffer lock poisoned");
+
+                let window = self.deduplication_window;
+                buffer.seen.retain(|_, seen| seen.elapsed() < window);
+
+                std::mem::replace(&mut buffer.hits, Vec::new())
+            };
+
+            if hits.is_empty() {
+                continue;
+            }
+
+            let count = hits.len();
+            let result = pool
+                .get()
+                .map_err(|error| error.to_string())
+                .and_then(|conn| flush(hits, &conn).map_err(|error| error.to_string()));
+
+            if let Err(error) = result {
+                eprintln!("page views: dropped {} views: {}", count, error);
+            }
+        }
+    }
+}
+
+fn flush(hits: Vec<Hit>, conn: &PgConnection) -> QueryResult<()> {
+    let mut views: BTreeMap<i32, i32> = BTreeMap::new();
+    for hit in &hits {
+        *views.entry(hit.article_id).or_insert(0) += 1;
+    }
+
+    let now = Utc::now().naive_utc();
+
+    conn.transaction(|| {
+        // views of unknown articles are ignored
+        let article_ids = swp_article::table
+            .filter(swp_article::id.eq_any(views.keys().cloned().collect::<Vec<_>>()))
+            .select(swp_article::id)
+            .load::<i32>(conn)?;
+
+        let mut statistics_ids = HashMap::new();
+
+        for article_id in article_ids {
+            let count = views[&article_id];
+            let updated = diesel::update(swp_article_statistics::table.filter(swp_article_statistics::article_id.eq(article_id)))
+                .set((
+                    swp_article_statistics::page_views_number.eq(swp_article_statistics::page_views_number + count),
+                    swp_article_statistics::updated_at.eq(now),
+                ))
+                .returning(swp_article_statistics::id)
+                .get_result::<i32>(conn)
+                .optional()?;
+
+            let statistics_id = match updated {
+                Some(id) => id,
+                None => diesel::insert_into(swp_article_statistics::table)
+                    .values((
+                        swp_article_statistics::article_id.eq(article_id),
+                        swp_article_statistics::page_views_number.eq(count),
+                        swp_article_statistics::impressions_number.eq(0),
+                        swp_article_statistics::created_at.eq(now),
+                    ))
+                    .returning(swp_article_statistics::id)
+                    .get_result::<i32>(conn)?,
+            };
+
+            statistics_ids.insert(article_id, statistics_id);
+        }
+
+        let events = hits
+            .iter()
+            .filter_map(|hit| {
+                statistics_ids.get(&hit.article_id).map(|statistics_id| {
+                    (
+                        swp_article_events::article_statistics_id.eq(*statistics_id),
+                        swp_article_events::action.eq(PAGE_VIEW_ACTION),
+                        swp_article_events::page_view_source.eq(hit.source.as_str()),
+                        swp_article_events::created_at.eq(hit.at),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(swp_article_events::table).values(events).execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// Salt used when `PUBLISHER_PAGE_VIEWS_SALT` is not set, it changes on every
+/// start so the hashes can't be compared across restarts.
+fn random_salt() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    format!(
+        "{:x}",
+        Sha256::new()
+            .chain(now.as_nanos().to_string().as_bytes())
+            .chain(process::id().to_string().as_bytes())
+            .result()
+    )
+}
+
+pub fn is_bot(user_agent: Option<&str>) -> bool {
+    match user_agent.map(str::trim) {
+        None | Some("") => true,
+        Some(user_agent) => {
+            let user_agent = user_agent.to_lowercase();
+            BOT_USER_AGENTS.iter().any(|fragment| user_agent.contains(fragment))
+        }
+    }
+}
+
+/// The tracker with what the request tells about the visitor, available to
+/// the GraphQL resolvers through the `Context`.
+#[derive(Clone)]
+pub struct PageViewRecorder {
+    tracker: PageViewTracker,
+    user_agent: Option<String>,
+    client_address: Option<String>,
+}
+
+impl PageViewRecorder {
+    /// Records a view unless it comes from a bot. Without a hash computed by
+    /// the client, visitors are identified by their address and user agent.
+    pub fn record(&self, article_id: i32, referrer: Option<&str>, visitor_hash: Option<&str>, site_url: &str) -> bool {
+        if is_bot(self.user_agent.as_ref().map(String::as_str)) {
+            return false;
+        }
+
+        let visitor = match visitor_hash.filter(|hash| !hash.is_empty()) {
+            Some(hash) => hash.to_string(),
+            None => format!(
+                "{}|{}",
+                self.client_address.as_ref().map_or("", String::as_str),
+                self.user_agent.as_ref().map_or("", String::as_str)
+            ),
+        };
+
+        self.tracker.record(article_id, PageViewSource::from_referrer(referrer, site_url), &visitor)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for PageViewRecorder {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<PageViewRecorder, ()> {
+        let tracker = request.guard::<State<PageViewTracker>>()?;
+
+        Outcome::Success(PageViewRecorder {
+            tracker: tracker.clone(),
+            user_agent: request.headers().get_one("User-Agent").map(str::to_owned),
+            client_address: request.client_ip().map(|address| address.to_string()),
+        })
+    }
+}
+
+/// Tracking pixel for pages without JavaScript (e.g. AMP's `amp-pixel`),
+/// never cached so every view reaches the API.
+pub struct TrackingPixel;
+
+impl<'r> Responder<'r> for TrackingPixel {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::GIF)
+            .header(Header::new("Cache-Control", "no-store, no-cache, must-revalidate"))
+            .sized_body(IoCursor::new(PIXEL))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(max_visitors: usize, deduplication_window: Duration) -> PageViewTracker {
+        PageViewTracker {
+            shared: Arc::new(Shared {
+                buffer: Mutex::new(Buffer::default()),
+                batch_ready: Condvar::new(),
+            }),
+            salt: "salt".to_string(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_buffered: DEFAULT_MAX_BUFFERED,
+            max_visitors,
+            deduplication_window,
+        }
+    }
+
+    fn buffered(tracker: &PageViewTracker) -> (usize, usize) {
+        let buffer = tracker.shared.buffer.lock().unwrap();
+
+        (buffer.hits.len(), buffer.seen.len())
+    }
+
+    #[test]
+    fn detects_bots() {
+        assert!(is_bot(None));
+        assert!(is_bot(Some("  ")));
+        assert!(is_bot(Some("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)")));
+        assert!(is_bot(Some("facebookexternalhit/1.1")));
+        assert!(is_bot(Some("curl/7.64.1")));
+        assert!(!is_bot(Some("Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:66.0) Gecko/20100101 Firefox/66.0")));
+    }
+
+    #[test]
+    fn tells_internal_from_external_referrers() {
+        let site_url = "https://www.example.com/";
+
+        assert_eq!(
+            PageViewSource::from_referrer(Some("https://WWW.example.com/news/storm"), site_url),
+            PageViewSource::Internal
+        );
+        assert_eq!(
+            PageViewSource::from_referrer(Some("https://www.google.com/"), site_url),
+            PageViewSource::External
+        );
+        assert_eq!(PageViewSource::from_referrer(Some("not a url"), site_url), PageViewSource::External);
+        assert_eq!(PageViewSource::from_referrer(None, site_url), PageViewSource::External);
+    }
+
+    #[test]
+    fn counts_views_of_a_visitor_once_per_window() {
+        let tracker = tracker(DEFAULT_MAX_VISITORS, Duration::from_secs(60));
+
+        assert!(tracker.record(1, PageViewSource::External, "visitor"));
+        assert!(!tracker.record(1, PageViewSource::Internal, "visitor"));
+        assert!(tracker.record(2, PageViewSource::External, "visitor"));
+        assert!(tracker.record(1, PageViewSource::External, "other visitor"));
+        assert_eq!(buffered(&tracker), (3, 3));
+    }
+
+    #[test]
+    fn counts_views_again_once_the_window_is_over() {
+        let tracker = tracker(DEFAULT_MAX_VISITORS, Duration::from_secs(0));
+
+        assert!(tracker.record(1, PageViewSource::External, "visitor"));
+        assert!(tracker.record(1, PageViewSource::External, "visitor"));
+        assert_eq!(buffered(&tracker), (2, 1));
+    }
+
+    #[test]
+    fn caps_the_visitors_of_the_window() {
+        let tracker = tracker(2, Duration::from_secs(60));
+
+        assert!(tracker.record(1, PageViewSource::External, "a"));
+        assert!(tracker.record(1, PageViewSource::External, "b"));
+        assert!(!tracker.record(1, PageViewSource::External, "c"));
+        assert!(!tracker.record(1, PageViewSource::External, "a"));
+        assert_eq!(buffered(&tracker), (2, 2));
+    }
+
+    #[test]
+    fn forgets_expired_visitors_when_the_window_is_full() {
+        let tracker = tracker(2, Duration::from_secs(0));
+
+        assert!(tracker.record(1, PageViewSource::External, "a"));
+        assert!(tracker.record(1, PageViewSource::External, "b"));
+        assert!(tracker.record(1, PageViewSource::External, "c"));
+        assert_eq!(buffered(&tracker), (3, 1));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+use std::io::Cursor as IoCursor;
+use chrono::prelude::*;
+use rocket::{
+    http::{ContentType, Header, Method, Status},
+    response::{self, Responder, Response},
+    Request,
+};
+use sha2::{Digest, Sha256};
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Response with the HTTP caching headers, answering conditional `GET`
+/// requests with `304 Not Modified`.
+pub struct CacheableResponse {
+    status: Status,
+    body: String,
+    content_type: ContentType,
+    etag: Option<String>,
+    cache_control: Option<String>,
+    last_modified: Option<NaiveDateTime>,
+    surrogate_keys: BTreeSet<String>,
+}
+
+impl CacheableResponse {
+    pub fn new(status: Status, body: String) -> Self {
+        CacheableResponse {
+            status,
+            body,
+            content_type: ContentType::JSON,
+            etag: None,
+            cache_control: None,
+            last_modified: None,
+            surrogate_keys: BTreeSet::new(),
+        }
+    }
+
+    pub fn error(status: Status, body: serde_json::Value) -> Self {
+        CacheableResponse::new(status, body.to_string()).cache_control("no-store".to_string())
+    }
+
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Sets a strong `ETag` computed from the given representation of the body.
+    pub fn etag_from(mut self, representation: &str) -> Self {
+        self.etag = Some(format!("\"{:x}\"", Sha256::digest(representation.as_bytes())));
+        self
+    }
+
+    pub fn cache_control(mut self, cache_control: String) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
+
+    pub fn last_modified(mut self, last_modified: Option<NaiveDateTime>) -> Self {
+        self.last_modified = last_modified;
+        self
+    }
+
+    pub fn surrogate_keys(mut self, surrogate_keys: BTreeSet<String>) -> Self {
+        self.surrogate_keys = surrogate_keys;
+        self
+    }
+}
+
+/// Adds `Surrogate-Key` (Fastly) and `Cache-Tag` (Cloudflare, Akamai) headers
+/// listing the entities the response contains.
+pub fn surrogate_key_headers(response: &mut response::ResponseBuilder<'_>, surrogate_keys: &BTreeSet<String>) {
+    if surrogate_keys.is_empty() {
+        return;
+    }
+
+    let keys = surrogate_keys.iter().map(String::as_str).collect::<Vec<_>>();
+    response.header(Header::new("Surrogate-Key", keys.join(" ")));
+    response.header(Header::new("Cache-Tag", keys.join(",")));
+}
+
+impl<'r> Responder<'r> for CacheableResponse {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let mut response = Response::build();
+        surrogate_key_headers(&mut response, &self.surrogate_keys);
+
+        if let Some(cache_control) = self.cache_control {
+            response.header(Header::new("Cache-Control", cache_control));
+        }
+
+        let is_conditional = request.method() == Method::Get && self.status == Status::Ok;
+        let headers = request.headers();
+        let mut not_modified = false;
+
+        if let Some(last_modified) = self.last_modified {
+            let last_modified = DateTime::<Utc>::from_utc(last_modified, Utc);
+            response.header(Header::new(
+                "Last-Modified",
+                last_modified.format(HTTP_DATE_FORMAT).to_string(),
+            ));
+
+            // If-Modified-Since is ignored when If-None-Match is present (RFC 7232)
+            not_modified = is_conditional
+                && headers.get_one("If-None-Match").is_none()
+                && headers
+                    .get_one("If-Modified-Since")
+                    .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+                    .map_or(false, |since| last_modified.timestamp() <= since.timestamp());
+        }
+
+        if let Some(etag) = self.etag {
+            not_modified = not_modified
+                || is_conditional
+                    && headers
+                        .get_one("If-None-Match")
+                        .map_or(false, |if_none_match| if_none_match_matches(if_none_match, &etag));
+
+            response.header(Header::new("ETag", etag));
+        }
+
+        if not_modified {
+            return response.status(Status::NotModified).ok();
+        }
+
+        response
+            .status(self.status)
+            .header(self.content_type)
+            .sized_body(IoCursor::new(self.body))
+            .ok()
+    }
+}
+
+fn if_none_match_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|candidate| candidate.trim().trim_start_matches("W/"))
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
@@ -1,27 +1,215 @@
 use rocket::{get, post};
+use rocket::http::Status;
 use rocket::response::content;
 use rocket::State;
-use super::graphql::{Context, Query, Schema};
+use super::avatars;
+use super::changes::EventBus;
+use super::changes::webhook::{Webhook, WebhookAuthentication};
+use super::feeds::{self, FeedSource};
+use super::oembed;
+use super::page_views::{PageViewRecorder, TrackingPixel};
+use super::response::CacheableResponse;
+use super::sitemaps::{self, SitemapBody, SitemapConfig, SitemapResponse, SitemapStream};
+use super::syndication::{amp, apple_news, instant_articles, Download};
+use super::syndication::apple_news::AnfLayouts;
+use super::syndication::wxr::{self, ExportAuthentication, WxrResponse};
+use super::graphql::{Context, Schema};
+use super::graphql::generator::UrlBuilders;
+use super::tenant::Tenant;
+use super::graphql::ninjs;
+use super::graphql::cache::{CacheScope, ResponseCache};
+use super::graphql::cache_control::CacheControl;
+use super::graphql::persisted_queries::PersistedQueries;
+use super::graphql::request::GraphQLRequest;
+use super::graphql::response::GraphQLResponse;
 
 #[get("/graphiql")]
 pub fn graphiql() -> content::Html<String> {
     juniper_rocket::graphiql_source("/graphql")
 }
 
-#[get("/graphql?<request>")]
+#[get("/graphql?<request..>")]
 pub fn get_graphql_handler(
     context: Context,
-    request: juniper_rocket::GraphQLRequest,
-    schema: State<Schema>
-) -> juniper_rocket::GraphQLResponse {
-    request.execute(&schema, &context)
+    request: GraphQLRequest,
+    schema: State<Schema>,
+    persisted_queries: State<PersistedQueries>,
+    cache: State<ResponseCache>,
+    scope: CacheScope,
+    cache_control: State<CacheControl>
+) -> GraphQLResponse {
+    request.execute(&schema, &context, &persisted_queries, &cache, &scope, &cache_control)
 }
 
 #[post("/graphql", data = "<request>")]
 pub fn post_graphql_handler(
     context: Context,
-    request: juniper_rocket::GraphQLRequest,
+    request: GraphQLRequest,
+    schema: State<Schema>,
+    persisted_queries: State<PersistedQueries>,
+    cache: State<ResponseCache>,
+    scope: CacheScope,
+    cache_control: State<CacheControl>
+) -> GraphQLResponse {
+    request.execute(&schema, &context, &persisted_queries, &cache, &scope, &cache_control)
+}
+
+#[post("/webhooks/publisher", data = "<webhook>")]
+pub fn publisher_webhook_handler(
+    _authentication: WebhookAuthentication,
+    webhook: Webhook,
+    bus: State<EventBus>
+) -> Status {
+    if let Some(event) = webhook.into_event() {
+        bus.publish(event);
+    }
+
+    Status::Accepted
+}
+
+#[get("/feeds/<file>?<items>")]
+pub fn route_feed(
+    file: String,
+    items: Option<i32>,
+    context: Context,
+    schema: State<Schema>
+) -> Result<CacheableResponse, Status> {
+    feeds::feed_response(FeedSource::Route, &file, items, &schema, &context)
+}
+
+#[get("/feeds/author/<file>?<items>")]
+pub fn author_feed(
+    file: String,
+    items: Option<i32>,
+    context: Context,
     schema: State<Schema>
-) -> juniper_rocket::GraphQLResponse {
-    request.execute(&schema, &context)
+) -> Result<CacheableResponse, Status> {
+    feeds::feed_response(FeedSource::Author, &file, items, &schema, &context)
+}
+
+#[get("/feeds/keyword/<file>?<items>")]
+pub fn keyword_feed(
+    file: String,
+    items: Option<i32>,
+    context: Context,
+    schema: State<Schema>
+) -> Result<CacheableResponse, Status> {
+    feeds::feed_response(FeedSource::Keyword, &file, items, &schema, &context)
+}
+
+#[get("/sitemap.xml")]
+pub fn sitemap_index(context: Context, config: State<SitemapConfig>) -> Result<SitemapResponse, Status> {
+    sitemaps::render_index(&context.db_con, &context.urls)
+        .map(|xml| SitemapResponse::new(SitemapBody::Complete(xml), &config))
+        .map_err(|error| {
+            eprintln!("sitemaps: {}", error);
+            Status::InternalServerError
+        })
+}
+
+#[get("/sitemaps/<file>")]
+pub fn sitemap(file: String, context: Context, config: State<SitemapConfig>) -> Result<SitemapResponse, Status> {
+    let body = match file.as_str() {
+        "routes.xml" => sitemaps::render_routes(&context.db_con, &context.urls).map(|xml| Some(SitemapBody::Complete(xml))),
+        "news.xml" => Ok(Some(SitemapBody::Streamed(SitemapStream::news(context.db_con, context.urls, &config)))),
+        _ => match sitemaps::parse_page(&file) {
+            Some(page) => SitemapStream::articles(context.db_con, context.urls, &config, page).map(|stream| stream.map(SitemapBody::Streamed)),
+            None => Ok(None),
+        },
+    };
+
+    body.map_err(|error| {
+        eprintln!("sitemaps: {}", error);
+        Status::InternalServerError
+    })?
+    .map(|body| SitemapResponse::new(body, &config))
+    .ok_or(Status::NotFound)
+}
+
+#[get("/oembed?<url>&<format>&<maxwidth>&<maxheight>")]
+pub fn oembed(
+    url: String,
+    format: Option<String>,
+    maxwidth: Option<u32>,
+    maxheight: Option<u32>,
+    context: Context
+) -> Result<CacheableResponse, Status> {
+    oembed::oembed_response(&url, format.as_ref().map(String::as_str), maxwidth, maxheight, &context)
+}
+
+#[get("/avatars/authors/<file>?<size>")]
+pub fn author_avatar(file: String, size: Option<u32>, context: Context) -> Result<CacheableResponse, Status> {
+    avatars::avatar_response(&file, size, &context)
+}
+
+#[get("/embed/articles/<id>")]
+pub fn embed_card(id: i32, context: Context) -> Result<CacheableResponse, Status> {
+    oembed::embed_card_response(id, &context)
+}
+
+#[get("/ninjs/articles/<id>?<version>")]
+pub fn article_ninjs(id: i32, version: Option<String>, context: Context) -> Result<CacheableResponse, Status> {
+    ninjs::ninjs_response(id, version.as_ref().map(String::as_str), &context)
+}
+
+#[get("/apple-news/articles/<id>/article.json")]
+pub fn apple_news_article(
+    id: i32,
+    context: Context,
+    layouts: State<AnfLayouts>,
+) -> Result<CacheableResponse, Status> {
+    apple_news::article_response(id, &layouts, &context)
+}
+
+#[get("/apple-news/bundle.tar?<route>&<limit>")]
+pub fn apple_news_bundle(
+    route: Option<i32>,
+    limit: Option<i64>,
+    context: Context,
+    layouts: State<AnfLayouts>,
+) -> Result<Download, Status> {
+    apple_news::bundle_response(route, limit, &layouts, &context)
+}
+
+#[get("/instant-articles/articles/<id>")]
+pub fn instant_article(id: i32, context: Context) -> Result<CacheableResponse, Status> {
+    instant_articles::article_response(id, &context)
+}
+
+#[get("/instant-articles/feed.rss?<route>&<limit>")]
+pub fn instant_articles_feed(route: Option<i32>, limit: Option<i64>, context: Context) -> Result<CacheableResponse, Status> {
+    instant_articles::feed_response(route, limit, &context)
+}
+
+#[get("/amp/articles/<id>")]
+pub fn amp_article(id: i32, context: Context) -> Result<CacheableResponse, Status> {
+    amp::article_response(id, &context)
+}
+
+#[get("/export/wordpress.xml?<route>&<keyword>&<from>&<to>")]
+pub fn wxr_export(
+    route: Option<i32>,
+    keyword: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    _authentication: ExportAuthentication,
+    context: Context
+) -> Result<WxrResponse, Status> {
+    wxr::export_response(route, keyword, from.as_ref().map(String::as_str), to.as_ref().map(String::as_str), context)
+}
+
+/// The pixel is served whether or not the view is counted.
+#[get("/page-views/<article_id>/pixel.gif?<referrer>&<visitor>")]
+pub fn page_view_pixel(
+    article_id: i32,
+    referrer: Option<String>,
+    visitor: Option<String>,
+    tenant: Tenant,
+    urls: State<UrlBuilders>,
+    recorder: PageViewRecorder
+) -> TrackingPixel {
+    let site_url = urls.for_tenant(&tenant.code).site_url("/");
+    recorder.record(article_id, referrer.as_ref().map(String::as_str), visitor.as_ref().map(String::as_str), &site_url);
+
+    TrackingPixel
 }
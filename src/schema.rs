@@ -22,6 +22,26 @@ table! {
         id -> Int4,
         name -> Varchar,
         slug -> Varchar,
+        staticprefix -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    swp_redirect_route (id) {
+        id -> Int4,
+        route_source_id -> Nullable<Int4>,
+        route_target_id -> Nullable<Int4>,
+        static_prefix -> Varchar,
+        uri_target -> Nullable<Varchar>,
+        permanent -> Bool,
+    }
+}
+
+table! {
+    swp_article_previous_relative_url (id) {
+        id -> Int4,
+        article_id -> Int4,
+        relative_url -> Varchar,
     }
 }
 
@@ -110,6 +130,24 @@ table! {
         id -> Int4,
         article_id -> Int4,
         page_views_number -> Int4,
+        impressions_number -> Int4,
+        internal_click_rate -> Nullable<Float8>,
+        created_at -> Timestamp,
+        updated_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    swp_article_events (id) {
+        id -> Int4,
+        article_statistics_id -> Int4,
+        action -> Varchar,
+        value -> Nullable<Varchar>,
+        impression_route_id -> Nullable<Int4>,
+        impression_article_id -> Nullable<Int4>,
+        impression_type -> Nullable<Varchar>,
+        page_view_source -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -190,6 +228,9 @@ joinable!(swp_article_sources -> swp_article (article_id));
 joinable!(swp_article_sources -> swp_article_source (source_id));
 
 joinable!(swp_article_statistics -> swp_article (article_id));
+joinable!(swp_article_events -> swp_article_statistics (article_statistics_id));
+
+joinable!(swp_article_previous_relative_url -> swp_article (article_id));
 
 allow_tables_to_appear_in_same_query!(
     swp_article,
@@ -203,11 +244,14 @@ allow_tables_to_appear_in_same_query!(
     swp_article_keyword,
     swp_keyword,
     swp_article_statistics,
+    swp_article_events,
     swp_article_seo_metadata,
     swp_article_seo_media,
     swp_article_related,
     swp_article_source,
     swp_article_sources,
     swp_slideshow,
-    swp_slideshow_item
+    swp_slideshow_item,
+    swp_redirect_route,
+    swp_article_previous_relative_url
 );
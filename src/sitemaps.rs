@@ -0,0 +1,392 @@
+//! XML sitemaps of the published articles and routes, and a Google News
+//! sitemap of the articles published in the last 48 hours.
+//!
+//! Article sitemaps are rendered in batches while the response is written, so
+//! memory usage does not depend on the number of articles.
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, Cursor as IoCursor, Read};
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::dsl::max;
+use diesel::prelude::*;
+use rocket::{
+    http::{ContentType, Header, Status},
+    response::{self, Responder, Response},
+    Request,
+};
+use crate::dates::format_date;
+use crate::db::DbConn;
+use crate::graphql::generator::{generate_asset_url, UrlBuilder};
+use crate::response::surrogate_key_headers;
+use crate::schema::{swp_article, swp_article_keyword, swp_article_media, swp_image, swp_keyword, swp_route};
+use crate::xml::escape;
+
+/// Maximum number of URLs in a single sitemap file.
+pub const URLS_PER_SITEMAP: i64 = 50_000;
+const NEWS_MAX_URLS: i64 = 1_000;
+const NEWS_MAX_AGE_HOURS: i64 = 48;
+const BATCH_SIZE: i64 = 1_000;
+const DEFAULT_MAX_AGE: u32 = 3600;
+const DEFAULT_PUBLICATION_NAME: &str = "Publisher";
+const DEFAULT_PUBLICATION_LANGUAGE: &str = "en";
+const PUBLISHED: &str = "published";
+
+const URLSET_OPEN: &str = r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">"#;
+const NEWS_URLSET_OPEN: &str = r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9" xmlns:news="http://www.google.com/schemas/sitemap-news/0.9">"#;
+const URLSET_CLOSE: &str = "</urlset>";
+
+/// Cache lifetime of the sitemaps and the publication of the news sitemap,
+/// read once at startup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapConfig {
+    max_age: u32,
+    news_publication_name: String,
+    news_publication_language: String,
+}
+
+impl SitemapConfig {
+    pub fn from_env() -> Self {
+        SitemapConfig {
+            max_age: std::env::var("PUBLISHER_SITEMAPS_MAX_AGE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_AGE),
+            news_publication_name: std::env::var("PUBLISHER_NEWS_PUBLICATION_NAME")
+                .unwrap_or_else(|_| DEFAULT_PUBLICATION_NAME.to_string()),
+            news_publication_language: std::env::var("PUBLISHER_NEWS_PUBLICATION_LANGUAGE")
+                .unwrap_or_else(|_| DEFAULT_PUBLICATION_LANGUAGE.to_string()),
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Clone, PartialEq)]
+struct SitemapArticle {
+    id: i32,
+    slug: String,
+    title: String,
+    route_id: i32,
+    published_at: Option<NaiveDateTime>,
+    updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SitemapKind {
+    Articles,
+    News,
+}
+
+/// Renders the `<url>` entries of one sitemap, one batch of articles at a time.
+pub struct SitemapStream {
+    conn: DbConn,
+    urls: UrlBuilder,
+    config: SitemapConfig,
+    kind: SitemapKind,
+    after_id: i32,
+    remaining: i64,
+    published_since: Option<NaiveDateTime>,
+    buffer: IoCursor<Vec<u8>>,
+    finished: bool,
+}
+
+impl SitemapStream {
+    /// Stream of the page-th (starting at 1) sitemap of articles, `None` when
+    /// the page is out of range.
+    pub fn articles(conn: DbConn, urls: UrlBuilder, config: &SitemapConfig, page: i64) -> QueryResult<Option<Self>> {
+        if page < 1 {
+            return Ok(None);
+        }
+
+        let first_id = swp_article::table
+            .filter(swp_article::status.eq(PUBLISHED))
+            .select(swp_article::id)
+            .order(swp_article::id.asc())
+            .offset((page - 1) * URLS_PER_SITEMAP)
+            .first::<i32>(&conn)
+            .optional()?;
+
+        Ok(first_id.map(|first_id| SitemapStream {
+            conn,
+            urls,
+            config: config.clone(),
+            kind: SitemapKind::Articles,
+            after_id: first_id - 1,
+            remaining: URLS_PER_SITEMAP,
+            published_since: None,
+            buffer: IoCursor::new(URLSET_OPEN.as_bytes().to_vec()),
+            finished: false,
+        }))
+    }
+
+    pub fn news(conn: DbConn, urls: UrlBuilder, config: &SitemapConfig) -> Self {
+        SitemapStream {
+            conn,
+            urls,
+            config: config.clone(),
+            kind: SitemapKind::News,
+            after_id: 0,
+            remaining: NEWS_MAX_URLS,
+            published_since: Some(Utc::now().naive_utc() - Duration::hours(NEWS_MAX_AGE_HOURS)),
+            buffer: IoCursor::new(NEWS_URLSET_OPEN.as_bytes().to_vec()),
+            finished: false,
+        }
+    }
+
+    fn next_batch(&mut self) -> QueryResult<Option<String>> {
+        if self.remaining <= 0 {
+            return Ok(None);
+        }
+
+        let mut query = swp_article::table
+            .into_boxed()
+            .select((
+                swp_article::id,
+                swp_article::slug,
+                swp_article::title,
+                swp_article::route_id,
+                swp_article::published_at,
+                swp_article::updated_at,
+            ))
+            .filter(swp_article::status.eq(PUBLISHED))
+            .filter(swp_article::id.gt(self.after_id))
+            .order(swp_article::id.asc())
+            .limit(BATCH_SIZE.min(self.remaining));
+
+        if let Some(since) = self.published_since {
+            query = query.filter(swp_article::published_at.ge(since));
+        }
+
+        let articles = query.load::<SitemapArticle>(&self.conn)?;
+
+        let last = match articles.last() {
+            Some(last) => last.id,
+            None => return Ok(None),
+        };
+        self.after_id = last;
+        self.remaining -= articles.len() as i64;
+
+        let ids = articles.iter().map(|article| article.id).collect::<Vec<_>>();
+        let route_ids = articles.iter().map(|article| article.route_id).collect::<BTreeSet<_>>();
+        let route_slugs: HashMap<i32, String> = swp_route::table
+            .filter(swp_route::id.eq_any(route_ids.into_iter().collect::<Vec<_>>()))
+            .select((swp_route::id, swp_route::slug))
+            .load::<(i32, String)>(&self.conn)?
+            .into_iter()
+            .collect();
+
+        let mut xml = String::new();
+
+        match self.kind {
+            SitemapKind::Articles => {
+                let mut images: HashMap<i32, Vec<(String, String, String)>> = HashMap::new();
+                let media = swp_article_media::table
+                    .inner_join(swp_image::table)
+                    .filter(swp_article_media::article_id.eq_any(&ids))
+                    .select((
+                        swp_article_media::article_id,
+                        swp_image::asset_id,
+                        swp_image::file_extension,
+                        swp_article_media::description,
+                    ))
+                    .load::<(i32, String, String, String)>(&self.conn)?;
+
+                for (article_id, asset_id, file_extension, description) in media {
+                    images.entry(article_id).or_insert_with(Vec::new).push((asset_id, file_extension, description));
+                }
+
+                for article in &articles {
+                    let route_slug = match route_slugs.get(&article.route_id) {
+                        Some(route_slug) => route_slug,
+                        None => continue,
+                    };
+
+                    xml.push_str("<url>");
+                    let url = self.urls.article_url(route_slug, &article.slug, article.id, article.published_at);
+                    xml.push_str(&format!("<loc>{}</loc>", escape(&url)));
+
+                    if let Some(lastmod) = article.updated_at.or(article.published_at) {
+                        xml.push_str(&format!("<lastmod>{}</lastmod>", format_date(lastmod)));
+                    }
+
+                    for (asset_id, file_extension, description) in images.get(&article.id).into_iter().flatten() {
+                        xml.push_str("<image:image>");
+                        xml.push_str(&format!("<image:loc>{}</image:loc>", escape(&generate_asset_url(asset_id, file_extension))));
+                        if !description.is_empty() {
+                            xml.push_str(&format!("<image:caption>{}</image:caption>", escape(description)));
+                        }
+                        xml.push_str("</image:image>");
+                    }
+
+                    xml.push_str("</url>");
+                }
+            }
+            SitemapKind::News => {
+                let mut keywords: HashMap<i32, Vec<String>> = HashMap::new();
+                let article_keywords = swp_article_keyword::table
+                    .inner_join(swp_keyword::table)
+                    .filter(swp_article_keyword::article_id.eq_any(&ids))
+                    .select((swp_article_keyword::article_id, swp_keyword::name))
+                    .load::<(i32, String)>(&self.conn)?;
+
+                for (article_id, name) in article_keywords {
+                    keywords.entry(article_id).or_insert_with(Vec::new).push(name);
+                }
+
+                for article in &articles {
+                    let (route_slug, published_at) = match (route_slugs.get(&article.route_id), article.published_at) {
+                        (Some(route_slug), Some(published_at)) => (route_slug, published_at),
+                        _ => continue,
+                    };
+
+                    xml.push_str("<url>");
+                    let url = self.urls.article_url(route_slug, &article.slug, article.id, article.published_at);
+                    xml.push_str(&format!("<loc>{}</loc>", escape(&url)));
+                    xml.push_str("<news:news><news:publication>");
+                    xml.push_str(&format!("<news:name>{}</news:name>", escape(&self.config.news_publication_name)));
+                    xml.push_str(&format!("<news:language>{}</news:language>", escape(&self.config.news_publication_language)));
+                    xml.push_str("</news:publication>");
+                    xml.push_str(&format!("<news:publication_date>{}</news:publication_date>", format_date(published_at)));
+                    xml.push_str(&format!("<news:title>{}</news:title>", escape(&article.title)));
+
+                    if let Some(names) = keywords.get(&article.id) {
+                        xml.push_str(&format!("<news:keywords>{}</news:keywords>", escape(&names.join(", "))));
+                    }
+
+                    xml.push_str("</news:news></url>");
+                }
+            }
+        }
+
+        Ok(Some(xml))
+    }
+}
+
+impl Read for SitemapStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.buffer.read(buf)?;
+            if read > 0 || self.finished {
+                return Ok(read);
+            }
+
+            let next = match self.next_batch() {
+                Ok(Some(xml)) => xml,
+                Ok(None) => {
+                    self.finished = true;
+                    URLSET_CLOSE.to_string()
+                }
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            };
+
+            self.buffer = IoCursor::new(next.into_bytes());
+        }
+    }
+}
+
+/// Sitemap index listing the article sitemap pages, the routes sitemap and
+/// the news sitemap.
+pub fn render_index(conn: &PgConnection, urls: &UrlBuilder) -> QueryResult<String> {
+    let total = swp_article::table
+        .filter(swp_article::status.eq(PUBLISHED))
+        .count()
+        .get_result::<i64>(conn)?;
+    let pages = (total + URLS_PER_SITEMAP - 1) / URLS_PER_SITEMAP;
+
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?><sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#);
+    xml.push_str(&sitemap_entry(urls, "/sitemaps/routes.xml", None));
+
+    for page in 1..=pages {
+        let lastmod = swp_article::table
+            .filter(swp_article::status.eq(PUBLISHED))
+            .filter(swp_article::id.eq_any(
+                swp_article::table
+                    .filter(swp_article::status.eq(PUBLISHED))
+                    .select(swp_article::id)
+                    .order(swp_article::id.asc())
+                    .offset((page - 1) * URLS_PER_SITEMAP)
+                    .limit(URLS_PER_SITEMAP),
+            ))
+            .select(max(swp_article::updated_at))
+            .first::<Option<NaiveDateTime>>(conn)?;
+
+        xml.push_str(&sitemap_entry(urls, &format!("/sitemaps/articles-{}.xml", page), lastmod));
+    }
+
+    xml.push_str(&sitemap_entry(urls, "/sitemaps/news.xml", None));
+    xml.push_str("</sitemapindex>");
+
+    Ok(xml)
+}
+
+pub fn render_routes(conn: &PgConnection, urls: &UrlBuilder) -> QueryResult<String> {
+    let slugs = swp_route::table
+        .select(swp_route::slug)
+        .order(swp_route::id.asc())
+        .load::<String>(conn)?;
+
+    let mut xml = String::from(URLSET_OPEN);
+    for slug in slugs {
+        xml.push_str(&format!("<url><loc>{}</loc></url>", escape(&urls.route_url(&slug))));
+    }
+    xml.push_str(URLSET_CLOSE);
+
+    Ok(xml)
+}
+
+fn sitemap_entry(urls: &UrlBuilder, path: &str, lastmod: Option<NaiveDateTime>) -> String {
+    let lastmod = lastmod
+        .map(|lastmod| format!("<lastmod>{}</lastmod>", format_date(lastmod)))
+        .unwrap_or_default();
+
+    format!("<sitemap><loc>{}</loc>{}</sitemap>", escape(&urls.site_url(path)), lastmod)
+}
+
+/// Parses `articles-<page>.xml`.
+pub fn parse_page(file_name: &str) -> Option<i64> {
+    if !file_name.starts_with("articles-") || !file_name.ends_with(".xml") {
+        return None;
+    }
+
+    file_name["articles-".len()..file_name.len() - ".xml".len()].parse().ok()
+}
+
+pub enum SitemapBody {
+    Complete(String),
+    Streamed(SitemapStream),
+}
+
+/// XML sitemap response, tagged with the `sitemaps` surrogate key so all
+/// sitemaps can be purged at once.
+pub struct SitemapResponse {
+    body: SitemapBody,
+    max_age: u32,
+}
+
+impl SitemapResponse {
+    pub fn new(body: SitemapBody, config: &SitemapConfig) -> Self {
+        SitemapResponse {
+            body,
+            max_age: config.max_age,
+        }
+    }
+}
+
+impl<'r> Responder<'r> for SitemapResponse {
+    fn respond_to(self, _request: &Request) -> response::Result<'r> {
+        let mut response = Response::build();
+        let surrogate_keys = ["sitemaps", "articles"].iter().map(|key| key.to_string()).collect();
+        surrogate_key_headers(&mut response, &surrogate_keys);
+
+        response
+            .status(Status::Ok)
+            .header(ContentType::XML)
+            .header(Header::new("Cache-Control", format!("public, max-age={}", self.max_age)));
+
+        match self.body {
+            SitemapBody::Complete(xml) => response.sized_body(IoCursor::new(xml)),
+            SitemapBody::Streamed(stream) => response.streamed_body(stream),
+        };
+
+        response.ok()
+    }
+}
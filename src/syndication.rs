@@ -0,0 +1,376 @@
+//! Article formats of distribution channels and partners. Every format is
+//! rendered from the same `ArticleContent`, the article with everything it
+//! shows loaded.
+
+pub mod amp;
+pub mod apple_news;
+pub mod html;
+pub mod instant_articles;
+pub mod tar;
+pub mod wxr;
+
+use std::collections::HashMap;
+use std::io::Cursor as IoCursor;
+use diesel::prelude::*;
+use rocket::{
+    http::{ContentType, Header, Status},
+    response::{self, Responder, Response},
+    Request,
+};
+use juniper_eager_loading::GraphqlNodeForModel;
+use crate::graphql::bylines::{self, BylineRoles};
+use crate::graphql::generator::generate_asset_url;
+use crate::graphql::{Author, BylineFormat, Context};
+use crate::models::{Article as ArticleModel, ArticleMedia as ArticleMediaModel, Author as AuthorModel};
+use crate::schema::{
+    swp_article, swp_article_author, swp_article_keyword, swp_article_media, swp_author, swp_image,
+    swp_image_rendition, swp_keyword, swp_route, swp_slideshow, swp_slideshow_item,
+};
+
+const PUBLISHED: &str = "published";
+
+/// Formats which can be limited to the articles of some routes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouteFormat {
+    InstantArticles,
+    Amp,
+}
+
+/// Routes the formats are limited to, configured once at startup in
+/// `PUBLISHER_INSTANT_ARTICLES_ROUTES` and `PUBLISHER_AMP_ROUTES`: the ids or
+/// slugs of the routes, separated by commas. A format is enabled for all
+/// routes when its variable is not set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteFormats {
+    instant_articles: Option<Vec<String>>,
+    amp: Option<Vec<String>>,
+}
+
+impl RouteFormats {
+    pub fn from_env() -> Self {
+        let routes = |variable: &str| {
+            std::env::var(variable)
+                .ok()
+                .map(|routes| routes.split(',').map(|route| route.trim().to_string()).collect())
+        };
+
+        RouteFormats {
+            instant_articles: routes("PUBLISHER_INSTANT_ARTICLES_ROUTES"),
+            amp: routes("PUBLISHER_AMP_ROUTES"),
+        }
+    }
+
+    pub fn enabled_for(&self, format: RouteFormat, route_id: i32, route_slug: &str) -> bool {
+        let routes = match format {
+            RouteFormat::InstantArticles => &self.instant_articles,
+            RouteFormat::Amp => &self.amp,
+        };
+
+        match routes {
+            Some(routes) => routes
+                .iter()
+                .any(|route| route == route_slug || route.parse::<i32>().ok() == Some(route_id)),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaItem {
+    pub id: i32,
+    pub key: String,
+    pub url: String,
+    pub mimetype: String,
+    pub caption: String,
+    pub by_line: String,
+    pub located: String,
+    pub usage_terms: String,
+    /// Size of the largest rendition, the original's size is not stored.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl MediaItem {
+    pub fn is_image(&self) -> bool {
+        self.mimetype.starts_with("image/")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlideshowContent {
+    pub code: String,
+    pub items: Vec<MediaItem>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArticleContent {
+    pub article: ArticleModel,
+    pub route_id: i32,
+    pub route_name: String,
+    pub route_slug: String,
+    pub url: String,
+    pub canonical_url: String,
+    /// Language of the tenant's website.
+    pub language: String,
+    pub authors: Vec<AuthorModel>,
+    pub byline_roles: BylineRoles,
+    pub keywords: Vec<(String, String)>,
+    pub feature_media: Option<MediaItem>,
+    /// Media neither featured nor part of a slideshow.
+    pub media: Vec<MediaItem>,
+    pub slideshows: Vec<SlideshowContent>,
+}
+
+impl ArticleContent {
+    pub fn load(article: ArticleModel, context: &Context) -> QueryResult<Self> {
+        let conn = &context.db_con;
+
+        let (route_id, route_name, route_slug) = swp_route::table
+            .find(article.route_id)
+            .select((swp_route::id, swp_route::name, swp_route::slug))
+            .first::<(i32, String, String)>(conn)?;
+
+        let authors = swp_article_author::table
+            .inner_join(swp_author::table)
+            .filter(swp_article_author::article_id.eq(article.id))
+            .select(swp_author::all_columns)
+            .order(swp_author::id.asc())
+            .load::<AuthorModel>(conn)?;
+
+        let keywords = swp_article_keyword::table
+            .inner_join(swp_keyword::table)
+            .filter(swp_article_keyword::article_id.eq(article.id))
+            .select((swp_keyword::name, swp_keyword::slug))
+            .load::<(String, String)>(conn)?;
+
+        let mut media = load_media(article.id, conn)?;
+
+        let slideshows = swp_slideshow::table
+            .filter(swp_slideshow::article_id.eq(article.id))
+            .select((swp_slideshow::id, swp_slideshow::code))
+            .order(swp_slideshow::id.asc())
+            .load::<(i32, String)>(conn)?;
+        let slideshow_items = swp_slideshow_item::table
+            .filter(swp_slideshow_item::slideshow_id.eq_any(slideshows.iter().map(|(id, _)| *id).collect::<Vec<_>>()))
+            .select((swp_slideshow_item::slideshow_id, swp_slideshow_item::article_media_id))
+            .order((swp_slideshow_item::position.asc(), swp_slideshow_item::id.asc()))
+            .load::<(i32, i32)>(conn)?;
+
+        let slideshows = slideshows
+            .into_iter()
+            .map(|(id, code)| SlideshowContent {
+                code,
+                items: slideshow_items
+                    .iter()
+                    .filter(|(slideshow_id, _)| *slideshow_id == id)
+                    .filter_map(|(_, media_id)| media.get(media_id).cloned())
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        let feature_media = article.feature_media.and_then(|id| media.remove(&id));
+        for (_, media_id) in &slideshow_items {
+            media.remove(media_id);
+        }
+        let mut media = media.into_iter().map(|(_, media)| media).collect::<Vec<_>>();
+        media.sort_by_key(|media| media.id);
+
+        let url = context.urls.article_url(&route_slug, &article.slug, article.id, article.published_at);
+        let canonical_url = context.urls.canonical_article_url(&route_slug, &article.slug, article.id, article.published_at);
+
+        context.record_key(format!("article-{}", article.id));
+
+        Ok(ArticleContent {
+            article,
+            route_id,
+            route_name,
+            route_slug,
+            url,
+            canonical_url,
+            language: context.tenant.language.clone(),
+            authors,
+            byline_roles: context.config.byline_roles.clone(),
+            keywords,
+            feature_media,
+            media,
+            slideshows,
+        })
+    }
+
+    /// Loads the published article with the given id.
+    pub fn load_published(id: i32, context: &Context) -> QueryResult<Option<Self>> {
+        let article = swp_article::table
+            .find(id)
+            .filter(swp_article::status.eq(PUBLISHED))
+            .first::<ArticleModel>(&context.db_con)
+            .optional()?;
+
+        article.map(|article| ArticleContent::load(article, context)).transpose()
+    }
+
+    pub fn enabled_for(&self, format: RouteFormat, context: &Context) -> bool {
+        context.route_formats.enabled_for(format, self.route_id, &self.route_slug)
+    }
+
+    pub fn author_names(&self) -> Vec<String> {
+        self.authors.iter().map(|author| author.name.clone()).collect()
+    }
+
+    /// The same byline as `Article.byline` in the locale, e.g. "By A and B;
+    /// Photos by C".
+    pub fn byline(&self, locale: &str) -> Option<String> {
+        let authors = Author::from_db_models(&self.authors);
+
+        bylines::byline(&bylines::group_by_role(&authors, &self.byline_roles), locale, &BylineFormat::Full, false)
+    }
+}
+
+fn load_media(article_id: i32, conn: &PgConnection) -> QueryResult<HashMap<i32, MediaItem>> {
+    let media = swp_article_media::table
+        .inner_join(swp_image::table)
+        .filter(swp_article_media::article_id.eq(article_id))
+        .select((swp_article_media::all_columns, swp_image::asset_id, swp_image::file_extension))
+        .load::<(ArticleMediaModel, String, String)>(conn)?;
+
+    let sizes: HashMap<i32, (i32, i32)> = swp_image_rendition::table
+        .filter(swp_image_rendition::media_id.eq_any(media.iter().map(|(media, _, _)| media.id).collect::<Vec<_>>()))
+        .select((swp_image_rendition::media_id, swp_image_rendition::width, swp_image_rendition::height))
+        .order(swp_image_rendition::width.asc())
+        .load::<(i32, i32, i32)>(conn)?
+        .into_iter()
+        .map(|(media_id, width, height)| (media_id, (width, height)))
+        .collect();
+
+    Ok(media
+        .into_iter()
+        .map(|(media, asset_id, file_extension)| {
+            let size = sizes.get(&media.id);
+            let item = MediaItem {
+                id: media.id,
+                key: media.key,
+                url: generate_asset_url(&asset_id, &file_extension),
+                mimetype: media.mimetype,
+                caption: media.description,
+                by_line: media.by_line,
+                located: media.located,
+                usage_terms: media.usage_terms,
+                width: size.map(|(width, _)| *width),
+                height: size.map(|(_, height)| *height),
+            };
+
+            (item.id, item)
+        })
+        .collect())
+}
+
+/// Ids of the latest published articles, of the route when given.
+pub fn latest_article_ids(route: Option<i32>, limit: i64, conn: &PgConnection) -> QueryResult<Vec<i32>> {
+    let mut query = swp_article::table
+        .select(swp_article::id)
+        .filter(swp_article::status.eq(PUBLISHED))
+        .order((swp_article::published_at.desc(), swp_article::id.desc()))
+        .limit(limit)
+        .into_boxed();
+
+    if let Some(route) = route {
+        query = query.filter(swp_article::route_id.eq(route));
+    }
+
+    query.load::<i32>(conn)
+}
+
+pub fn internal_error(error: diesel::result::Error) -> Status {
+    eprintln!("syndication: {}", error);
+    Status::InternalServerError
+}
+
+/// Binary file downloaded as an attachment, e.g. an archive of articles.
+pub struct Download {
+    pub file_name: String,
+    pub content_type: ContentType,
+    pub body: Vec<u8>,
+}
+
+impl<'r> Responder<'r> for Download {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(self.content_type)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", self.file_name)))
+            .header(Header::new("Cache-Control", "no-store"))
+            .sized_body(IoCursor::new(self.body))
+            .ok()
+    }
+}
+
+/// Articles for the tests of the formats, which are rendered without a
+/// database.
+#[cfg(test)]
+pub mod fixtures {
+    use chrono::NaiveDate;
+    use super::*;
+
+    pub fn image(id: i32, key: &str) -> MediaItem {
+        MediaItem {
+            id,
+            key: key.to_string(),
+            url: format!("https://cdn.example.com/{}.jpg", key),
+            mimetype: "image/jpeg".to_string(),
+            caption: format!("Caption of {}", key),
+            by_line: "Photographer".to_string(),
+            located: "Berlin".to_string(),
+            usage_terms: String::new(),
+            width: Some(1600),
+            height: Some(900),
+        }
+    }
+
+    pub fn author(id: i32, name: &str, role: &str, slug: Option<&str>) -> AuthorModel {
+        AuthorModel {
+            id,
+            name: name.to_string(),
+            role: role.to_string(),
+            job_title: String::new(),
+            biography: None,
+            slug: slug.map(str::to_string),
+            twitter: None,
+            facebook: None,
+            instagram: None,
+            author_media_id: None,
+        }
+    }
+
+    /// A published article of the `news` route with the body, a featured
+    /// image and one author, on a German website.
+    pub fn article_content(body: &str) -> ArticleContent {
+        ArticleContent {
+            article: ArticleModel {
+                id: 42,
+                slug: "storm".to_string(),
+                status: PUBLISHED.to_string(),
+                title: "Storm & rain".to_string(),
+                body: body.to_string(),
+                lead: "<p>The <b>storm</b> hit the coast &amp; the city.</p>".to_string(),
+                route_id: 5,
+                comments_count: 0,
+                extra: None,
+                metadata: None,
+                feature_media: Some(1),
+                seo_metadata_id: None,
+                published_at: Some(NaiveDate::from_ymd(2019, 5, 1).and_hms(12, 0, 0)),
+                updated_at: Some(NaiveDate::from_ymd(2019, 5, 2).and_hms(8, 30, 0)),
+            },
+            route_id: 5,
+            route_name: "News".to_string(),
+            route_slug: "news".to_string(),
+            url: "https://www.example.com/news/storm".to_string(),
+            canonical_url: "https://www.example.com/news/storm".to_string(),
+            language: "de".to_string(),
+            authors: vec![author(1, "Jane Doe", "writer", Some("jane-doe"))],
+            byline_roles: BylineRoles::default(),
+            keywords: vec![("Weather".to_string(), "weather".to_string())],
+            feature_media: Some(image(1, "feature")),
+            media: Vec::new(),
+            slideshows: Vec::new(),
+        }
+    }
+}
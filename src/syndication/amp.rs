@@ -0,0 +1,388 @@
+//! Standalone AMP (https://amp.dev/documentation/guides-and-tutorials/learn/spec/amphtml/)
+//! pages of articles.
+
+use std::collections::BTreeSet;
+use rocket::http::{ContentType, Status};
+use crate::dates::{format_date, format_long_date};
+use crate::graphql::generator::UrlBuilder;
+use crate::graphql::seo::plain_text;
+use crate::graphql::structured_data;
+use crate::graphql::Context;
+use crate::response::CacheableResponse;
+use crate::xml::escape;
+use super::html::{parse_attributes, write_attributes};
+use super::{internal_error, ArticleContent, MediaItem, RouteFormat};
+
+const MAX_AGE: u32 = 300;
+/// Size used for images whose size is unknown, `amp-img` requires one.
+const DEFAULT_WIDTH: u32 = 1200;
+const DEFAULT_HEIGHT: u32 = 675;
+
+const BOILERPLATE: &str = "<style amp-boilerplate>body{-webkit-animation:-amp-start 8s steps(1,end) 0s 1 normal both;-moz-animation:-amp-start 8s steps(1,end) 0s 1 normal both;-ms-animation:-amp-start 8s steps(1,end) 0s 1 normal both;animation:-amp-start 8s steps(1,end) 0s 1 normal both}@-webkit-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-moz-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-ms-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@-o-keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}@keyframes -amp-start{from{visibility:hidden}to{visibility:visible}}</style><noscript><style amp-boilerplate>body{-webkit-animation:none;-moz-animation:none;-ms-animation:none;animation:none}</style></noscript>";
+
+const STYLE: &str = "body{margin:0;font-family:Georgia,serif;color:#222;line-height:1.6}\
+article{max-width:720px;margin:0 auto;padding:16px}\
+h1{font-size:2em;line-height:1.2}\
+.lead{font-size:1.2em;color:#444}\
+.byline,time,figcaption{font-family:sans-serif;font-size:.85em;color:#666}\
+blockquote{margin:1em 0;padding-left:1em;border-left:3px solid #ddd}";
+
+/// Elements AMP doesn't allow, removed with their content.
+const REMOVED_WITH_CONTENT: &[&str] = &[
+    "script", "style", "noscript", "object", "applet", "form", "template", "video", "audio", "frameset", "svg",
+];
+/// Elements AMP doesn't allow whose content is kept.
+const REMOVED: &[&str] = &[
+    "embed", "frame", "input", "button", "select", "option", "textarea", "link", "meta", "base", "param", "font",
+];
+
+/// Complete AMP page. The body is sanitized: forbidden elements and
+/// attributes (`style`, event handlers, `javascript:` links) are removed,
+/// images become `amp-img` and https iframes `amp-iframe`.
+pub fn render(content: &ArticleContent, structured_data: &str, urls: &UrlBuilder) -> String {
+    let article = &content.article;
+    let mut extensions = BTreeSet::new();
+    let mut body = String::new();
+
+    body.push_str(&format!("<h1>{}</h1>", escape(&article.title)));
+
+    let lead = plain_text(&article.lead);
+    if !lead.is_empty() {
+        body.push_str(&format!(r#"<p class="lead">{}</p>"#, escape(&lead)));
+    }
+
+    if let Some(byline) = content.byline(&content.language) {
+        body.push_str(&format!(r#"<p class="byline">{}</p>"#, escape(&byline)));
+    }
+
+    if let Some(published_at) = article.published_at {
+        body.push_str(&format!(
+            r#"<time datetime="{}">{}</time>"#,
+            format_date(published_at),
+            format_long_date(published_at, &content.language)
+        ));
+    }
+
+    if let Some(media) = content.feature_media.as_ref().filter(|media| media.is_image()) {
+        body.push_str(&figure(media));
+    }
+
+    body.push_str(&sanitize(&article.body, &mut extensions));
+
+    for slideshow in &content.slideshows {
+        let images = slideshow
+            .items
+            .iter()
+            .filter(|media| media.is_image())
+            .map(|media| amp_img(&media.url, &media.caption, media.width, media.height))
+            .collect::<String>();
+
+        if !images.is_empty() {
+            extensions.insert("amp-carousel");
+            body.push_str(&format!(
+                r#"<amp-carousel type="slides" layout="responsive" width="{}" height="{}" controls>{}</amp-carousel>"#,
+                DEFAULT_WIDTH, DEFAULT_HEIGHT, images
+            ));
+        }
+    }
+
+    for media in content.media.iter().filter(|media| media.is_image()) {
+        body.push_str(&figure(media));
+    }
+
+    // the referrer and client id are substituted by the AMP runtime
+    let pixel_url = urls.api_url(&format!(
+        "/page-views/{}/pixel.gif?referrer=DOCUMENT_REFERRER&visitor=CLIENT_ID(publisher-page-views)",
+        article.id
+    ));
+    body.push_str(&format!(r#"<amp-pixel src="{}" layout="nodisplay"></amp-pixel>"#, escape(&pixel_url)));
+
+    let scripts = extensions
+        .iter()
+        .map(|extension| {
+            let version = if *extension == "amp-carousel" { "0.2" } else { "0.1" };
+            format!(
+                r#"<script async custom-element="{0}" src="https://cdn.ampproject.org/v0/{0}-{1}.js"></script>"#,
+                extension, version
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<!doctype html>
+<html ⚡ lang="{language}">
+<head>
+<meta charset="utf-8">
+<script async src="https://cdn.ampproject.org/v0.js"></script>
+{scripts}
+<title>{title}</title>
+<link rel="canonical" href="{canonical_url}">
+<meta name="viewport" content="width=device-width">
+{boilerplate}
+<style amp-custom>{style}</style>
+{structured_data}
+</head>
+<body>
+<article>
+{body}
+</article>
+</body>
+</html>
+"#,
+        language = escape(&content.language),
+        scripts = scripts,
+        title = escape(&article.title),
+        canonical_url = escape(&content.canonical_url),
+        boilerplate = BOILERPLATE,
+        style = STYLE,
+        structured_data = structured_data,
+        body = body,
+    )
+}
+
+fn figure(media: &MediaItem) -> String {
+    let caption = if media.caption.trim().is_empty() {
+        String::new()
+    } else {
+        format!("<figcaption>{}</figcaption>", escape(&media.caption))
+    };
+
+    format!(
+        "<figure>{}{}</figure>",
+        amp_img(&media.url, &media.caption, media.width, media.height),
+        caption
+    )
+}
+
+fn amp_img(src: &str, alt: &str, width: Option<i32>, height: Option<i32>) -> String {
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => (width as u32, height as u32),
+        _ => (DEFAULT_WIDTH, DEFAULT_HEIGHT),
+    };
+
+    format!(
+        r#"<amp-img src="{}" alt="{}" width="{}" height="{}" layout="responsive"></amp-img>"#,
+        escape(src),
+        escape(alt),
+        width,
+        height
+    )
+}
+
+fn allowed_attribute(name: &str, value: &str) -> bool {
+    let value = value.trim().to_ascii_lowercase();
+
+    !(name == "style"
+        || name.starts_with("on")
+        || name == "xmlns"
+        || name.starts_with("xml:")
+        || ((name == "href" || name == "src") && value.starts_with("javascript:")))
+}
+
+/// Rewrites the HTML of the body into AMP HTML, collecting the extensions the
+/// custom elements it uses need.
+fn sanitize(html: &str, extensions: &mut BTreeSet<&'static str>) -> String {
+    let mut output = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+
+        let tag_end = match rest.find('>') {
+            Some(tag_end) => tag_end,
+            None => {
+                rest = "";
+                break;
+            }
+        };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or_else(|| tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if name.is_empty() || name.starts_with('!') || name.starts_with('?') || REMOVED.contains(&name.as_str()) {
+            continue;
+        }
+
+        if REMOVED_WITH_CONTENT.contains(&name.as_str()) {
+            if !closing {
+                rest = skip_element(rest, &name);
+            }
+            continue;
+        }
+
+        if closing {
+            match name.as_str() {
+                "img" => {}
+                "iframe" => output.push_str("</amp-iframe>"),
+                _ => output.push_str(&format!("</{}>", name)),
+            }
+            continue;
+        }
+
+        let attributes = parse_attributes(&tag[name_end..])
+            .into_iter()
+            .filter(|(name, value)| allowed_attribute(name, value))
+            .collect::<Vec<_>>();
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let dimension = |name: &str| attribute(name).and_then(|value| value.trim().parse::<i32>().ok());
+
+        match name.as_str() {
+            "img" => {
+                if let Some(src) = attribute("src") {
+                    output.push_str(&amp_img(src, attribute("alt").unwrap_or(""), dimension("width"), dimension("height")));
+                }
+            }
+            "iframe" => match attribute("src").filter(|src| src.starts_with("https://")) {
+                Some(src) => {
+                    extensions.insert("amp-iframe");
+                    output.push_str(&format!(
+                        r#"<amp-iframe src="{}" width="{}" height="{}" layout="responsive" sandbox="allow-scripts allow-same-origin allow-popups" frameborder="0">"#,
+                        escape(src),
+                        dimension("width").unwrap_or(DEFAULT_WIDTH as i32),
+                        dimension("height").unwrap_or(DEFAULT_HEIGHT as i32),
+                    ));
+                }
+                // amp-iframe only loads https documents
+                None => rest = skip_element(rest, "iframe"),
+            },
+            _ => output.push_str(&format!("<{}{}>", name, write_attributes(&attributes))),
+        }
+    }
+
+    output.push_str(rest);
+
+    output
+}
+
+/// Skips the content and the end tag of an element whose start tag was
+/// consumed.
+fn skip_element<'a>(html: &'a str, name: &str) -> &'a str {
+    let end_tag = format!("</{}", name);
+
+    html.to_ascii_lowercase()
+        .find(&end_tag)
+        .and_then(|start| html[start..].find('>').map(|end| &html[start + end + 1..]))
+        .unwrap_or("")
+}
+
+pub fn article_response(id: i32, context: &Context) -> Result<CacheableResponse, Status> {
+    let content = ArticleContent::load_published(id, context)
+        .map_err(internal_error)?
+        .filter(|content| content.enabled_for(RouteFormat::Amp, context))
+        .ok_or(Status::NotFound)?;
+    let structured_data = structured_data::news_article(&content.article, context).map_err(internal_error)?;
+    let body = render(&content, &structured_data::script_tag(&structured_data), &context.urls);
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(ContentType::HTML)
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", MAX_AGE))
+        .last_modified(content.article.updated_at.or(content.article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::article_content;
+    use super::*;
+
+    fn sanitized(html: &str) -> (String, BTreeSet<&'static str>) {
+        let mut extensions = BTreeSet::new();
+        let output = sanitize(html, &mut extensions);
+
+        (output, extensions)
+    }
+
+    #[test]
+    fn renders_the_required_markup() {
+        let urls = UrlBuilder::new("https://www.example.com", None).unwrap();
+        let html = render(&article_content("<p>Text</p>"), "", &urls);
+
+        assert!(html.starts_with("<!doctype html>\n<html ⚡ lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n"));
+        assert!(html.contains(r#"<script async src="https://cdn.ampproject.org/v0.js"></script>"#));
+        assert!(html.contains(BOILERPLATE));
+        assert!(html.contains(r#"<link rel="canonical" href="https://www.example.com/news/storm">"#));
+        assert!(html.contains(r#"<meta name="viewport" content="width=device-width">"#));
+        assert!(html.contains("<style amp-custom>"));
+        assert!(html.contains("<title>Storm &amp; rain</title>"));
+        assert!(html.contains(r#"<p class="lead">The storm hit the coast &amp; the city.</p>"#));
+        assert!(html.contains(r#"<p class="byline">Von Jane Doe</p>"#));
+        assert!(html.contains(r#"<time datetime="2019-05-01T12:00:00Z">1. Mai 2019</time>"#));
+        assert!(html.contains(
+            r#"<amp-img src="https://cdn.example.com/feature.jpg" alt="Caption of feature" width="1600" height="900" layout="responsive"></amp-img>"#
+        ));
+        assert!(html.contains("<p>Text</p>"));
+        assert!(!html.contains("custom-element"));
+    }
+
+    #[test]
+    fn removes_scripts_styles_and_event_handlers() {
+        let (html, _) = sanitized(concat!(
+            r#"<p class="intro" style="color: red" onclick="alert(1)">Hello<script>alert(1)</script></p>"#,
+            r#"<style>p { color: red }</style>"#,
+            r#"<div onMouseOver="track()">Text</div>"#,
+        ));
+
+        assert_eq!(html, r#"<p class="intro">Hello</p><div>Text</div>"#);
+    }
+
+    #[test]
+    fn rejects_javascript_links() {
+        let (html, _) = sanitized(concat!(
+            r#"<a href="javascript:alert(1)">first</a>"#,
+            r#"<a href=" JavaScript:alert(1)">second</a>"#,
+            r#"<a href="https://www.example.com/">third</a>"#,
+        ));
+
+        assert_eq!(html, r#"<a>first</a><a>second</a><a href="https://www.example.com/">third</a>"#);
+    }
+
+    #[test]
+    fn converts_images_to_amp_img() {
+        let (html, extensions) = sanitized(r#"<img src="https://cdn.example.com/a.jpg" alt="A" width="800" height="600"><img src="https://cdn.example.com/b.jpg">"#);
+
+        assert_eq!(
+            html,
+            concat!(
+                r#"<amp-img src="https://cdn.example.com/a.jpg" alt="A" width="800" height="600" layout="responsive"></amp-img>"#,
+                r#"<amp-img src="https://cdn.example.com/b.jpg" alt="" width="1200" height="675" layout="responsive"></amp-img>"#,
+            )
+        );
+        assert!(extensions.is_empty());
+    }
+
+    #[test]
+    fn converts_https_iframes_and_drops_the_others() {
+        let (html, extensions) = sanitized(concat!(
+            r#"<iframe src="http://www.example.com/embed" width="560" height="315"><p>Fallback</p></iframe>"#,
+            r#"<iframe src="https://www.example.com/embed" width="560" height="315"></iframe>"#,
+        ));
+
+        assert_eq!(
+            html,
+            r#"<amp-iframe src="https://www.example.com/embed" width="560" height="315" layout="responsive" sandbox="allow-scripts allow-same-origin allow-popups" frameborder="0"></amp-iframe>"#
+        );
+        assert!(extensions.contains("amp-iframe"));
+
+        let (html, extensions) = sanitized(r#"<iframe src="http://www.example.com/embed"></iframe><p>After</p>"#);
+
+        assert_eq!(html, "<p>After</p>");
+        assert!(extensions.is_empty());
+    }
+}
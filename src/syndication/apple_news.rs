@@ -0,0 +1,230 @@
+//! Apple News Format (https://developer.apple.com/documentation/apple_news/apple_news_format)
+//! `article.json` documents.
+
+use std::collections::HashMap;
+use rocket::http::{ContentType, Status};
+use serde_json::{json, Value};
+use crate::dates::format_date;
+use crate::graphql::seo::plain_text;
+use crate::graphql::Context;
+use crate::response::CacheableResponse;
+use super::html::{blocks, Block};
+use super::tar::TarBuilder;
+use super::{internal_error, latest_article_ids, ArticleContent, Download, MediaItem};
+
+const ANF_VERSION: &str = "1.7";
+const DEFAULT_BUNDLE_ARTICLES: i64 = 20;
+const MAX_BUNDLE_ARTICLES: i64 = 100;
+/// Apple News accepts at most 50 keywords.
+const MAX_KEYWORDS: usize = 50;
+
+/// Layout of the documents.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnfLayout {
+    pub columns: u32,
+    pub width: u32,
+    #[serde(default)]
+    pub margin: u32,
+    #[serde(default)]
+    pub gutter: u32,
+}
+
+impl Default for AnfLayout {
+    fn default() -> Self {
+        AnfLayout { columns: 7, width: 1024, margin: 60, gutter: 20 }
+    }
+}
+
+/// Layouts of the tenants' documents, configured once at startup:
+/// `PUBLISHER_APPLE_NEWS_LAYOUTS` (a JSON object keyed by tenant codes,
+/// `default` for the other tenants).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnfLayouts {
+    layouts: HashMap<String, AnfLayout>,
+}
+
+impl AnfLayouts {
+    pub fn from_env() -> Self {
+        let layouts = std::env::var("PUBLISHER_APPLE_NEWS_LAYOUTS")
+            .ok()
+            .and_then(|layouts| {
+                serde_json::from_str::<HashMap<String, AnfLayout>>(&layouts)
+                    .map_err(|error| eprintln!("invalid PUBLISHER_APPLE_NEWS_LAYOUTS: {}", error))
+                    .ok()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(code, layout)| (code.to_lowercase(), layout))
+            .collect();
+
+        AnfLayouts { layouts }
+    }
+
+    pub fn for_tenant(&self, code: &str) -> AnfLayout {
+        self.layouts
+            .get(&code.to_lowercase())
+            .or_else(|| self.layouts.get("default"))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+pub fn article_document(content: &ArticleContent, layouts: &AnfLayouts, context: &Context) -> Value {
+    let article = &content.article;
+    let mut components = vec![json!({ "role": "title", "text": article.title, "textStyle": "title" })];
+
+    let intro = plain_text(&article.lead);
+    if !intro.is_empty() {
+        components.push(json!({ "role": "intro", "text": intro, "textStyle": "intro" }));
+    }
+
+    if let Some(byline) = content.byline(&content.language) {
+        components.push(json!({ "role": "byline", "text": byline, "textStyle": "byline" }));
+    }
+
+    if let Some(media) = content.feature_media.as_ref().filter(|media| media.is_image()) {
+        components.push(photo(media));
+    }
+
+    components.extend(blocks(&article.body).iter().filter_map(component));
+    components.extend(content.media.iter().filter(|media| media.is_image()).map(photo));
+
+    for slideshow in &content.slideshows {
+        let items = slideshow
+            .items
+            .iter()
+            .filter(|media| media.is_image())
+            .map(|media| {
+                let mut item = json!({ "URL": media.url });
+                if !media.caption.is_empty() {
+                    item["caption"] = json!(media.caption);
+                }
+                item
+            })
+            .collect::<Vec<_>>();
+
+        if !items.is_empty() {
+            components.push(json!({ "role": "gallery", "items": items }));
+        }
+    }
+
+    let mut metadata = json!({
+        "canonicalURL": content.canonical_url,
+        "excerpt": intro,
+        "authors": content.author_names(),
+        "keywords": content.keywords.iter().take(MAX_KEYWORDS).map(|(name, _)| name).collect::<Vec<_>>(),
+    });
+
+    if let Some(published_at) = article.published_at {
+        metadata["datePublished"] = json!(format_date(published_at));
+    }
+
+    if let Some(modified_at) = article.updated_at {
+        metadata["dateModified"] = json!(format_date(modified_at));
+    }
+
+    if let Some(media) = &content.feature_media {
+        metadata["thumbnailURL"] = json!(media.url);
+    }
+
+    json!({
+        "version": ANF_VERSION,
+        "identifier": format!("article-{}", article.id),
+        "title": article.title,
+        "language": content.language,
+        "layout": layouts.for_tenant(&context.tenant.code),
+        "metadata": metadata,
+        "components": components,
+        "componentTextStyles": {
+            "default": { "fontName": "Georgia", "fontSize": 18, "lineHeight": 26 },
+            "title": { "fontName": "Georgia-Bold", "fontSize": 36, "lineHeight": 42 },
+            "intro": { "fontName": "Georgia-Italic", "fontSize": 20, "lineHeight": 28 },
+            "byline": { "fontName": "HelveticaNeue", "fontSize": 14, "textColor": "#666666" },
+        },
+    })
+}
+
+fn photo(media: &MediaItem) -> Value {
+    let mut photo = json!({ "role": "photo", "URL": media.url });
+
+    if !media.caption.is_empty() {
+        photo["caption"] = json!(media.caption);
+    }
+
+    photo
+}
+
+/// Maps a body block to an ANF component. Paragraphs and lists keep their
+/// markup, as ANF supports a subset of HTML in `body` components.
+fn component(block: &Block) -> Option<Value> {
+    if let Some(level) = block.is_heading() {
+        return Some(json!({ "role": format!("heading{}", level), "text": block.text() }));
+    }
+
+    match block.tag.as_str() {
+        "blockquote" => Some(json!({ "role": "quote", "text": block.text() })),
+        "img" | "figure" => {
+            let url = block.attribute("src")?;
+            let mut photo = json!({ "role": "photo", "URL": url });
+            if let Some(caption) = block.attribute("alt").filter(|caption| !caption.is_empty()) {
+                photo["caption"] = json!(caption);
+            }
+            Some(photo)
+        }
+        "hr" => Some(json!({ "role": "divider" })),
+        "script" | "style" | "iframe" => None,
+        "ul" | "ol" | "pre" => Some(json!({ "role": "body", "format": "html", "text": block.outer_html() })),
+        _ if block.text().is_empty() => None,
+        _ => Some(json!({ "role": "body", "format": "html", "text": format!("<p>{}</p>", block.inner_html) })),
+    }
+}
+
+pub fn article_response(id: i32, layouts: &AnfLayouts, context: &Context) -> Result<CacheableResponse, Status> {
+    let content = ArticleContent::load_published(id, context)
+        .map_err(internal_error)?
+        .ok_or(Status::NotFound)?;
+    let body = article_document(&content, layouts, context).to_string();
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .etag_from(&body)
+        .cache_control("public, max-age=300".to_string())
+        .last_modified(content.article.updated_at.or(content.article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+/// Tar archive of the latest published articles (of the route when given),
+/// one `article-<id>/article.json` bundle per article.
+pub fn bundle_response(
+    route: Option<i32>,
+    limit: Option<i64>,
+    layouts: &AnfLayouts,
+    context: &Context,
+) -> Result<Download, Status> {
+    let limit = limit.unwrap_or(DEFAULT_BUNDLE_ARTICLES).max(1).min(MAX_BUNDLE_ARTICLES);
+    let ids = latest_article_ids(route, limit, &context.db_con).map_err(internal_error)?;
+    let mut archive = TarBuilder::new();
+
+    for id in ids {
+        if let Some(content) = ArticleContent::load_published(id, context).map_err(internal_error)? {
+            let document = article_document(&content, layouts, context).to_string();
+            let modified = content
+                .article
+                .updated_at
+                .or(content.article.published_at)
+                .map_or(0, |modified| modified.timestamp());
+
+            archive
+                .append(&format!("article-{}/article.json", id), document.as_bytes(), modified)
+                .map_err(|error| {
+                    eprintln!("apple news: {}", error);
+                    Status::InternalServerError
+                })?;
+        }
+    }
+
+    Ok(Download {
+        file_name: "apple-news.tar".to_string(),
+        content_type: ContentType::new("application", "x-tar"),
+        body: archive.finish(),
+    })
+}
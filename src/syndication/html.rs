@@ -0,0 +1,270 @@
+//! Minimal splitting of article bodies into their top-level HTML blocks, as
+//! most formats need the body as a list of paragraphs, headings, quotes and
+//! images instead of a single HTML string.
+
+use crate::xml::{decode_entities, escape};
+
+const VOID_ELEMENTS: &[&str] = &["area", "br", "embed", "hr", "img", "input", "source", "wbr"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// Lowercased tag name, `p` for text outside of any element.
+    pub tag: String,
+    /// Attributes as written in the start tag.
+    pub attributes: String,
+    pub inner_html: String,
+}
+
+impl Block {
+    pub fn outer_html(&self) -> String {
+        if VOID_ELEMENTS.contains(&self.tag.as_str()) {
+            format!("<{}{}>", self.tag, self.attributes)
+        } else {
+            format!("<{0}{1}>{2}</{0}>", self.tag, self.attributes, self.inner_html)
+        }
+    }
+
+    pub fn text(&self) -> String {
+        crate::graphql::seo::plain_text(&self.inner_html)
+    }
+
+    /// Value of the attribute of this element, or of the first nested element
+    /// having it (e.g. the `src` of an `<img>` inside a `<figure>`).
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        attribute(&self.attributes, name).or_else(|| {
+            let pattern = format!("{}=", name);
+            let start = self.inner_html.find(&pattern)?;
+            let tag_start = self.inner_html[..start].rfind('<')?;
+            let tag_end = self.inner_html[start..].find('>')? + start;
+
+            attribute(&self.inner_html[tag_start..tag_end], name)
+        })
+    }
+
+    pub fn is_heading(&self) -> Option<u8> {
+        match self.tag.as_str() {
+            "h1" => Some(1),
+            "h2" => Some(2),
+            "h3" => Some(3),
+            "h4" => Some(4),
+            "h5" => Some(5),
+            "h6" => Some(6),
+            _ => None,
+        }
+    }
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let lowercase = attributes.to_ascii_lowercase();
+    let mut offset = 0;
+
+    while let Some(index) = lowercase[offset..].find(name) {
+        let start = offset + index;
+        let before = lowercase[..start].chars().last();
+        let rest = attributes[start + name.len()..].trim_start();
+        offset = start + name.len();
+
+        if before.map_or(false, |c| !c.is_whitespace() && c != '<') || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value = rest[1..].trim_start();
+        return match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                value[1..].find(quote).map(|end| decode_entities(&value[1..end + 1]))
+            }
+            Some(_) => Some(decode_entities(value.split(|c: char| c.is_whitespace() || c == '>').next().unwrap_or(""))),
+            None => None,
+        };
+    }
+
+    None
+}
+
+/// All the attributes of a start tag, names lowercased and values decoded.
+pub fn parse_attributes(attributes: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    let mut rest = attributes.trim_matches(|c: char| c.is_whitespace() || c == '/');
+
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or_else(|| rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        let mut value = String::new();
+        rest = rest[name_end..].trim_start();
+
+        if rest.starts_with('=') {
+            rest = rest[1..].trim_start();
+
+            match rest.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let end = rest[1..].find(quote).map_or(rest.len(), |end| end + 1);
+                    value = decode_entities(&rest[1..end]);
+                    rest = rest.get(end + 1..).unwrap_or("");
+                }
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or_else(|| rest.len());
+                    value = decode_entities(&rest[..end]);
+                    rest = &rest[end..];
+                }
+            }
+        }
+
+        if !name.is_empty() {
+            parsed.push((name, value));
+        }
+
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+    }
+
+    parsed
+}
+
+/// Inverse of `parse_attributes`, with a leading space when not empty.
+pub fn write_attributes(attributes: &[(String, String)]) -> String {
+    attributes
+        .iter()
+        .map(|(name, value)| format!(r#" {}="{}""#, name, escape(value)))
+        .collect()
+}
+
+/// Splits the HTML into its top-level blocks. Text and inline elements
+/// outside of block elements are wrapped into paragraphs.
+pub fn blocks(html: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut inline = String::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                inline.push_str(rest);
+                break;
+            }
+        };
+
+        inline.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+
+        let tag_end = match rest.find('>') {
+            Some(tag_end) => tag_end,
+            None => {
+                inline.push_str(&escape(rest));
+                break;
+            }
+        };
+        let start_tag = &rest[1..tag_end];
+        let name_end = start_tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(start_tag.len());
+        let tag = start_tag[..name_end].to_ascii_lowercase();
+
+        if tag.is_empty() || tag.starts_with('/') || tag.starts_with('!') {
+            // stray end tags and doctypes
+            rest = &rest[tag_end + 1..];
+            continue;
+        }
+
+        if is_inline(&tag) {
+            // inline markup stays in the current paragraph
+            let length = closing_tag(&rest[tag_end + 1..], &tag)
+                .map_or(tag_end + 1, |(inner_end, after)| tag_end + 1 + inner_end + after);
+            inline.push_str(&rest[..length]);
+            rest = &rest[length..];
+            continue;
+        }
+
+        flush_inline(&mut inline, &mut blocks);
+
+        let attributes = start_tag[name_end..].trim_end_matches('/').to_string();
+        let content = &rest[tag_end + 1..];
+
+        if VOID_ELEMENTS.contains(&tag.as_str()) || start_tag.ends_with('/') {
+            blocks.push(Block { tag, attributes, inner_html: String::new() });
+            rest = content;
+            continue;
+        }
+
+        let (inner_end, after) = closing_tag(content, &tag).unwrap_or((content.len(), 0));
+        blocks.push(Block {
+            tag,
+            attributes,
+            inner_html: content[..inner_end].trim().to_string(),
+        });
+        rest = &content[inner_end + after..];
+    }
+
+    flush_inline(&mut inline, &mut blocks);
+
+    blocks
+}
+
+fn flush_inline(inline: &mut String, blocks: &mut Vec<Block>) {
+    let text = inline.trim();
+
+    if !text.is_empty() {
+        blocks.push(Block {
+            tag: "p".to_string(),
+            attributes: String::new(),
+            inner_html: text.to_string(),
+        });
+    }
+
+    inline.clear();
+}
+
+fn is_inline(tag: &str) -> bool {
+    match tag {
+        "a" | "abbr" | "b" | "cite" | "code" | "em" | "i" | "mark" | "q" | "s" | "small" | "span" | "strong"
+        | "sub" | "sup" | "u" | "br" => true,
+        _ => false,
+    }
+}
+
+/// Finds the end tag matching an element whose start tag was already
+/// consumed, returns the length of its content and of the end tag.
+fn closing_tag(content: &str, tag: &str) -> Option<(usize, usize)> {
+    if VOID_ELEMENTS.contains(&tag) {
+        return None;
+    }
+
+    let lowercase = content.to_ascii_lowercase();
+    let open = format!("<{}", tag);
+    let close = format!("</{}", tag);
+    let mut depth = 1;
+    let mut offset = 0;
+
+    loop {
+        let next_close = lowercase[offset..].find(&close)? + offset;
+        let next_open = lowercase[offset..].find(&open).map(|index| index + offset);
+
+        match next_open {
+            Some(next_open) if next_open < next_close => {
+                if is_tag_boundary(&lowercase, next_open + open.len()) {
+                    depth += 1;
+                }
+                offset = next_open + open.len();
+            }
+            _ if !is_tag_boundary(&lowercase, next_close + close.len()) => offset = next_close + close.len(),
+            _ => {
+                depth -= 1;
+                let end = lowercase[next_close..].find('>')? + next_close + 1;
+
+                if depth == 0 {
+                    return Some((next_close, end - next_close));
+                }
+
+                offset = end;
+            }
+        }
+    }
+}
+
+fn is_tag_boundary(html: &str, index: usize) -> bool {
+    html[index..].chars().next().map_or(true, |c| c.is_whitespace() || c == '>' || c == '/')
+}
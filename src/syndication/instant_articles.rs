@@ -0,0 +1,344 @@
+//! Facebook Instant Articles
+//! (https://developers.facebook.com/docs/instant-articles/reference) markup
+//! of articles, and the RSS feed Facebook imports them from.
+
+use chrono::prelude::*;
+use rocket::http::{ContentType, Status};
+use crate::dates::{format_date, format_long_date};
+use crate::feeds::{rss, Feed, FeedItem};
+use crate::graphql::generator::UrlBuilder;
+use crate::graphql::seo::plain_text;
+use crate::graphql::Context;
+use crate::response::CacheableResponse;
+use crate::tenant::Organization;
+use crate::xml::escape;
+use super::html::{blocks, parse_attributes, write_attributes, Block};
+use super::{internal_error, latest_article_ids, ArticleContent, MediaItem, RouteFormat};
+
+const DEFAULT_FEED_ARTICLES: i64 = 50;
+const MAX_FEED_ARTICLES: i64 = 100;
+const MAX_AGE: u32 = 300;
+
+/// Full Instant Article document. Instant Articles only have `h1` and `h2`
+/// headings, all images are in `<figure>` elements and embeds in
+/// `op-interactive` figures.
+pub fn render(content: &ArticleContent, urls: &UrlBuilder, organization: Option<&Organization>) -> String {
+    let article = &content.article;
+    let mut html = String::new();
+
+    html.push_str("<!doctype html>\n");
+    html.push_str(&format!(
+        r#"<html lang="{}" prefix="op: http://media.facebook.com/op#">"#,
+        escape(&content.language)
+    ));
+    html.push_str("<head>");
+    html.push_str(r#"<meta charset="utf-8">"#);
+    html.push_str(&format!(r#"<link rel="canonical" href="{}">"#, escape(&content.canonical_url)));
+    html.push_str(r#"<meta property="op:markup_version" content="v1.0">"#);
+    html.push_str(&format!(r#"<meta property="fb:article_style" content="{}">"#, escape(&article_style())));
+    html.push_str("</head>");
+    html.push_str("<body><article><header>");
+
+    if let Some(media) = content.feature_media.as_ref().filter(|media| media.is_image()) {
+        html.push_str(&figure(media));
+    }
+
+    html.push_str(&format!("<h1>{}</h1>", escape(&article.title)));
+
+    let lead = plain_text(&article.lead);
+    if !lead.is_empty() {
+        html.push_str(&format!("<h2>{}</h2>", escape(&lead)));
+    }
+
+    if let Some(published_at) = article.published_at {
+        html.push_str(&time("op-published", published_at, &content.language));
+    }
+
+    if let Some(updated_at) = article.updated_at {
+        html.push_str(&time("op-modified", updated_at, &content.language));
+    }
+
+    for author in &content.authors {
+        match &author.slug {
+            Some(slug) => html.push_str(&format!(
+                r#"<address><a href="{}">{}</a></address>"#,
+                escape(&urls.author_url(slug)),
+                escape(&author.name)
+            )),
+            None => html.push_str(&format!("<address>{}</address>", escape(&author.name))),
+        }
+    }
+
+    html.push_str("</header>");
+
+    for block in blocks(&article.body) {
+        html.push_str(&element(&block));
+    }
+
+    for slideshow in &content.slideshows {
+        let figures = slideshow
+            .items
+            .iter()
+            .filter(|media| media.is_image())
+            .map(figure)
+            .collect::<String>();
+
+        if !figures.is_empty() {
+            html.push_str(&format!(r#"<figure class="op-slideshow">{}</figure>"#, figures));
+        }
+    }
+
+    for media in content.media.iter().filter(|media| media.is_image()) {
+        html.push_str(&figure(media));
+    }
+
+    if let Some(organization) = organization {
+        let year = article.published_at.map_or_else(|| Utc::now().year(), |published_at| published_at.year());
+        html.push_str(&format!(
+            "<footer><small>© {} {}</small></footer>",
+            year,
+            escape(&organization.name)
+        ));
+    }
+
+    html.push_str("</article></body></html>");
+
+    html
+}
+
+fn article_style() -> String {
+    std::env::var("PUBLISHER_INSTANT_ARTICLES_STYLE").unwrap_or_else(|_| "default".to_string())
+}
+
+fn time(class: &str, date: NaiveDateTime, language: &str) -> String {
+    format!(
+        r#"<time class="{}" datetime="{}">{}</time>"#,
+        class,
+        format_date(date),
+        format_long_date(date, language)
+    )
+}
+
+fn figure(media: &MediaItem) -> String {
+    image_figure(&media.url, &media.caption)
+}
+
+fn image_figure(src: &str, caption: &str) -> String {
+    let caption = if caption.trim().is_empty() {
+        String::new()
+    } else {
+        format!("<figcaption>{}</figcaption>", escape(caption))
+    };
+
+    format!(r#"<figure><img src="{}">{}</figure>"#, escape(src), caption)
+}
+
+fn element(block: &Block) -> String {
+    match block.is_heading() {
+        Some(1) => return format!("<h1>{}</h1>", escape(&block.text())),
+        Some(_) => return format!("<h2>{}</h2>", escape(&block.text())),
+        None => {}
+    }
+
+    match block.tag.as_str() {
+        "img" | "figure" => match block.attribute("src") {
+            Some(src) => {
+                let caption = block.attribute("alt").unwrap_or_default();
+                image_figure(&src, &caption)
+            }
+            None => String::new(),
+        },
+        "iframe" => {
+            let attributes = parse_attributes(&block.attributes)
+                .into_iter()
+                .filter(|(name, _)| ["src", "width", "height"].contains(&name.as_str()))
+                .collect::<Vec<_>>();
+            format!(r#"<figure class="op-interactive"><iframe{}></iframe></figure>"#, write_attributes(&attributes))
+        }
+        "blockquote" => format!("<blockquote>{}</blockquote>", escape(&block.text())),
+        "ul" | "ol" | "pre" => block.outer_html(),
+        "script" | "style" | "hr" => String::new(),
+        "p" if block.text().is_empty() => match block.attribute("src") {
+            // paragraphs only containing an image
+            Some(src) => image_figure(&src, &block.attribute("alt").unwrap_or_default()),
+            None => String::new(),
+        },
+        "p" => format!("<p>{}</p>", block.inner_html),
+        _ => {
+            let text = block.text();
+            if text.is_empty() {
+                String::new()
+            } else {
+                format!("<p>{}</p>", escape(&text))
+            }
+        }
+    }
+}
+
+pub fn article_response(id: i32, context: &Context) -> Result<CacheableResponse, Status> {
+    let content = ArticleContent::load_published(id, context)
+        .map_err(internal_error)?
+        .filter(|content| content.enabled_for(RouteFormat::InstantArticles, context))
+        .ok_or(Status::NotFound)?;
+    let body = render(&content, &context.urls, context.organization.as_ref());
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(ContentType::HTML)
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", MAX_AGE))
+        .last_modified(content.article.updated_at.or(content.article.published_at))
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+/// RSS feed of the latest articles of the routes Instant Articles are enabled
+/// for (of the given route only when set), with the Instant Article markup
+/// as `content:encoded`.
+pub fn feed_response(route: Option<i32>, limit: Option<i64>, context: &Context) -> Result<CacheableResponse, Status> {
+    let limit = limit.unwrap_or(DEFAULT_FEED_ARTICLES).max(1).min(MAX_FEED_ARTICLES);
+    let ids = latest_article_ids(route, limit, &context.db_con).map_err(internal_error)?;
+    let mut items = Vec::new();
+    let mut updated = None;
+
+    for id in ids {
+        let content = match ArticleContent::load_published(id, context).map_err(internal_error)? {
+            Some(content) if content.enabled_for(RouteFormat::InstantArticles, context) => content,
+            _ => continue,
+        };
+
+        let modified = content.article.updated_at.or(content.article.published_at);
+        updated = updated.max(modified);
+
+        items.push(FeedItem {
+            id: content.article.id.to_string(),
+            content_html: render(&content, &context.urls, context.organization.as_ref()),
+            url: content.canonical_url,
+            title: content.article.title.clone(),
+            summary: plain_text(&content.article.lead),
+            published_at: content.article.published_at,
+            updated_at: content.article.updated_at,
+            authors: content.authors.iter().map(|author| author.name.clone()).collect(),
+            categories: vec![content.route_name],
+            enclosure: None,
+        });
+    }
+
+    let feed = Feed {
+        title: context
+            .organization
+            .as_ref()
+            .map_or_else(|| "Instant Articles".to_string(), |organization| organization.name.clone()),
+        link: context.urls.site_url("/"),
+        feed_url: context.urls.api_url("/instant-articles/feed.rss"),
+        updated,
+        items,
+    };
+    let body = rss::render(&feed);
+
+    Ok(CacheableResponse::new(Status::Ok, body.clone())
+        .content_type(ContentType::new("application", "rss+xml"))
+        .etag_from(&body)
+        .cache_control(format!("public, max-age={}", MAX_AGE))
+        .last_modified(updated)
+        .surrogate_keys(context.response_metadata().surrogate_keys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixtures::{article_content, image};
+    use super::super::SlideshowContent;
+    use super::*;
+
+    fn render_body(body: &str) -> String {
+        let urls = UrlBuilder::new("https://www.example.com", None).unwrap();
+
+        render(&article_content(body), &urls, None)
+    }
+
+    #[test]
+    fn renders_the_document_and_the_header() {
+        let urls = UrlBuilder::new("https://www.example.com", None).unwrap();
+        let organization = Organization {
+            name: "Example News".to_string(),
+            logo: None,
+        };
+        let html = render(&article_content("<p>Text</p>"), &urls, Some(&organization));
+
+        assert!(html.starts_with(r#"<!doctype html>
+<html lang="de" prefix="op: http://media.facebook.com/op#"><head><meta charset="utf-8">"#));
+        assert!(html.contains(r#"<link rel="canonical" href="https://www.example.com/news/storm">"#));
+        assert!(html.contains(r#"<meta property="op:markup_version" content="v1.0">"#));
+        assert!(html.contains(r#"<meta property="fb:article_style" content="#));
+        assert!(html.contains(concat!(
+            r#"<body><article><header>"#,
+            r#"<figure><img src="https://cdn.example.com/feature.jpg"><figcaption>Caption of feature</figcaption></figure>"#,
+            r#"<h1>Storm &amp; rain</h1>"#,
+            r#"<h2>The storm hit the coast &amp; the city.</h2>"#,
+            r#"<time class="op-published" datetime="2019-05-01T12:00:00Z">1. Mai 2019</time>"#,
+            r#"<time class="op-modified" datetime="2019-05-02T08:30:00Z">2. Mai 2019</time>"#,
+            r#"<address><a href="https://www.example.com/author/jane-doe">Jane Doe</a></address>"#,
+            r#"</header><p>Text</p>"#,
+        )));
+        assert!(html.ends_with("<footer><small>© 2019 Example News</small></footer></article></body></html>"));
+    }
+
+    #[test]
+    fn only_has_h1_and_h2_headings() {
+        let html = render_body("<h1>Part</h1><h3>Section</h3><h6>Note</h6>");
+
+        assert!(html.contains("</header><h1>Part</h1><h2>Section</h2><h2>Note</h2></article>"));
+    }
+
+    #[test]
+    fn puts_images_in_figures() {
+        let html = render_body(concat!(
+            r#"<figure><img src="https://cdn.example.com/a.jpg" alt="A"></figure>"#,
+            r#"<p><img src="https://cdn.example.com/b.jpg"></p>"#,
+            r#"<img src="https://cdn.example.com/c.jpg">"#,
+        ));
+
+        assert!(html.contains(concat!(
+            r#"</header>"#,
+            r#"<figure><img src="https://cdn.example.com/a.jpg"><figcaption>A</figcaption></figure>"#,
+            r#"<figure><img src="https://cdn.example.com/b.jpg"></figure>"#,
+            r#"<figure><img src="https://cdn.example.com/c.jpg"></figure>"#,
+            r#"</article>"#,
+        )));
+    }
+
+    #[test]
+    fn puts_embeds_in_interactive_figures() {
+        let html = render_body(r#"<iframe src="https://www.example.com/embed" width="560" height="315" onload="track()" style="border: 0"></iframe>"#);
+
+        assert!(html.contains(
+            r#"<figure class="op-interactive"><iframe src="https://www.example.com/embed" width="560" height="315"></iframe></figure>"#
+        ));
+    }
+
+    #[test]
+    fn drops_scripts_styles_and_rules() {
+        let html = render_body("<p>Before</p><script>alert(1)</script><style>p {}</style><hr><p>After</p>");
+
+        assert!(html.contains("</header><p>Before</p><p>After</p></article>"));
+    }
+
+    #[test]
+    fn renders_slideshows_and_media() {
+        let mut content = article_content("");
+        content.slideshows.push(SlideshowContent {
+            code: "gallery".to_string(),
+            items: vec![image(2, "first"), image(3, "second")],
+        });
+        content.media.push(image(4, "other"));
+        let urls = UrlBuilder::new("https://www.example.com", None).unwrap();
+        let html = render(&content, &urls, None);
+
+        assert!(html.contains(concat!(
+            r#"</header><figure class="op-slideshow">"#,
+            r#"<figure><img src="https://cdn.example.com/first.jpg"><figcaption>Caption of first</figcaption></figure>"#,
+            r#"<figure><img src="https://cdn.example.com/second.jpg"><figcaption>Caption of second</figcaption></figure>"#,
+            r#"</figure>"#,
+            r#"<figure><img src="https://cdn.example.com/other.jpg"><figcaption>Caption of other</figcaption></figure>"#,
+            r#"</article>"#,
+        )));
+    }
+}
@@ -0,0 +1,149 @@
+//! Minimal writer of (ustar) tar archives, for exports bundling several
+//! files into one download.
+
+const BLOCK_SIZE: usize = 512;
+const NAME_SIZE: usize = 100;
+const PREFIX_SIZE: usize = 155;
+
+#[derive(Default)]
+pub struct TarBuilder {
+    data: Vec<u8>,
+}
+
+impl TarBuilder {
+    pub fn new() -> Self {
+        TarBuilder::default()
+    }
+
+    /// Appends a regular file. Paths longer than 100 bytes are split between
+    /// the ustar prefix and name fields at a `/`, paths that can't be split
+    /// are rejected.
+    pub fn append(&mut self, path: &str, contents: &[u8], modified: i64) -> Result<(), String> {
+        let (prefix, name) = split_path(path).ok_or_else(|| format!("path too long for a tar archive: {}", path))?;
+        let mut header = [0u8; BLOCK_SIZE];
+
+        write_field(&mut header[0..100], name.as_bytes());
+        write_field(&mut header[100..108], b"0000644");
+        write_field(&mut header[108..116], b"0000000");
+        write_field(&mut header[116..124], b"0000000");
+        write_field(&mut header[124..136], format!("{:011o}", contents.len()).as_bytes());
+        write_field(&mut header[136..148], format!("{:011o}", modified.max(0)).as_bytes());
+        header[156] = b'0';
+        write_field(&mut header[257..263], b"ustar\0");
+        write_field(&mut header[263..265], b"00");
+        write_field(&mut header[345..500], prefix.as_bytes());
+
+        // the checksum is computed with the checksum field filled with spaces
+        for byte in &mut header[148..156] {
+            *byte = b' ';
+        }
+        let checksum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+        write_field(&mut header[148..156], format!("{:06o}\0 ", checksum).as_bytes());
+
+        self.data.extend_from_slice(&header);
+        self.data.extend_from_slice(contents);
+        self.pad();
+
+        Ok(())
+    }
+
+    /// Archive terminated by two empty blocks.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.data.extend_from_slice(&[0u8; BLOCK_SIZE * 2]);
+        self.data
+    }
+
+    fn pad(&mut self) {
+        let remainder = self.data.len() % BLOCK_SIZE;
+
+        if remainder != 0 {
+            self.data.extend(std::iter::repeat(0).take(BLOCK_SIZE - remainder));
+        }
+    }
+}
+
+/// Prefix and name fields of the path, `None` when it doesn't fit.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME_SIZE {
+        return Some(("", path));
+    }
+
+    path.match_indices('/')
+        .map(|(index, _)| (&path[..index], &path[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= PREFIX_SIZE && !name.is_empty() && name.len() <= NAME_SIZE)
+}
+
+fn write_field(field: &mut [u8], value: &[u8]) {
+    let length = value.len().min(field.len());
+    field[..length].copy_from_slice(&value[..length]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(header: &[u8], range: std::ops::Range<usize>) -> &str {
+        let field = &header[range];
+        let end = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+
+        std::str::from_utf8(&field[..end]).unwrap()
+    }
+
+    #[test]
+    fn writes_the_header_and_pads_the_contents() {
+        let mut builder = TarBuilder::new();
+        builder.append("article-1/article.json", b"{}", 1_556_712_000).unwrap();
+        let archive = builder.finish();
+        let header = &archive[..BLOCK_SIZE];
+
+        assert_eq!(archive.len(), BLOCK_SIZE * 4);
+        assert_eq!(field(header, 0..100), "article-1/article.json");
+        assert_eq!(field(header, 100..108), "0000644");
+        assert_eq!(field(header, 124..136), "00000000002");
+        assert_eq!(field(header, 136..148), format!("{:011o}", 1_556_712_000));
+        assert_eq!(header[156], b'0');
+        assert_eq!(field(header, 257..263), "ustar");
+        assert_eq!(field(header, 345..500), "");
+        assert_eq!(&archive[BLOCK_SIZE..BLOCK_SIZE + 2], b"{}");
+        assert!(archive[BLOCK_SIZE + 2..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn writes_the_checksum_of_the_header() {
+        let mut builder = TarBuilder::new();
+        builder.append("article-1/article.json", b"{}", 0).unwrap();
+        let archive = builder.finish();
+        let header = &archive[..BLOCK_SIZE];
+
+        let expected: u32 = header
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| if (148..156).contains(&index) { u32::from(b' ') } else { u32::from(*byte) })
+            .sum();
+
+        assert_eq!(&header[154..156], b"\0 ");
+        assert_eq!(u32::from_str_radix(field(header, 148..154), 8).unwrap(), expected);
+    }
+
+    #[test]
+    fn splits_long_paths_between_the_prefix_and_the_name() {
+        let directory = "d".repeat(120);
+        let path = format!("{}/article.json", directory);
+        let mut builder = TarBuilder::new();
+        builder.append(&path, b"{}", 0).unwrap();
+        let archive = builder.finish();
+
+        assert_eq!(field(&archive, 0..100), "article.json");
+        assert_eq!(field(&archive, 345..500), directory);
+    }
+
+    #[test]
+    fn rejects_paths_that_do_not_fit() {
+        let mut builder = TarBuilder::new();
+
+        assert!(builder.append(&"a".repeat(101), b"", 0).is_err());
+        assert!(builder.append(&format!("{}/{}", "d".repeat(156), "a"), b"", 0).is_err());
+        assert!(builder.append(&format!("d/{}", "a".repeat(101)), b"", 0).is_err());
+        assert_eq!(builder.finish().len(), BLOCK_SIZE * 2);
+    }
+}
@@ -0,0 +1,492 @@
+//! WordPress eXtended RSS (WXR 1.2) export of published articles, importable
+//! with WordPress' importer. Authors are exported as `wp:author`, routes as
+//! categories, keywords as tags and media as attachments linking to the CDN.
+//!
+//! Like the sitemaps, articles are rendered in batches while the export is
+//! written, so memory usage does not depend on the number of articles.
+
+use std::collections::BTreeSet;
+use std::io::{self, Cursor as IoCursor, Read, Write};
+use chrono::prelude::*;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use rocket::{
+    http::{ContentType, Header, Status},
+    request::{self, FromRequest},
+    response::{self, Responder, Response},
+    Outcome, Request, State,
+};
+use crate::changes::webhook::constant_time_eq;
+use crate::dates::format_rfc822_date;
+use crate::db::DbConnPool;
+use crate::graphql::generator::UrlBuilders;
+use crate::graphql::seo::plain_text;
+use crate::graphql::{Context, ContextConfig};
+use crate::models::{Article as ArticleModel, Author as AuthorModel};
+use crate::schema::{swp_article, swp_article_author, swp_article_keyword, swp_author, swp_keyword, swp_route};
+use crate::tenant::TenantLanguages;
+use crate::xml::{cdata, escape};
+use super::{ArticleContent, MediaItem, RouteFormats, PUBLISHED};
+
+const BATCH_SIZE: i64 = 100;
+/// Added to media ids to get the attachments' post ids, so they don't collide
+/// with the articles' ids.
+const ATTACHMENT_ID_OFFSET: i64 = 1_000_000_000;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const POST_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const RSS_OPEN: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/" xmlns:content="http://purl.org/rss/1.0/modules/content/" xmlns:wfw="http://wellformedweb.org/CommentAPI/" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+"#;
+const RSS_CLOSE: &str = "</channel>\n</rss>\n";
+
+/// Articles to export, all published articles when nothing is set. Dates are
+/// inclusive and compared with the publishing date.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WxrFilter {
+    pub route: Option<i32>,
+    pub keyword: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl WxrFilter {
+    /// Parses the `YYYY-MM-DD` dates, `None` when one is invalid.
+    pub fn parse(route: Option<i32>, keyword: Option<String>, from: Option<&str>, to: Option<&str>) -> Option<Self> {
+        let date = |value: Option<&str>| match value {
+            Some(value) => NaiveDate::parse_from_str(value, DATE_FORMAT).ok().map(Some),
+            None => Some(None),
+        };
+
+        Some(WxrFilter {
+            route,
+            keyword: keyword.filter(|keyword| !keyword.is_empty()),
+            from: date(from)?,
+            to: date(to)?,
+        })
+    }
+
+    fn articles(&self) -> swp_article::BoxedQuery<'static, Pg> {
+        let mut query = swp_article::table.filter(swp_article::status.eq(PUBLISHED)).into_boxed();
+
+        if let Some(route) = self.route {
+            query = query.filter(swp_article::route_id.eq(route));
+        }
+
+        if let Some(keyword) = &self.keyword {
+            query = query.filter(
+                swp_article::id.eq_any(
+                    swp_article_keyword::table
+                        .inner_join(swp_keyword::table)
+                        .filter(swp_keyword::slug.eq(keyword.clone()))
+                        .select(swp_article_keyword::article_id),
+                ),
+            );
+        }
+
+        if let Some(from) = self.from {
+            query = query.filter(swp_article::published_at.ge(from.and_hms(0, 0, 0)));
+        }
+
+        if let Some(to) = self.to {
+            query = query.filter(swp_article::published_at.lt(to.succ().and_hms(0, 0, 0)));
+        }
+
+        query
+    }
+}
+
+/// Renders the export: the channel with the authors, categories and tags
+/// first, then the articles one batch at a time.
+pub struct WxrStream {
+    context: Context,
+    filter: WxrFilter,
+    after_id: i32,
+    buffer: IoCursor<Vec<u8>>,
+    finished: bool,
+}
+
+impl WxrStream {
+    pub fn new(context: Context, filter: WxrFilter) -> QueryResult<Self> {
+        let header = channel_header(&context, &filter)?;
+
+        Ok(WxrStream {
+            context,
+            filter,
+            after_id: 0,
+            buffer: IoCursor::new(header.into_bytes()),
+            finished: false,
+        })
+    }
+
+    fn next_batch(&mut self) -> QueryResult<Option<String>> {
+        let articles = self
+            .filter
+            .articles()
+            .filter(swp_article::id.gt(self.after_id))
+            .order(swp_article::id.asc())
+            .limit(BATCH_SIZE)
+            .load::<ArticleModel>(&self.context.db_con)?;
+
+        self.after_id = match articles.last() {
+            Some(last) => last.id,
+            None => return Ok(None),
+        };
+
+        let mut xml = String::new();
+
+        for article in articles {
+            let content = ArticleContent::load(article, &self.context)?;
+            xml.push_str(&article_item(&content));
+
+            // the feature media can also be part of a slideshow
+            let mut exported = BTreeSet::new();
+            let attachments = content
+                .feature_media
+                .iter()
+                .chain(content.slideshows.iter().flat_map(|slideshow| slideshow.items.iter()))
+                .chain(content.media.iter())
+                .filter(|media| exported.insert(media.id));
+            for media in attachments {
+                xml.push_str(&attachment_item(media, &content));
+            }
+        }
+
+        // the keys are only used for caching headers, which exports don't have,
+        // and the rows cached by the loader would grow for the whole export
+        *self.context.response_metadata.lock().expect("response metadata lock poisoned") = Default::default();
+        self.context.loader.clear();
+
+        Ok(Some(xml))
+    }
+}
+
+impl Read for WxrStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.buffer.read(buf)?;
+            if read > 0 || self.finished {
+                return Ok(read);
+            }
+
+            let next = match self.next_batch() {
+                Ok(Some(xml)) => xml,
+                Ok(None) => {
+                    self.finished = true;
+                    RSS_CLOSE.to_string()
+                }
+                Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error.to_string())),
+            };
+
+            self.buffer = IoCursor::new(next.into_bytes());
+        }
+    }
+}
+
+fn channel_header(context: &Context, filter: &WxrFilter) -> QueryResult<String> {
+    let conn = &context.db_con;
+    let site_url = context.urls.site_url("/");
+    let title = context
+        .organization
+        .as_ref()
+        .map_or_else(|| site_url.clone(), |organization| organization.name.clone());
+    let language = &context.tenant.language;
+
+    let mut xml = String::from(RSS_OPEN);
+    xml.push_str(&format!("<title>{}</title>\n", escape(&title)));
+    xml.push_str(&format!("<link>{}</link>\n", escape(&site_url)));
+    xml.push_str("<description></description>\n");
+    xml.push_str(&format!("<pubDate>{}</pubDate>\n", format_rfc822_date(Utc::now().naive_utc())));
+    xml.push_str(&format!("<language>{}</language>\n", escape(language)));
+    xml.push_str("<wp:wxr_version>1.2</wp:wxr_version>\n");
+    xml.push_str(&format!("<wp:base_site_url>{}</wp:base_site_url>\n", escape(&site_url)));
+    xml.push_str(&format!("<wp:base_blog_url>{}</wp:base_blog_url>\n", escape(&site_url)));
+
+    let authors = swp_author::table
+        .filter(
+            swp_author::id.eq_any(
+                swp_article_author::table
+                    .filter(swp_article_author::article_id.eq_any(filter.articles().select(swp_article::id)))
+                    .select(swp_article_author::author_id),
+            ),
+        )
+        .order(swp_author::id.asc())
+        .load::<AuthorModel>(conn)?;
+
+    for author in &authors {
+        xml.push_str("<wp:author>");
+        xml.push_str(&format!("<wp:author_id>{}</wp:author_id>", author.id));
+        xml.push_str(&format!("<wp:author_login>{}</wp:author_login>", cdata(&author_login(author))));
+        xml.push_str("<wp:author_email></wp:author_email>");
+        xml.push_str(&format!("<wp:author_display_name>{}</wp:author_display_name>", cdata(&author.name)));
+        xml.push_str("<wp:author_first_name></wp:author_first_name>");
+        xml.push_str("<wp:author_last_name></wp:author_last_name>");
+        xml.push_str("</wp:author>\n");
+    }
+
+    let routes = swp_route::table
+        .filter(swp_route::id.eq_any(filter.articles().select(swp_article::route_id)))
+        .select((swp_route::id, swp_route::name, swp_route::slug))
+        .order(swp_route::id.asc())
+        .load::<(i32, String, String)>(conn)?;
+
+    for (id, name, slug) in &routes {
+        xml.push_str("<wp:category>");
+        xml.push_str(&format!("<wp:term_id>{}</wp:term_id>", id));
+        xml.push_str(&format!("<wp:category_nicename>{}</wp:category_nicename>", cdata(slug)));
+        xml.push_str("<wp:category_parent></wp:category_parent>");
+        xml.push_str(&format!("<wp:cat_name>{}</wp:cat_name>", cdata(name)));
+        xml.push_str("</wp:category>\n");
+    }
+
+    let keywords = swp_keyword::table
+        .filter(
+            swp_keyword::id.eq_any(
+                swp_article_keyword::table
+                    .filter(swp_article_keyword::article_id.eq_any(filter.articles().select(swp_article::id)))
+                    .select(swp_article_keyword::keyword_id),
+            ),
+        )
+        .select((swp_keyword::id, swp_keyword::name, swp_keyword::slug))
+        .order(swp_keyword::id.asc())
+        .load::<(i32, String, String)>(conn)?;
+
+    for (id, name, slug) in &keywords {
+        xml.push_str("<wp:tag>");
+        xml.push_str(&format!("<wp:term_id>{}</wp:term_id>", id));
+        xml.push_str(&format!("<wp:tag_slug>{}</wp:tag_slug>", cdata(slug)));
+        xml.push_str(&format!("<wp:tag_name>{}</wp:tag_name>", cdata(name)));
+        xml.push_str("</wp:tag>\n");
+    }
+
+    Ok(xml)
+}
+
+/// WordPress logins are unique, authors without slug get one from their id.
+fn author_login(author: &AuthorModel) -> String {
+    author.slug.clone().unwrap_or_else(|| format!("author-{}", author.id))
+}
+
+fn post_date(date: Option<NaiveDateTime>) -> String {
+    date.map(|date| date.format(POST_DATE_FORMAT).to_string()).unwrap_or_default()
+}
+
+fn article_item(content: &ArticleContent) -> String {
+    let article = &content.article;
+    let mut xml = String::from("<item>");
+
+    xml.push_str(&format!("<title>{}</title>", escape(&article.title)));
+    xml.push_str(&format!("<link>{}</link>", escape(&content.url)));
+
+    if let Some(published_at) = article.published_at {
+        xml.push_str(&format!("<pubDate>{}</pubDate>", format_rfc822_date(published_at)));
+    }
+
+    // WXR has a single author per post, the others are kept in a meta field
+    let creator = content.authors.first().map(author_login).unwrap_or_default();
+    xml.push_str(&format!("<dc:creator>{}</dc:creator>", cdata(&creator)));
+    xml.push_str(&format!(r#"<guid isPermaLink="false">{}</guid>"#, escape(&content.canonical_url)));
+    xml.push_str("<description></description>");
+    xml.push_str(&format!("<content:encoded>{}</content:encoded>", cdata(&article.body)));
+    xml.push_str(&format!("<excerpt:encoded>{}</excerpt:encoded>", cdata(&plain_text(&article.lead))));
+    xml.push_str(&format!("<wp:post_id>{}</wp:post_id>", article.id));
+    xml.push_str(&post_fields(article.published_at, article.updated_at));
+    xml.push_str(&format!("<wp:post_name>{}</wp:post_name>", cdata(&article.slug)));
+    xml.push_str("<wp:status><![CDATA[publish]]></wp:status>");
+    xml.push_str("<wp:post_parent>0</wp:post_parent>");
+    xml.push_str("<wp:menu_order>0</wp:menu_order>");
+    xml.push_str("<wp:post_type><![CDATA[post]]></wp:post_type>");
+    xml.push_str("<wp:post_password></wp:post_password>");
+    xml.push_str("<wp:is_sticky>0</wp:is_sticky>");
+    xml.push_str(&format!(
+        r#"<category domain="category" nicename="{}">{}</category>"#,
+        escape(&content.route_slug),
+        cdata(&content.route_name)
+    ));
+
+    for (name, slug) in &content.keywords {
+        xml.push_str(&format!(r#"<category domain="post_tag" nicename="{}">{}</category>"#, escape(slug), cdata(name)));
+    }
+
+    if let Some(media) = &content.feature_media {
+        xml.push_str(&post_meta("_thumbnail_id", &(ATTACHMENT_ID_OFFSET + i64::from(media.id)).to_string()));
+    }
+
+    if content.authors.len() > 1 {
+        xml.push_str(&post_meta("publisher_authors", &content.author_names().join(", ")));
+    }
+
+    xml.push_str(&post_meta("publisher_article_id", &article.id.to_string()));
+    xml.push_str("</item>\n");
+
+    xml
+}
+
+fn attachment_item(media: &MediaItem, content: &ArticleContent) -> String {
+    let article = &content.article;
+    let title = if media.caption.is_empty() { &media.key } else { &media.caption };
+    let mut xml = String::from("<item>");
+
+    xml.push_str(&format!("<title>{}</title>", escape(title)));
+    xml.push_str(&format!("<link>{}</link>", escape(&media.url)));
+    xml.push_str(&format!(r#"<guid isPermaLink="false">{}</guid>"#, escape(&media.url)));
+    xml.push_str("<description></description>");
+    xml.push_str("<content:encoded><![CDATA[]]></content:encoded>");
+    xml.push_str(&format!("<excerpt:encoded>{}</excerpt:encoded>", cdata(&media.caption)));
+    xml.push_str(&format!("<wp:post_id>{}</wp:post_id>", ATTACHMENT_ID_OFFSET + i64::from(media.id)));
+    xml.push_str(&post_fields(article.published_at, article.updated_at));
+    xml.push_str(&format!("<wp:post_name>{}</wp:post_name>", cdata(&format!("{}-{}", article.slug, media.key))));
+    xml.push_str("<wp:status><![CDATA[inherit]]></wp:status>");
+    xml.push_str(&format!("<wp:post_parent>{}</wp:post_parent>", article.id));
+    xml.push_str("<wp:menu_order>0</wp:menu_order>");
+    xml.push_str("<wp:post_type><![CDATA[attachment]]></wp:post_type>");
+    xml.push_str("<wp:post_password></wp:post_password>");
+    xml.push_str("<wp:is_sticky>0</wp:is_sticky>");
+    xml.push_str(&format!("<wp:attachment_url>{}</wp:attachment_url>", cdata(&media.url)));
+
+    if !media.by_line.is_empty() {
+        xml.push_str(&post_meta("publisher_by_line", &media.by_line));
+    }
+
+    xml.push_str("</item>\n");
+
+    xml
+}
+
+/// Dates and comment settings, Publisher stores dates in UTC.
+fn post_fields(published_at: Option<NaiveDateTime>, updated_at: Option<NaiveDateTime>) -> String {
+    let published_at = post_date(published_at);
+    let updated_at = post_date(updated_at);
+
+    format!(
+        "<wp:post_date>{0}</wp:post_date><wp:post_date_gmt>{0}</wp:post_date_gmt>\
+         <wp:post_modified>{1}</wp:post_modified><wp:post_modified_gmt>{1}</wp:post_modified_gmt>\
+         <wp:comment_status><![CDATA[closed]]></wp:comment_status><wp:ping_status><![CDATA[closed]]></wp:ping_status>",
+        cdata(&published_at),
+        cdata(&updated_at)
+    )
+}
+
+fn post_meta(key: &str, value: &str) -> String {
+    format!(
+        "<wp:postmeta><wp:meta_key>{}</wp:meta_key><wp:meta_value>{}</wp:meta_value></wp:postmeta>",
+        cdata(key),
+        cdata(value)
+    )
+}
+
+/// Token of the exports, `PUBLISHER_EXPORT_TOKEN` read once at startup.
+/// Exports are disabled when it is not set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportToken(Option<String>);
+
+impl ExportToken {
+    pub fn from_env() -> Self {
+        ExportToken(std::env::var("PUBLISHER_EXPORT_TOKEN").ok().filter(|token| !token.is_empty()))
+    }
+}
+
+/// Guard checking the export token, sent as a bearer token. It isn't
+/// accepted in the query string, where it would end up in access logs.
+pub struct ExportAuthentication;
+
+impl<'a, 'r> FromRequest<'a, 'r> for ExportAuthentication {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<ExportAuthentication, ()> {
+        let export_token = request.guard::<State<ExportToken>>()?;
+        let secret = match export_token.0 {
+            Some(ref secret) => secret,
+            None => return Outcome::Failure((Status::NotFound, ())),
+        };
+
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .filter(|authorization| authorization.starts_with("Bearer "))
+            .map(|authorization| &authorization["Bearer ".len()..]);
+
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), secret.as_bytes()) => {
+                Outcome::Success(ExportAuthentication)
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+pub struct WxrResponse(WxrStream);
+
+impl<'r> Responder<'r> for WxrResponse {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::XML)
+            .header(Header::new("Content-Disposition", r#"attachment; filename="wordpress.xml""#))
+            .header(Header::new("Cache-Control", "no-store"))
+            .streamed_body(self.0)
+            .ok()
+    }
+}
+
+pub fn export_response(
+    route: Option<i32>,
+    keyword: Option<String>,
+    from: Option<&str>,
+    to: Option<&str>,
+    context: Context,
+) -> Result<WxrResponse, Status> {
+    let filter = WxrFilter::parse(route, keyword, from, to).ok_or(Status::BadRequest)?;
+
+    WxrStream::new(context, filter).map(WxrResponse).map_err(super::internal_error)
+}
+
+/// `export-wxr [--tenant <code>] [--route <id>] [--keyword <slug>]
+/// [--from <YYYY-MM-DD>] [--to <YYYY-MM-DD>] [--output <file>]`, writes to
+/// the standard output unless an output file is given.
+pub fn export_command(
+    args: &[String],
+    db_pool: &DbConnPool,
+    urls: &UrlBuilders,
+    languages: &TenantLanguages,
+    route_formats: &RouteFormats,
+    config: &ContextConfig,
+) -> Result<(), String> {
+    let mut tenant = crate::tenant::DEFAULT_TENANT.to_string();
+    let mut route = None;
+    let mut keyword = None;
+    let mut from = None;
+    let mut to = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value of {}", arg))?.clone();
+
+        match arg.as_str() {
+            "--tenant" => tenant = value,
+            "--route" => route = Some(value.parse::<i32>().map_err(|_| format!("invalid route id {}", value))?),
+            "--keyword" => keyword = Some(value),
+            "--from" => from = Some(value),
+            "--to" => to = Some(value),
+            "--output" => output = Some(value),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    let filter = WxrFilter::parse(route, keyword, from.as_ref().map(String::as_str), to.as_ref().map(String::as_str))
+        .ok_or_else(|| "dates must be formatted as YYYY-MM-DD".to_string())?;
+    let db_con = db_pool.get().map_err(|error| error.to_string())?;
+    let context = Context::for_tenant(db_con, &languages.tenant(&tenant), urls, route_formats, config);
+    let mut stream = WxrStream::new(context, filter).map_err(|error| error.to_string())?;
+
+    let written = match output {
+        Some(path) => {
+            let mut file = io::BufWriter::new(std::fs::File::create(&path).map_err(|error| format!("{}: {}", path, error))?);
+            io::copy(&mut stream, &mut file).and_then(|_| file.flush())
+        }
+        None => io::copy(&mut stream, &mut io::stdout().lock()).map(|_| ()),
+    };
+
+    written.map_err(|error| error.to_string())
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use rocket::{
+    request::{self, FromRequest, Request},
+    Outcome, State,
+};
+
+pub const TENANT_HEADER: &str = "X-Publisher-Tenant";
+pub const DEFAULT_TENANT: &str = "default";
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Tenant the request is made for, taken from the `X-Publisher-Tenant` header
+/// or, when it is missing, from the host name the API was called on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub code: String,
+    /// Language of the tenant's website, e.g. `en` or `de-AT`.
+    pub language: String,
+}
+
+/// Languages of the tenants' websites, configured once at startup:
+/// `PUBLISHER_TENANT_LANGUAGES` (`tenant=language` pairs separated by commas),
+/// `PUBLISHER_LANGUAGE` for the other tenants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TenantLanguages {
+    default: String,
+    tenants: HashMap<String, String>,
+}
+
+impl TenantLanguages {
+    pub fn from_env() -> Self {
+        let tenants = std::env::var("PUBLISHER_TENANT_LANGUAGES")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(code), Some(language)) if !code.trim().is_empty() && !language.trim().is_empty() => {
+                        Some((code.trim().to_lowercase(), language.trim().to_string()))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        TenantLanguages {
+            default: std::env::var("PUBLISHER_LANGUAGE")
+                .ok()
+                .filter(|language| !language.is_empty())
+                .unwrap_or_else(|| DEFAULT_LANGUAGE.to_string()),
+            tenants,
+        }
+    }
+
+    /// Tenant named by the `X-Publisher-Tenant` header or, when it is
+    /// missing, by the host name of the `Host` header.
+    pub fn resolve(&self, tenant_header: Option<&str>, host: Option<&str>) -> Tenant {
+        let code = tenant_header
+            .or_else(|| host.map(|host| host.split(':').next().unwrap_or(host)))
+            .filter(|code| !code.is_empty())
+            .unwrap_or(DEFAULT_TENANT);
+
+        self.tenant(code)
+    }
+
+    pub fn tenant(&self, code: &str) -> Tenant {
+        let code = code.to_lowercase();
+
+        Tenant {
+            language: self.tenants.get(&code).unwrap_or(&self.default).clone(),
+            code,
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Tenant {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Tenant, ()> {
+        let languages = request.guard::<State<TenantLanguages>>()?;
+        let headers = request.headers();
+
+        Outcome::Success(languages.resolve(headers.get_one(TENANT_HEADER), headers.get_one("Host")))
+    }
+}
+
+/// Organization publishing a tenant's website, e.g. the `publisher` of
+/// schema.org documents.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Organization {
+    pub name: String,
+    #[serde(default)]
+    pub logo: Option<String>,
+}
+
+/// Organizations of the tenants, configured once at startup:
+/// `PUBLISHER_TENANT_ORGANIZATIONS` (a JSON object keyed by tenant codes),
+/// `PUBLISHER_ORGANIZATION_NAME` and `PUBLISHER_ORGANIZATION_LOGO` for the
+/// other tenants.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TenantOrganizations {
+    default: Option<Organization>,
+    tenants: HashMap<String, Organization>,
+}
+
+impl TenantOrganizations {
+    pub fn from_env() -> Self {
+        let tenants = std::env::var("PUBLISHER_TENANT_ORGANIZATIONS")
+            .ok()
+            .and_then(|organizations| {
+                serde_json::from_str::<HashMap<String, Organization>>(&organizations)
+                    .map_err(|error| eprintln!("invalid PUBLISHER_TENANT_ORGANIZATIONS: {}", error))
+                    .ok()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(code, organization)| (code.to_lowercase(), organization))
+            .collect();
+
+        TenantOrganizations {
+            default: std::env::var("PUBLISHER_ORGANIZATION_NAME").ok().map(|name| Organization {
+                name,
+                logo: std::env::var("PUBLISHER_ORGANIZATION_LOGO").ok(),
+            }),
+            tenants,
+        }
+    }
+
+    pub fn for_tenant(&self, code: &str) -> Option<Organization> {
+        self.tenants.get(&code.to_lowercase()).or_else(|| self.default.as_ref()).cloned()
+    }
+}
@@ -0,0 +1,504 @@
+//! Outbound webhooks notifying downstream systems about content changes.
+//!
+//! Subscriptions are read from the JSON file set in `PUBLISHER_WEBHOOKS_CONFIG`.
+//! For every matching change event the subscription's GraphQL query is run
+//! with the changed article's `id` as a variable, and the result is POSTed,
+//! signed with HMAC-SHA256, to the subscription's URL. Deleted articles are
+//! announced as `article.unpublished` with the data of the change event.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use juniper::http::GraphQLRequest;
+use sha2::Sha256;
+use crate::changes::{ChangeEvent, ChangeKind, Entity, EventBus};
+use crate::db::DbConnPool;
+use crate::graphql::generator::UrlBuilders;
+use crate::graphql::{Context, ContextConfig, Mutation, Query, Schema};
+use crate::models::Article as ArticleModel;
+use crate::syndication::RouteFormats;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 6;
+const DEFAULT_DEAD_LETTER_LOG: &str = "webhooks-dead-letter.log";
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookSubscription {
+    pub url: String,
+    pub secret: String,
+    /// GraphQL query run with the `$id` of the changed article.
+    pub query: String,
+    /// Event types (`published`, `updated`, `unpublished`), all when empty.
+    #[serde(default)]
+    pub events: Vec<ChangeKind>,
+    /// Route ids, all when empty.
+    #[serde(default)]
+    pub routes: Vec<i32>,
+    /// Article statuses, all when empty.
+    #[serde(default)]
+    pub statuses: Vec<String>,
+}
+
+impl WebhookSubscription {
+    pub fn matches(&self, kind: ChangeKind, article: &ChangedArticle) -> bool {
+        (self.events.is_empty() || self.events.contains(&kind))
+            && (self.routes.is_empty() || article.route_id.map_or(false, |route_id| self.routes.contains(&route_id)))
+            && (self.statuses.is_empty() || article.status.as_ref().map_or(true, |status| self.statuses.contains(status)))
+    }
+}
+
+/// The changed article subscriptions are matched against. Deleted articles
+/// only have the route of the change event and no status, they match any
+/// statuses since only published articles are announced when deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedArticle {
+    pub route_id: Option<i32>,
+    pub status: Option<String>,
+}
+
+impl ChangedArticle {
+    fn from_model(article: &ArticleModel) -> Self {
+        ChangedArticle {
+            route_id: Some(article.route_id),
+            status: Some(article.status.clone()),
+        }
+    }
+
+    fn deleted(event: &ChangeEvent) -> Self {
+        ChangedArticle {
+            route_id: event.route_id,
+            status: None,
+        }
+    }
+}
+
+/// How often and when failed deliveries are attempted again: after
+/// `base_delay`, then twice as long after each failure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempts: u32) -> Duration {
+        self.base_delay * 2u32.saturating_pow(attempts.saturating_sub(1))
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Delivery {
+    id: String,
+    url: String,
+    #[serde(skip)]
+    secret: String,
+    event: String,
+    body: String,
+    attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+/// Delivery waiting for its next attempt, ordered by the earliest attempt
+/// first.
+struct ScheduledDelivery {
+    due: Instant,
+    delivery: Delivery,
+}
+
+impl PartialEq for ScheduledDelivery {
+    fn eq(&self, other: &Self) -> bool {
+        self.due == other.due
+    }
+}
+
+impl Eq for ScheduledDelivery {}
+
+impl PartialOrd for ScheduledDelivery {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledDelivery {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.due.cmp(&self.due)
+    }
+}
+
+pub fn spawn_from_env(
+    bus: EventBus,
+    pool: DbConnPool,
+    urls: UrlBuilders,
+    route_formats: RouteFormats,
+    context_config: ContextConfig,
+) {
+    let config_path = match std::env::var("PUBLISHER_WEBHOOKS_CONFIG") {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+
+    let config = fs::read_to_string(&config_path).expect("failed to read PUBLISHER_WEBHOOKS_CONFIG");
+    let subscriptions: Vec<WebhookSubscription> =
+        serde_json::from_str(&config).expect("invalid webhooks configuration");
+
+    let retry_policy = RetryPolicy {
+        max_attempts: std::env::var("PUBLISHER_WEBHOOKS_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS),
+        base_delay: BASE_RETRY_DELAY,
+    };
+    let dead_letter_log = std::env::var("PUBLISHER_WEBHOOKS_DEAD_LETTER_LOG")
+        .unwrap_or_else(|_| DEFAULT_DEAD_LETTER_LOG.to_string());
+
+    let (deliveries, queue) = channel();
+    let events = bus.subscribe();
+
+    thread::Builder::new()
+        .name("webhooks-dispatcher".to_string())
+        .spawn(move || dispatch(events, subscriptions, pool, urls, route_formats, context_config, deliveries))
+        .expect("failed to spawn webhooks dispatcher thread");
+
+    thread::Builder::new()
+        .name("webhooks-delivery".to_string())
+        .spawn(move || deliver(queue, retry_policy, dead_letter_log))
+        .expect("failed to spawn webhooks delivery thread");
+}
+
+fn dispatch(
+    events: Receiver<ChangeEvent>,
+    subscriptions: Vec<WebhookSubscription>,
+    pool: DbConnPool,
+    urls: UrlBuilders,
+    route_formats: RouteFormats,
+    context_config: ContextConfig,
+    deliveries: Sender<Delivery>,
+) {
+    let schema = Schema::new(Query, Mutation);
+
+    for event in events {
+        if event.entity != Entity::Article {
+            continue;
+        }
+
+        let db_con = match pool.get() {
+            Ok(db_con) => db_con,
+            Err(error) => {
+                eprintln!("webhooks: {}", error);
+                continue;
+            }
+        };
+
+        let (kind, article) = {
+            use crate::schema::swp_article;
+
+            match swp_article::table.find(event.id).first::<ArticleModel>(&db_con).optional() {
+                Ok(Some(article)) => (event.kind, ChangedArticle::from_model(&article)),
+                Ok(None) => (ChangeKind::Unpublished, ChangedArticle::deleted(&event)),
+                Err(error) => {
+                    eprintln!("webhooks: {}", error);
+                    continue;
+                }
+            }
+        };
+
+        let context = Context::new(db_con, &urls, &route_formats, &context_config);
+        let event_name = format!("article.{}", kind.as_str());
+
+        for subscription in subscriptions.iter().filter(|subscription| subscription.matches(kind, &article)) {
+            let variables = serde_json::from_value(serde_json::json!({ "id": event.id }))
+                .expect("variables are valid input values");
+            let request = GraphQLRequest::new(subscription.query.clone(), None, Some(variables));
+            let response = serde_json::to_value(&request.execute(&schema, &context))
+                .expect("failed to serialize GraphQL response");
+
+            let body = serde_json::json!({
+                "event": event_name,
+                "article": { "id": event.id, "routeId": article.route_id, "status": article.status },
+                "payload": response,
+            });
+
+            let delivery = Delivery {
+                id: delivery_id(event.id),
+                url: subscription.url.clone(),
+                secret: subscription.secret.clone(),
+                event: event_name.clone(),
+                body: body.to_string(),
+                attempts: 0,
+                last_error: None,
+            };
+
+            if deliveries.send(delivery).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn deliver(queue: Receiver<Delivery>, retry_policy: RetryPolicy, dead_letter_log: String) {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to build webhooks HTTP client");
+    let mut scheduled = BinaryHeap::new();
+
+    loop {
+        let timeout = scheduled
+            .peek()
+            .map(|next: &ScheduledDelivery| next.due.saturating_duration_since(Instant::now()))
+            .unwrap_or_else(|| Duration::from_secs(3600));
+
+        match queue.recv_timeout(timeout) {
+            Ok(delivery) => scheduled.push(ScheduledDelivery { due: Instant::now(), delivery }),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => match scheduled.peek() {
+                // nothing is queued anymore, only the retries are left
+                Some(next) => thread::sleep(next.due.saturating_duration_since(Instant::now())),
+                None => return,
+            },
+        }
+
+        while scheduled.peek().map_or(false, |next| next.due <= Instant::now()) {
+            let mut delivery = scheduled.pop().expect("peeked delivery exists").delivery;
+            delivery.attempts += 1;
+
+            match send(&client, &delivery) {
+                Ok(()) => {}
+                Err(error) if delivery.attempts < retry_policy.max_attempts => {
+                    delivery.last_error = Some(error);
+                    scheduled.push(ScheduledDelivery {
+                        due: Instant::now() + retry_policy.delay(delivery.attempts),
+                        delivery,
+                    });
+                }
+                Err(error) => {
+                    delivery.last_error = Some(error);
+                    write_dead_letter(&dead_letter_log, &delivery);
+                }
+            }
+        }
+    }
+}
+
+fn send(client: &reqwest::blocking::Client, delivery: &Delivery) -> Result<(), String> {
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Publisher-Event", delivery.event.as_str())
+        .header("X-Publisher-Delivery", delivery.id.as_str())
+        .header("X-Publisher-Signature", format!("sha256={}", sign(&delivery.secret, &delivery.body)))
+        .body(delivery.body.clone())
+        .send()
+        .map_err(|error| error.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("endpoint responded with {}", response.status()))
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the body, receivers compute the same value with
+/// their copy of the secret to verify the `X-Publisher-Signature` header.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.input(body.as_bytes());
+
+    format!("{:x}", mac.result().code())
+}
+
+fn write_dead_letter(path: &str, delivery: &Delivery) {
+    let line = serde_json::to_string(delivery).expect("failed to serialize delivery");
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+
+    if let Err(error) = written {
+        eprintln!("webhooks: failed to write dead letter log: {}, delivery: {}", error, line);
+    }
+}
+
+fn delivery_id(article_id: i32) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    format!("{}-{}-{}", article_id, now.as_secs(), now.subsec_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use super::*;
+
+    struct ReceivedRequest {
+        headers: HashMap<String, String>,
+        body: String,
+        received_at: Instant,
+    }
+
+    /// Local HTTP endpoint answering the n-th request with the n-th status,
+    /// and the last status after that.
+    fn endpoint(statuses: &[u16]) -> (String, mpsc::Receiver<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test endpoint");
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let statuses = statuses.to_vec();
+        let (requests, received) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.expect("failed to accept test connection");
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = HashMap::new();
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    if let Some(colon) = line.find(':') {
+                        headers.insert(line[..colon].to_lowercase(), line[colon + 1..].trim().to_string());
+                    }
+                }
+
+                let length = headers.get("content-length").map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = ReceivedRequest {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                    received_at: Instant::now(),
+                };
+
+                // recorded before responding, the delivery is done afterwards
+                if requests.send(request).is_err() {
+                    return;
+                }
+
+                let status = statuses.get(index).or_else(|| statuses.last()).copied().unwrap_or(200);
+                write!(stream, "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    fn delivery(url: &str) -> Delivery {
+        Delivery {
+            id: delivery_id(42),
+            url: url.to_string(),
+            secret: "secret".to_string(),
+            event: "article.published".to_string(),
+            body: r#"{"event":"article.published","article":{"id":42}}"#.to_string(),
+            attempts: 0,
+            last_error: None,
+        }
+    }
+
+    fn dead_letter_log(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("webhooks-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Delivers the deliveries until all of them succeeded or were dead
+    /// lettered.
+    fn deliver_all(deliveries: Vec<Delivery>, retry_policy: RetryPolicy, dead_letter_log: &str) {
+        let (sender, queue) = channel();
+        let dead_letter_log = dead_letter_log.to_string();
+        let delivering = thread::spawn(move || deliver(queue, retry_policy, dead_letter_log));
+
+        for delivery in deliveries {
+            sender.send(delivery).unwrap();
+        }
+        drop(sender);
+
+        delivering.join().expect("delivery thread panicked");
+    }
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn sends_the_signature_of_the_body() {
+        let (url, received) = endpoint(&[200]);
+        let log = dead_letter_log("signature");
+        let delivery = delivery(&url);
+        let policy = RetryPolicy { max_attempts: 1, base_delay: Duration::from_millis(10) };
+
+        deliver_all(vec![delivery.clone()], policy, &log);
+
+        let request = received.try_recv().expect("the endpoint received the delivery");
+        assert_eq!(request.body, delivery.body);
+        assert_eq!(request.headers["x-publisher-event"], "article.published");
+        assert_eq!(request.headers["x-publisher-delivery"], delivery.id);
+        assert_eq!(
+            request.headers["x-publisher-signature"],
+            format!("sha256={}", sign("secret", &delivery.body))
+        );
+        assert!(fs::metadata(&log).is_err());
+    }
+
+    #[test]
+    fn retries_failing_endpoints_with_backoff() {
+        let (url, received) = endpoint(&[500, 503, 200]);
+        let log = dead_letter_log("backoff");
+        let policy = RetryPolicy { max_attempts: 5, base_delay: Duration::from_millis(100) };
+
+        deliver_all(vec![delivery(&url)], policy, &log);
+
+        let requests = received.try_iter().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].received_at - requests[0].received_at >= Duration::from_millis(100));
+        assert!(requests[2].received_at - requests[1].received_at >= Duration::from_millis(200));
+        assert!(fs::metadata(&log).is_err());
+    }
+
+    #[test]
+    fn dead_letters_deliveries_after_max_attempts() {
+        let (url, received) = endpoint(&[500]);
+        let log = dead_letter_log("dead-letter");
+        let delivery = delivery(&url);
+        let policy = RetryPolicy { max_attempts: 3, base_delay: Duration::from_millis(10) };
+
+        deliver_all(vec![delivery.clone()], policy, &log);
+
+        assert_eq!(received.try_iter().count(), 3);
+
+        let lines = fs::read_to_string(&log).expect("the dead letter log was written");
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+
+        let dead_letter: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(dead_letter["id"], delivery.id.as_str());
+        assert_eq!(dead_letter["attempts"], 3);
+        assert_eq!(dead_letter["body"], delivery.body.as_str());
+        assert!(dead_letter.get("secret").is_none());
+        assert!(dead_letter["last_error"].as_str().unwrap().contains("500"));
+
+        fs::remove_file(&log).unwrap();
+    }
+}